@@ -2,12 +2,15 @@
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
 
-use anyhow::{anyhow, Context};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Context, Error as AnyError};
 use itertools::Itertools;
 use reqwest::header::{self, HeaderMap, HeaderValue};
-use reqwest::{Client, ClientBuilder};
+use reqwest::{Certificate, Client, ClientBuilder, Method};
+use tracing::debug;
 
-use crate::defs::{Auth, BackendConfig, Error, JsonValue, Result};
+use crate::defs::{Auth, BackendConfig, Error, HttpsOptions, JsonValue, Result};
 
 /// Internal state for sending HTTPS requests to the Proxmox VE API.
 #[derive(Debug)]
@@ -18,8 +21,11 @@ pub struct BackendData {
     /// The HTTP client used to send the requests.
     client: Client,
 
-    /// The base API URL to use when sending requests.
-    url: String,
+    /// The base API URLs to use when sending requests, in order of preference.
+    urls: Vec<String>,
+
+    /// The index of the URL that the last request was successfully sent to.
+    current: AtomicUsize,
 }
 
 impl BackendData {
@@ -30,7 +36,7 @@ impl BackendData {
     /// # Errors
     ///
     /// [`Error::Reqwest`] if the HTTP client could not be initialized.
-    pub fn new(cfg: BackendConfig) -> Result<Self> {
+    pub fn new(cfg: BackendConfig, opts: HttpsOptions) -> Result<Self> {
         let client = {
             let headers = {
                 let mut headers = HeaderMap::new();
@@ -46,9 +52,23 @@ impl BackendData {
                 };
                 headers
             };
-            ClientBuilder::new()
-                .danger_accept_invalid_certs(true)
-                .default_headers(headers)
+            let mut builder = ClientBuilder::new()
+                .danger_accept_invalid_certs(!opts.verify_tls)
+                .default_headers(headers);
+            if let Some(ref pem) = opts.ca_cert_pem {
+                builder = builder.add_root_certificate(
+                    Certificate::from_pem(pem)
+                        .context("Could not parse the CA certificate")
+                        .map_err(Error::Reqwest)?,
+                );
+            }
+            if let Some(connect_timeout) = opts.connect_timeout {
+                builder = builder.connect_timeout(connect_timeout);
+            }
+            if let Some(timeout) = opts.timeout {
+                builder = builder.timeout(timeout);
+            }
+            builder
                 .build()
                 .context("Could not build the HTTPS client")
                 .map_err(Error::Reqwest)?
@@ -56,7 +76,8 @@ impl BackendData {
         Ok(Self {
             _auth: cfg.auth,
             client,
-            url: cfg.url,
+            urls: [cfg.url].into_iter().chain(opts.fallback_urls).collect(),
+            current: AtomicUsize::new(0),
         })
     }
 
@@ -67,40 +88,118 @@ impl BackendData {
     /// [`Error::Reqwest`] if the request could not be built or sent at all.
     /// [`Error::Api`] if the API responds with an error or something unexpected.
     pub async fn get(&self, path: &str) -> Result<JsonValue> {
-        let url = format!("{url}/api2/json/{path}", url = self.url);
-        let req = self
-            .client
-            .get(&url)
+        self.request(Method::GET, path, &[]).await
+    }
+
+    /// Send an HTTPS POST request with form-encoded parameters, return a JSON structure.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Reqwest`] if the request could not be built or sent at all.
+    /// [`Error::Api`] if the API responds with an error or something unexpected.
+    pub async fn post(&self, path: &str, params: &[(&str, &str)]) -> Result<JsonValue> {
+        self.request(Method::POST, path, params).await
+    }
+
+    /// Send an HTTPS PUT request with form-encoded parameters, return a JSON structure.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Reqwest`] if the request could not be built or sent at all.
+    /// [`Error::Api`] if the API responds with an error or something unexpected.
+    pub async fn put(&self, path: &str, params: &[(&str, &str)]) -> Result<JsonValue> {
+        self.request(Method::PUT, path, params).await
+    }
+
+    /// Send an HTTPS DELETE request with query parameters, return a JSON structure.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Reqwest`] if the request could not be built or sent at all.
+    /// [`Error::Api`] if the API responds with an error or something unexpected.
+    pub async fn delete(&self, path: &str, params: &[(&str, &str)]) -> Result<JsonValue> {
+        self.request(Method::DELETE, path, params).await
+    }
+
+    /// Send an HTTPS request, return a JSON structure.
+    ///
+    /// The parameters are sent as a form for POST and PUT requests and in the query string otherwise.
+    ///
+    /// If the server cannot be reached, try the next one of the configured URLs.
+    /// A GET request is also retried if it times out; the others may have been
+    /// processed by the server even if no response was received.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Reqwest`] if the request could not be built or sent at all.
+    /// [`Error::Api`] if the API responds with an error or something unexpected.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<JsonValue> {
+        let first = self.current.load(Ordering::Relaxed);
+        let mut idx = first;
+        let (url, resp) = loop {
+            let base = self
+                .urls
+                .get(idx)
+                .ok_or_else(|| Error::Internal(format!("No API URL at index {idx}")))?;
+            let url = format!("{base}/api2/json/{path}");
+            let builder = self.client.request(method.clone(), &url);
+            let req = if params.is_empty() {
+                builder
+            } else if method == Method::POST || method == Method::PUT {
+                builder.form(params)
+            } else {
+                builder.query(params)
+            }
             .build()
-            .with_context(|| format!("Could not build a GET request for {url}"))
+            .with_context(|| format!("Could not build a {method} request for {url}"))
             .map_err(Error::Reqwest)?;
-        let resp = self
-            .client
-            .execute(req)
-            .await
-            .with_context(|| format!("The GET request for {url} failed"))
-            .map_err(Error::Reqwest)?
+            match self.client.execute(req).await {
+                Ok(resp) => {
+                    self.current.store(idx, Ordering::Relaxed);
+                    break (url, resp);
+                }
+                Err(err) => {
+                    let next = (idx + 1) % self.urls.len();
+                    if next == first
+                        || !(err.is_connect() || (method == Method::GET && err.is_timeout()))
+                    {
+                        return Err(Error::Reqwest(
+                            AnyError::new(err)
+                                .context(format!("The {method} request for {url} failed")),
+                        ));
+                    }
+                    debug!("The {method} request for {url} failed, trying the next URL: {err}");
+                    idx = next;
+                }
+            }
+        };
+        let resp = resp
             .error_for_status()
-            .with_context(|| format!("The GET request for {url} returned an error"))
+            .with_context(|| format!("The {method} request for {url} returned an error"))
             .map_err(Error::Api)?;
 
         let ctype = String::from_utf8(
             resp.headers()
                 .get(header::CONTENT_TYPE)
                 .with_context(|| {
-                    format!("The GET request for {url} did not return a Content-Type header")
+                    format!("The {method} request for {url} did not return a Content-Type header")
                 })
                 .map_err(Error::Api)?
                 .as_ref()
                 .to_vec(),
         )
         .with_context(|| {
-            format!("The GET request for {url} did not return a parseable Content-Type header")
+            format!("The {method} request for {url} did not return a parseable Content-Type header")
         })
         .map_err(Error::Api)?;
         if ctype != "application/json" && ctype != "application/json;charset=UTF-8" {
             return Err(Error::Api(anyhow!(
-                "The GET request for {url} returned an unexpected Content-Type header: {ctype}"
+                "The {method} request for {url} returned an unexpected Content-Type header: {ctype}"
             )));
         }
 
@@ -108,12 +207,14 @@ impl BackendData {
             .bytes()
             .await
             .with_context(|| {
-                format!("Could not receive the full response to the GET request for {url}")
+                format!("Could not receive the full response to the {method} request for {url}")
             })
             .map_err(Error::Api)?;
         let raw = serde_json::from_slice(&raw_bytes)
             .with_context(|| {
-                format!("Could not decode the response to the GET request for {url} as valid JSON")
+                format!(
+                    "Could not decode the response to the {method} request for {url} as valid JSON"
+                )
             })
             .map_err(Error::Api)?;
         #[allow(clippy::wildcard_enum_match_arm)]
@@ -123,14 +224,14 @@ impl BackendData {
                 // if keys.len() != 1 || keys[0] != "data" {
                 if *keys != ["data"] {
                     return Err(Error::Api(anyhow!(
-                        "The GET request for {url} returned a weird object: {top:?}"
+                        "The {method} request for {url} returned a weird object: {top:?}"
                     )));
                 }
                 top.remove("data")
                     .ok_or_else(|| Error::Internal(format!("'data' should be in {top:?}")))
             }
             other => Err(Error::Api(anyhow!(
-                "The GET request for {url} returned something weird: {other:?}"
+                "The {method} request for {url} returned something weird: {other:?}"
             ))),
         }
     }
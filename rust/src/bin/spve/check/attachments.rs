@@ -0,0 +1,133 @@
+//! Check that the `StorPool` volumes are attached to the nodes where the VMs run.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use tracing::{debug, warn};
+
+use proxmoxy::types::VmStatus;
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::storpool::{Attachment, GlobalIdDecoder, Rights};
+use crate::MainExit;
+
+/// Describe a `StorPool` client using the Proxmox VE node name if it is known.
+fn client_desc(node_names: &HashMap<u32, String>, client: u32) -> String {
+    node_names.get(&client).map_or_else(
+        || format!("the unknown StorPool client {client}"),
+        |name| format!("node {name} (StorPool client {client})"),
+    )
+}
+
+/// Check the `StorPool` attachments of the volumes used by the VMs' disks.
+pub async fn cmd_check_attachments(
+    cfg: &Config,
+    api: &Proxmoxy,
+    guests: GuestSelector,
+) -> Result<MainExit> {
+    let sp_api = cfg.get_storpool_api()?;
+    let node_names = cfg.storpool_node_names();
+    let node_ids: HashMap<&str, u32> = node_names
+        .iter()
+        .map(|(sp_id, name)| (name.as_str(), *sp_id))
+        .collect();
+
+    let storage = super::get_storage(api).await?;
+
+    let mut attachments: HashMap<String, Vec<Attachment>> = HashMap::new();
+    for att in sp_api.attachments().await? {
+        attachments.entry(att.volume.clone()).or_default().push(att);
+    }
+    debug!(
+        "Got information about {count} attached StorPool volume(s)",
+        count = attachments.len()
+    );
+
+    let decoder = GlobalIdDecoder::new()?;
+    let mut problems = false;
+    let mut unknown_nodes = HashSet::new();
+    for sel in guests::select_with_pools(api, &guests).await?.vms {
+        let name = sel.node.as_str();
+        let vm = &sel.vm;
+        let expected = if let Some(sp_id) = node_ids.get(name) {
+            *sp_id
+        } else {
+            if unknown_nodes.insert(name.to_owned()) {
+                warn!("No StorPool client ID configured for the {name} node");
+                problems = true;
+            }
+            continue;
+        };
+
+        let vmid = vm.vmid();
+        if vm.lock() == Some("migrate") {
+            debug!("Skipping VM {vmid}, it is being migrated");
+            continue;
+        }
+
+        let vm_desc = sel.describe();
+        let vmcfg = api
+            .get(api.path().nodes().id(name).qemu().id(vmid).config())
+            .await
+            .map_err(Error::Api)?;
+        for disk in vmcfg.disks() {
+            if storage
+                .get(disk.storage())
+                .map_or(true, |store| store.as_storpool().is_none())
+            {
+                continue;
+            }
+            let disk_id = super::disk_id(&vm_desc, disk);
+            let global_id = if let Some(global_id) = decoder.decode(disk.volid()) {
+                global_id
+            } else {
+                debug!(
+                    "Skipping {disk_id}: no StorPool global ID in {volid}",
+                    volid = disk.volid()
+                );
+                continue;
+            };
+            let sp_name = format!("~{global_id}");
+
+            let disk_atts = attachments.get(&sp_name).map_or(&[][..], Vec::as_slice);
+            for att in disk_atts {
+                if att.client != expected {
+                    warn!(
+                        "{disk_id} ({sp_name}) is attached to {client} instead of node {name}",
+                        client = client_desc(&node_names, att.client)
+                    );
+                    problems = true;
+                    continue;
+                }
+
+                let rights = if att.snapshot {
+                    Rights::ReadOnly
+                } else {
+                    Rights::ReadWrite
+                };
+                if att.rights != rights {
+                    warn!(
+                        "{disk_id} ({sp_name}) is attached to node {name} as '{actual}' instead of '{rights}'",
+                        actual = att.rights.as_ref(),
+                        rights = rights.as_ref()
+                    );
+                    problems = true;
+                }
+            }
+
+            if vm.status() == VmStatus::Running
+                && !disk_atts.iter().any(|att| att.client == expected)
+            {
+                warn!("{disk_id} ({sp_name}) is not attached to node {name} where the VM runs");
+                problems = true;
+            }
+        }
+    }
+
+    Ok(MainExit::from_problems(problems))
+}
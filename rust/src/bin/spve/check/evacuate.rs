@@ -0,0 +1,180 @@
+//! Check that all the guests on a node can be migrated away from it.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use tracing::debug;
+
+use proxmoxy::types::{NodeStatus, StorageConfig, VmStatus, VmSummary};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::{GuestSelector, MigrateTarget};
+use crate::defs::{Error, Result};
+use crate::guests::{self, SelectedVm};
+use crate::migrate::{self, Guest};
+use crate::output;
+use crate::MainExit;
+
+/// Find the reasons a VM cannot be migrated, and the storage that its disks are on.
+async fn examine_vm(
+    api: &Proxmoxy,
+    source: &str,
+    storage: &HashMap<String, StorageConfig>,
+    vm: &VmSummary,
+) -> Result<(Vec<String>, HashSet<String>)> {
+    let vmid = vm.vmid();
+    let path_vms = api.path().nodes().id(source).qemu();
+    let mut vm_reasons = Vec::new();
+    if let Some(lock) = vm.lock() {
+        vm_reasons.push(format!("locked ({lock})"));
+    }
+
+    let vmcfg = api
+        .get(path_vms.clone().id(vmid).config())
+        .await
+        .map_err(Error::Api)?;
+    let mut used = HashSet::new();
+    for disk in vmcfg.disks() {
+        if disk.options().get("media").map(String::as_str) == Some("cdrom") {
+            continue;
+        }
+        let disk_name = format!(
+            "{disk_type}{idx}",
+            disk_type = disk.disk_type().as_ref(),
+            idx = disk.idx()
+        );
+        match storage.get(disk.storage()) {
+            Some(store) if store.as_storpool().is_some() => {
+                used.insert(disk.storage().to_owned());
+            }
+            _ => vm_reasons.push(format!(
+                "{disk_name} is on the {storage} storage, not on a shared storpool one",
+                storage = disk.storage()
+            )),
+        }
+    }
+
+    let info = api
+        .get(path_vms.id(vmid).migrate())
+        .await
+        .map_err(Error::Api)?;
+    for res in info.local_resources() {
+        vm_reasons.push(format!("uses the local {res} resource"));
+    }
+    for disk in info.local_disks() {
+        let volid = disk
+            .get("volid")
+            .and_then(|volid| volid.as_str())
+            .unwrap_or("(unknown)");
+        vm_reasons.push(format!("uses the local {volid} volume"));
+    }
+    Ok((vm_reasons, used))
+}
+
+/// Check that all the guests on a node can be migrated to other nodes.
+pub async fn cmd_check_evacuate(
+    api: &Proxmoxy,
+    source: String,
+    guests: GuestSelector,
+) -> Result<MainExit> {
+    let storage = super::get_storage(api).await?;
+
+    let nodes = api.get(api.path().nodes()).await.map_err(Error::Api)?;
+    if !nodes
+        .iter()
+        .any(|node| node.node() == source && node.status() == NodeStatus::Online)
+    {
+        return Err(Error::Invoke(anyhow!("The {source} node is not online")));
+    }
+    let headroom = migrate::headroom(&nodes, &source);
+    if headroom.is_empty() {
+        return Err(Error::Invoke(anyhow!(
+            "No online nodes other than {source} to migrate to"
+        )));
+    }
+
+    let vms = guests::select(api, &guests).await?.vms;
+    debug!(
+        "Got information about {count} VM(s) on node {source}",
+        count = vms.len()
+    );
+
+    let mut reasons: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut vm_storage: HashMap<u32, HashSet<String>> = HashMap::new();
+    let mut guests = Vec::new();
+    for SelectedVm { vm, .. } in vms {
+        let vmid = vm.vmid();
+        let (vm_reasons, used) = examine_vm(api, &source, &storage, &vm).await?;
+        reasons.insert(vmid, vm_reasons);
+        vm_storage.insert(vmid, used);
+
+        guests.push(Guest {
+            vmid,
+            name: vm.name().unwrap_or_default().to_owned(),
+            mem: if vm.status() == VmStatus::Running {
+                vm.maxmem().unwrap_or(0)
+            } else {
+                0
+            },
+        });
+    }
+    guests.sort_by(|first, second| {
+        second
+            .mem
+            .cmp(&first.mem)
+            .then(first.vmid.cmp(&second.vmid))
+    });
+
+    let names: HashMap<u32, String> = guests
+        .iter()
+        .map(|guest| (guest.vmid, guest.name.clone()))
+        .collect();
+    let (migrations, unplaced) =
+        migrate::plan(guests, &MigrateTarget::Auto, headroom, |vmid, node| {
+            vm_storage.get(&vmid).map_or(true, |used| {
+                used.iter().all(|name| {
+                    storage
+                        .get(name)
+                        .map_or(false, |store| store.common().enabled_on(node))
+                })
+            })
+        });
+    for vmid in unplaced {
+        reasons
+            .entry(vmid)
+            .or_default()
+            .push("no online node with enough free memory and the same storage".to_owned());
+    }
+    let targets: HashMap<u32, String> = migrations
+        .into_iter()
+        .map(|mig| (mig.vmid, mig.target))
+        .collect();
+
+    let mut problems = false;
+    let mut vmids: Vec<u32> = names.keys().copied().collect();
+    vmids.sort_unstable();
+    for vmid in vmids {
+        let name = names.get(&vmid).map_or("", String::as_str);
+        let vm_reasons = reasons.get(&vmid).map_or(&[][..], Vec::as_slice);
+        if vm_reasons.is_empty() {
+            output::emit(&format!(
+                "VM {vmid} ({name}): PASS: {source} -> {target}",
+                target = targets.get(&vmid).map_or("?", String::as_str)
+            ));
+        } else {
+            output::emit(&format!(
+                "VM {vmid} ({name}): FAIL: {reasons}",
+                reasons = vm_reasons.join("; ")
+            ));
+            problems = true;
+        }
+    }
+    output::emit(&format!(
+        "Node {source}: {result}",
+        result = if problems { "FAIL" } else { "PASS" }
+    ));
+
+    Ok(MainExit::from_problems(problems))
+}
@@ -0,0 +1,193 @@
+//! Check that the HA-managed VMs can fail over to nodes with the `StorPool` client.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use tracing::{debug, warn};
+
+use proxmoxy::types::{HaGroup, StorageConfig, VmConfig};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::MainExit;
+
+/// Check that all the nodes in an HA group run the `StorPool` client.
+fn check_group(group: &HaGroup, client_nodes: &HashSet<String>) -> bool {
+    let name = group.group();
+    let mut problems = false;
+    for node in group.nodes().keys() {
+        if !client_nodes.contains(node.as_str()) {
+            warn!(
+                "HA group {name}: contains the {node} node that does not run the StorPool client"
+            );
+            problems = true;
+        }
+    }
+    problems
+}
+
+/// Get the names of the nodes that run the `StorPool` client.
+async fn client_nodes(cfg: &Config) -> Result<HashSet<String>> {
+    let running: HashSet<u32> = cfg
+        .get_storpool_api()?
+        .services()
+        .await?
+        .clients
+        .into_values()
+        .filter(|client| client.status == "running")
+        .map(|client| client.id)
+        .collect();
+    let client_nodes: HashSet<String> = cfg
+        .storpool_node_names()
+        .into_iter()
+        .filter_map(|(sp_id, name)| running.contains(&sp_id).then(|| name))
+        .collect();
+    debug!(
+        "{count} node(s) run the StorPool client",
+        count = client_nodes.len()
+    );
+    Ok(client_nodes)
+}
+
+/// Check that an HA-managed VM's disks are on shared storage enabled on the failover nodes.
+fn check_disks(
+    sid: &str,
+    vmid: u32,
+    vmcfg: &VmConfig,
+    storage: &HashMap<String, StorageConfig>,
+    failover: &[&str],
+) -> bool {
+    let mut problems = false;
+    for disk in vmcfg.disks() {
+        let disk_id = super::disk_id(&format!("VM {vmid}"), disk);
+        let name = disk.storage();
+        let common = match storage.get(name) {
+            Some(store) if store.as_storpool().is_some() => store.common(),
+            Some(_) => {
+                warn!("HA resource {sid}: {disk_id} is on the non-StorPool {name} storage");
+                problems = true;
+                continue;
+            }
+            None => {
+                warn!("HA resource {sid}: {disk_id} is on the unknown {name} storage");
+                problems = true;
+                continue;
+            }
+        };
+        if !common.shared() {
+            warn!("HA resource {sid}: {disk_id} is on the {name} storage that is not shared");
+            problems = true;
+        }
+        for target in failover.iter().filter(|target| !common.enabled_on(target)) {
+            warn!(
+                "HA resource {sid}: {disk_id} is on the {name} storage that is not enabled on the {target} node"
+            );
+            problems = true;
+        }
+    }
+    problems
+}
+
+/// Check the HA-managed VMs and the HA groups they belong to.
+pub async fn cmd_check_ha(cfg: &Config, api: &Proxmoxy) -> Result<MainExit> {
+    let ha_path = api.path().cluster().ha();
+    let resources = api
+        .get(ha_path.clone().resources())
+        .await
+        .map_err(Error::Api)?;
+    let groups: HashMap<String, HaGroup> = api
+        .get(ha_path.groups())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .map(|group| (group.group().to_owned(), group))
+        .collect();
+    debug!(
+        "Got information about {res_count} HA resource(s) and {group_count} HA group(s)",
+        res_count = resources.len(),
+        group_count = groups.len()
+    );
+
+    let client_nodes = client_nodes(cfg).await?;
+    let all_nodes: Vec<String> = api
+        .get(api.path().nodes())
+        .await
+        .map_err(Error::Api)?
+        .iter()
+        .map(|node| node.node().to_owned())
+        .sorted()
+        .collect();
+    let storage: HashMap<String, StorageConfig> = super::get_storage(api).await?;
+    let selection = guests::select(api, &GuestSelector::default()).await?;
+    let vm_nodes: HashMap<u32, &str> = selection
+        .vms
+        .iter()
+        .map(|sel| (sel.vm.vmid(), sel.node.as_str()))
+        .collect();
+
+    let mut problems = false;
+    let mut checked_groups = HashSet::new();
+    for res in resources.iter().sorted_by_key(|res| res.sid()) {
+        let sid = res.sid();
+        let vmid = if let Some(vmid) = res.vmid() {
+            vmid
+        } else {
+            debug!("Skipping the {sid} HA resource, not a virtual machine");
+            continue;
+        };
+
+        let failover: Vec<&str> = if let Some(name) = res.group() {
+            if let Some(group) = groups.get(name) {
+                if checked_groups.insert(name) {
+                    problems = check_group(group, &client_nodes) || problems;
+                }
+                group.nodes().keys().map(String::as_str).collect()
+            } else {
+                warn!("HA resource {sid}: no '{name}' HA group");
+                problems = true;
+                continue;
+            }
+        } else {
+            for node in &all_nodes {
+                if !client_nodes.contains(node.as_str()) {
+                    warn!(
+                        "HA resource {sid}: not in a group, may fail over to the {node} node that does not run the StorPool client"
+                    );
+                    problems = true;
+                }
+            }
+            all_nodes.iter().map(String::as_str).collect()
+        };
+
+        let node = match vm_nodes.get(&vmid) {
+            Some(node) => *node,
+            None if selection.offline.is_empty() => {
+                warn!("HA resource {sid}: no VM with ID {vmid}");
+                problems = true;
+                continue;
+            }
+            None => {
+                warn!(
+                    "HA resource {sid}: VM {vmid} not found on the online nodes; offline: {offline}",
+                    offline = selection.offline.join(", ")
+                );
+                problems = true;
+                continue;
+            }
+        };
+
+        debug!("Checking VM {vmid} on the {node} node");
+        let vmcfg = api
+            .get(api.path().nodes().id(node).qemu().id(vmid).config())
+            .await
+            .map_err(Error::Api)?;
+        problems = check_disks(sid, vmid, &vmcfg, &storage, &failover) || problems;
+    }
+
+    Ok(MainExit::from_problems(problems))
+}
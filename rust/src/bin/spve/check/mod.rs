@@ -0,0 +1,54 @@
+//! Check various Proxmox VE and `StorPool` configuration settings.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::HashMap;
+
+use tracing::debug;
+
+use proxmoxy::types::{StorageConfig, VmDisk};
+use proxmoxy::Proxmoxy;
+
+use crate::defs::{Error, Result};
+
+pub mod attachments;
+pub mod evacuate;
+pub mod ha;
+pub mod snapshots;
+pub mod storage;
+pub mod vms;
+
+/// Fetch the storage definitions for the cluster, keyed by name.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE API request failed.
+pub async fn get_storage(api: &Proxmoxy) -> Result<HashMap<String, StorageConfig>> {
+    let storage: HashMap<String, StorageConfig> = api
+        .get(api.path().storage())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .map(|store| (store.storage().to_owned(), store))
+        .collect();
+    debug!(
+        "Got information about {count} storage(s)",
+        count = storage.len()
+    );
+    for (name, store) in &storage {
+        debug!(
+            "- {name}: {storage_type}",
+            storage_type = store.storage_type()
+        );
+    }
+    Ok(storage)
+}
+
+/// Build a human-readable description of a VM's disk for diagnostic messages.
+fn disk_id(vm_desc: &str, disk: &VmDisk) -> String {
+    format!(
+        "the {disk_type}{idx} disk for {vm_desc}",
+        disk_type = disk.disk_type().as_ref(),
+        idx = disk.idx(),
+    )
+}
@@ -0,0 +1,191 @@
+//! Cross-check the Proxmox VE snapshots against the `StorPool` snapshots.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use tracing::{debug, warn};
+
+use proxmoxy::types::{StorageConfig, VmConfig};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::storpool::{StorPool, Volume};
+use crate::MainExit;
+
+/// The value of the `pve-disk` tag for the volumes holding a VM's memory state.
+pub const VMSTATE_DISK: &str = "state";
+
+/// The `StorPool` objects that belong to a single Proxmox VE snapshot.
+#[derive(Debug, Default)]
+struct SpSnapshotSet {
+    /// The names of the `StorPool` snapshots of the VM's disks.
+    disks: Vec<String>,
+
+    /// The names of the `StorPool` volumes or snapshots holding the VM's memory state.
+    vmstate: Vec<String>,
+}
+
+/// Get the Proxmox VE VM ID and snapshot name that a `StorPool` object was created for.
+///
+/// Only the `pve-snap` tag holds the snapshot name; `pve-snap-v` holds the global ID
+/// of the volume that the snapshot was taken of.
+#[must_use]
+pub fn snapshot_owner(vol: &Volume) -> Option<(u32, String)> {
+    let vmid = vol.tags.get("pve-vm")?.parse().ok()?;
+    let name = vol.tags.get("pve-snap")?;
+    Some((vmid, name.clone()))
+}
+
+/// Count the disks in a VM snapshot's configuration that `StorPool` should hold snapshots of.
+#[must_use]
+pub fn sp_disk_count(snap_cfg: &VmConfig, sp_names: &HashSet<&str>) -> usize {
+    snap_cfg
+        .disks()
+        .iter()
+        .filter(|disk| sp_names.contains(disk.storage()))
+        .filter(|disk| disk.options().get("media").map(String::as_str) != Some("cdrom"))
+        .count()
+}
+
+/// Group the `StorPool` snapshots and memory state volumes by the VM snapshot they belong to.
+///
+/// Also report whether any memory state volumes could not be attributed to a VM snapshot.
+async fn sp_snapshot_sets(
+    sp_api: &StorPool,
+    sp_names: &HashSet<&str>,
+) -> Result<(HashMap<(u32, String), SpSnapshotSet>, bool)> {
+    let ours = |vol: &Volume| {
+        vol.tags
+            .get("pve")
+            .map_or(false, |store| sp_names.contains(store.as_str()))
+    };
+
+    let mut problems = false;
+    let mut sp_sets: HashMap<(u32, String), SpSnapshotSet> = HashMap::new();
+    for snap in sp_api.snapshots().await?.into_iter().filter(ours) {
+        if let Some(owner) = snapshot_owner(&snap) {
+            let set = sp_sets.entry(owner).or_default();
+            if snap.tags.get("pve-disk").map(String::as_str) == Some(VMSTATE_DISK) {
+                set.vmstate.push(snap.name);
+            } else {
+                set.disks.push(snap.name);
+            }
+        } else {
+            debug!(
+                "StorPool snapshot {name} is not tagged as a VM snapshot",
+                name = snap.name
+            );
+        }
+    }
+    for vol in sp_api.volumes().await?.into_iter().filter(|vol| {
+        ours(vol) && vol.tags.get("pve-disk").map(String::as_str) == Some(VMSTATE_DISK)
+    }) {
+        if let Some(owner) = snapshot_owner(&vol) {
+            sp_sets.entry(owner).or_default().vmstate.push(vol.name);
+        } else {
+            warn!(
+                "StorPool volume {name} holds a VM memory state, but is not tagged with a VM snapshot",
+                name = vol.name
+            );
+            problems = true;
+        }
+    }
+    debug!(
+        "Got {count} VM snapshot(s) from StorPool",
+        count = sp_sets.len()
+    );
+    Ok((sp_sets, problems))
+}
+
+/// Report the Proxmox VE snapshots and the `StorPool` snapshots that do not match.
+pub async fn cmd_check_snapshots(
+    cfg: &Config,
+    api: &Proxmoxy,
+    guests: GuestSelector,
+) -> Result<MainExit> {
+    let sp_api = cfg.get_storpool_api()?;
+
+    let storage = super::get_storage(api).await?;
+    let sp_names: HashSet<&str> = storage
+        .values()
+        .filter(|store| store.as_storpool().is_some())
+        .map(StorageConfig::storage)
+        .collect();
+    let (sp_sets, mut problems) = sp_snapshot_sets(&sp_api, &sp_names).await?;
+
+    let selection = guests::select(api, &guests).await?;
+    for name in &selection.offline {
+        warn!(
+            "The {name} node is not online, the StorPool snapshots of its VMs will not be checked"
+        );
+    }
+    let partial = !selection.offline.is_empty() || !guests.selects_all();
+    let mut seen_vms = HashSet::new();
+    let mut seen_snapshots = HashSet::new();
+    for sel in selection.vms {
+        let vmid = sel.vm.vmid();
+        seen_vms.insert(vmid);
+        let path_snapshots = api.path().nodes().id(&sel.node).qemu().id(vmid).snapshot();
+        for snap in api
+            .get(path_snapshots.clone())
+            .await
+            .map_err(Error::Api)?
+            .into_iter()
+            .filter(|snap| !snap.is_current())
+        {
+            let snap_name = snap.name();
+            let key = (vmid, snap_name.to_owned());
+            let snap_cfg = api
+                .get(path_snapshots.clone().id(snap_name).config())
+                .await
+                .map_err(Error::Api)?;
+            let expected = sp_disk_count(&snap_cfg, &sp_names);
+            let sp_set = sp_sets.get(&key);
+            let found = sp_set.map_or(0, |set| set.disks.len());
+            if found < expected {
+                warn!(
+                    "VM {vmid} snapshot {snap_name}: {expected} StorPool disk(s), but only {found} StorPool snapshot(s)"
+                );
+                problems = true;
+            }
+            if snap.vmstate() && expected > 0 && sp_set.map_or(true, |set| set.vmstate.is_empty()) {
+                warn!("VM {vmid} snapshot {snap_name}: no StorPool memory state volume");
+                problems = true;
+            }
+            seen_snapshots.insert(key);
+        }
+    }
+
+    let mut orphans: Vec<(u32, &str, &SpSnapshotSet)> = sp_sets
+        .iter()
+        .filter(|&(key, _)| !seen_snapshots.contains(key))
+        .map(|(key, set)| (key.0, key.1.as_str(), set))
+        .collect();
+    orphans.sort_by(|first, second| (first.0, first.1).cmp(&(second.0, second.1)));
+    for (vmid, snap_name, set) in orphans {
+        if partial && !seen_vms.contains(&vmid) {
+            debug!("Skipping the StorPool snapshots of VM {vmid}, it was not examined");
+            continue;
+        }
+        if !set.disks.is_empty() {
+            warn!(
+                "VM {vmid} snapshot {snap_name}: no such Proxmox VE snapshot for StorPool snapshot(s) {names}",
+                names = set.disks.join(", ")
+            );
+            problems = true;
+        }
+        if !set.vmstate.is_empty() {
+            warn!(
+                "VM {vmid} snapshot {snap_name}: no such Proxmox VE snapshot for StorPool memory state {names}",
+                names = set.vmstate.join(", ")
+            );
+            problems = true;
+        }
+    }
+
+    Ok(MainExit::from_problems(problems))
+}
@@ -0,0 +1,153 @@
+//! Check the `storpool` storage definitions against the `StorPool` cluster.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use regex::Regex;
+use tracing::{debug, warn};
+
+use proxmoxy::types::{StorPoolStorage, StorageConfig};
+use proxmoxy::Proxmoxy;
+
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::storpool::RESERVED_TAGS;
+use crate::MainExit;
+
+/// The content types that the `StorPool` plugin supports.
+const SUPPORTED_CONTENT: [&str; 5] = ["backup", "images", "iso", "none", "rootdir"];
+
+/// Recognize a valid `StorPool` tag name or value.
+pub const RE_TAG_PATTERN: &str = r"^[A-Za-z0-9_.:-]+$";
+
+/// Check the `extra-tags` setting of a storage, e.g. "owner=team-a tier=ssd".
+///
+/// Return true if any problems were found.
+#[must_use]
+pub fn check_extra_tags(name: &str, spec: &str, re_tag: &Regex) -> bool {
+    let mut problems = false;
+    for item in spec.split_whitespace() {
+        match item.split_once('=') {
+            None => {
+                warn!("Storage {name}: extra tag '{item}' is not in the 'name=value' form");
+                problems = true;
+            }
+            Some((tag, value)) => {
+                if !re_tag.is_match(tag) || !re_tag.is_match(value) {
+                    warn!("Storage {name}: invalid characters in the '{item}' extra tag");
+                    problems = true;
+                } else if RESERVED_TAGS.contains(&tag) {
+                    warn!("Storage {name}: the '{tag}' extra tag is reserved for the plugin");
+                    problems = true;
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Check a single `storpool` storage definition.
+fn check_storage(
+    store: &StorPoolStorage,
+    templates: &HashSet<String>,
+    all_nodes: &[String],
+    client_nodes: &HashSet<&str>,
+    re_tag: &Regex,
+) -> bool {
+    let common = store.common();
+    let name = common.storage();
+    let mut problems = false;
+
+    let template = store.template_name();
+    if !templates.contains(template) {
+        warn!("Storage {name}: no '{template}' StorPool template");
+        problems = true;
+    }
+
+    if let Some(spec) = store.extra_tags() {
+        problems = check_extra_tags(name, spec, re_tag) || problems;
+    }
+
+    for content in common.content().iter().sorted() {
+        if !SUPPORTED_CONTENT.contains(&content.as_str()) {
+            warn!("Storage {name}: unsupported content type '{content}'");
+            problems = true;
+        }
+    }
+
+    for node in all_nodes.iter().filter(|node| common.enabled_on(node)) {
+        if !client_nodes.contains(node.as_str()) {
+            warn!(
+                "Storage {name}: enabled on the {node} node that does not run the StorPool client"
+            );
+            problems = true;
+        }
+    }
+
+    problems
+}
+
+/// Check the `storpool` storage definitions.
+pub async fn cmd_check_storage(cfg: &Config, api: &Proxmoxy) -> Result<MainExit> {
+    let sp_api = cfg.get_storpool_api()?;
+    let node_names = cfg.storpool_node_names();
+
+    let re_tag = Regex::new(RE_TAG_PATTERN).map_err(|err| {
+        Error::Internal(format!("Could not build the tag regular expression: {err}"))
+    })?;
+
+    let templates: HashSet<String> = sp_api
+        .templates_status()
+        .await?
+        .into_iter()
+        .map(|tmpl| tmpl.name)
+        .collect();
+    debug!(
+        "Got information about {count} StorPool template(s)",
+        count = templates.len()
+    );
+
+    let running: HashSet<u32> = sp_api
+        .services()
+        .await?
+        .clients
+        .into_values()
+        .filter(|client| client.status == "running")
+        .map(|client| client.id)
+        .collect();
+    let client_nodes: HashSet<&str> = node_names
+        .iter()
+        .filter(|&(sp_id, _)| running.contains(sp_id))
+        .map(|(_, name)| name.as_str())
+        .collect();
+    debug!(
+        "{count} node(s) run the StorPool client",
+        count = client_nodes.len()
+    );
+
+    let all_nodes: Vec<String> = api
+        .get(api.path().nodes())
+        .await
+        .map_err(Error::Api)?
+        .iter()
+        .map(|node| node.node().to_owned())
+        .collect();
+
+    let storage: HashMap<String, StorageConfig> = super::get_storage(api).await?;
+    let mut problems = false;
+    for store in storage
+        .values()
+        .filter_map(StorageConfig::as_storpool)
+        .sorted_by_key(|store| store.common().storage())
+    {
+        debug!(
+            "Checking the {name} storage",
+            name = store.common().storage()
+        );
+        problems = check_storage(store, &templates, &all_nodes, &client_nodes, &re_tag) || problems;
+    }
+
+    Ok(MainExit::from_problems(problems))
+}
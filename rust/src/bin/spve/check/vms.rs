@@ -0,0 +1,143 @@
+//! Check the configuration of `StorPool`-backed VM disks.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::HashMap;
+
+use tracing::{debug, warn};
+
+use proxmoxy::types::{StorageConfig, VmConfig, VmDisk, VmDiskType};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::{ConfigState, GuestSelector};
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::MainExit;
+
+/// Check the configuration of a single disk for a VM.
+fn check_vm_disk(disk_id: &str, disk: &VmDisk) -> bool {
+    let mut problems: bool = false;
+
+    #[allow(clippy::wildcard_enum_match_arm)]
+    match disk.disk_type() {
+        VmDiskType::Virtio => (),
+        other => {
+            warn!(
+                "Disk type '{other}' instead of 'virtio' for {disk_id}",
+                other = other.as_ref()
+            );
+            problems = true;
+        }
+    };
+
+    match disk.options().get("cache") {
+        None => (),
+        Some(value) if value == "none" => (),
+        Some(other) => {
+            warn!("Expected 'cache=none' for {disk_id}, got '{other}'");
+            problems = true;
+        }
+    }
+
+    match disk.options().get("discard") {
+        None => {
+            warn!("No 'discard' defined for {disk_id}");
+            problems = true;
+        }
+        Some(value) if value == "on" => (),
+        Some(other) => {
+            warn!("Expected 'discard=on' for {disk_id}, got '{other}'");
+            problems = true;
+        }
+    }
+
+    match disk.options().get("iothread") {
+        None => {
+            warn!("No 'iothread' defined for {disk_id}");
+            problems = true;
+        }
+        Some(value) if value == "1" => (),
+        Some(other) => {
+            warn!("Expected 'iothread=1' for {disk_id}, got '{other}'");
+            problems = true;
+        }
+    }
+
+    problems
+}
+
+/// Check the `StorPool`-backed disks in a version of a VM's configuration.
+///
+/// If `previous` is specified, only check the disks that differ from it.
+fn check_vm_config(
+    vm_desc: &str,
+    vmcfg: &VmConfig,
+    previous: Option<&VmConfig>,
+    storage: &HashMap<String, StorageConfig>,
+) -> bool {
+    let mut problems = false;
+    for disk in vmcfg.disks() {
+        if previous.map_or(false, |prev| prev.disks().contains(disk)) {
+            continue;
+        }
+
+        let disk_id = if previous.is_some() {
+            format!(
+                "{disk_id} (pending)",
+                disk_id = super::disk_id(vm_desc, disk)
+            )
+        } else {
+            super::disk_id(vm_desc, disk)
+        };
+        match storage.get(disk.storage()) {
+            None => {
+                warn!(
+                    "Invalid type {storage} for {disk_id}",
+                    storage = disk.storage(),
+                );
+                problems = true;
+            }
+            Some(store) if store.as_storpool().is_none() => (),
+            Some(_) => {
+                problems = check_vm_disk(&disk_id, disk) || problems;
+            }
+        }
+    }
+    problems
+}
+
+/// Check the `StorPool`-backed VM disks.
+pub async fn cmd_check_vms(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    state: ConfigState,
+) -> Result<MainExit> {
+    let storage = super::get_storage(api).await?;
+
+    let mut problems = false;
+    for sel in guests::select_with_pools(api, &guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let path_vm = api.path().nodes().id(&sel.node).qemu().id(vmid);
+
+        let vm_desc = sel.describe();
+        debug!("Looking for disks on {vm_desc}");
+        let vm_problems = if state == ConfigState::Current {
+            let vmcfg = api.get(path_vm.config()).await.map_err(Error::Api)?;
+            check_vm_config(&vm_desc, &vmcfg, None, &storage)
+        } else {
+            let pending = api.get(path_vm.pending()).await.map_err(Error::Api)?;
+            let pending_cfg = pending.pending().map_err(Error::Api)?;
+            if state == ConfigState::Both {
+                let current_cfg = pending.current().map_err(Error::Api)?;
+                let current_problems = check_vm_config(&vm_desc, &current_cfg, None, &storage);
+                check_vm_config(&vm_desc, &pending_cfg, Some(&current_cfg), &storage)
+                    || current_problems
+            } else {
+                check_vm_config(&vm_desc, &pending_cfg, None, &storage)
+            }
+        };
+        problems = vm_problems || problems;
+    }
+
+    Ok(MainExit::from_problems(problems))
+}
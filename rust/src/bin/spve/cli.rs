@@ -1,31 +1,794 @@
 //! Parse the spve command-line - subcommands, options, etc.
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
+// The doc comments of the clap structures are displayed verbatim as the --help text.
+#![allow(clippy::doc_markdown)]
 
 use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::result::Result as StdResult;
+use std::time::Duration;
 
-use anyhow::Context;
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, Context};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
+use proxmoxy::types::{VmStatus, VmSummary};
+
+use crate::compat::PveFeature;
+use crate::config::Target;
 use crate::defs::{Error, Result};
+use crate::output::Format;
 
-/// The action requested by the command-line subcommands.
+/// Which clusters to run the command against.
 #[derive(Debug)]
+pub enum Clusters {
+    /// The default cluster specified in the configuration file.
+    Default,
+
+    /// The clusters specified on the command line.
+    Named(Vec<String>),
+
+    /// All the configured clusters.
+    All,
+}
+
+/// The output format for the effective configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    /// The same format as the configuration files.
+    Toml,
+
+    /// A JSON object.
+    Json,
+}
+
+/// Which of the storage usage report's tables to display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UsageTable {
+    /// The usage of each storpool storage.
+    Storage,
+
+    /// The usage of the storpool storage by each VM.
+    Vms,
+}
+
+/// Which version of the VM configuration to examine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigState {
+    /// The configuration that the VM is currently running with.
+    Current,
+
+    /// The configuration that the VM will have once the pending changes are applied.
+    Pending,
+
+    /// Both the current configuration and the pending changes.
+    Both,
+}
+
+/// Which guests to operate on.
+#[derive(Debug, Clone, Default)]
+pub struct GuestSelector {
+    /// Only the VMs with IDs within these ranges; all of them if empty.
+    pub vmids: Vec<RangeInclusive<u32>>,
+
+    /// Only the VMs on these nodes; all of them if empty.
+    pub nodes: Vec<String>,
+
+    /// Only the VMs that have at least one of these tags; all of them if empty.
+    pub tags: Vec<String>,
+
+    /// Only the VMs that belong to at least one of these pools; all of them if empty.
+    pub pools: Vec<String>,
+
+    /// Only the VMs that have a disk on at least one of these storages; all of them if empty.
+    pub storage: Vec<String>,
+
+    /// Only the VMs in this state; all of them if not specified.
+    pub status: Option<VmStatus>,
+
+    /// Never the VMs with IDs within these ranges.
+    pub exclude: Vec<RangeInclusive<u32>>,
+}
+
+impl GuestSelector {
+    /// Were no selection criteria specified at all?
+    ///
+    /// Excluding some VMs does not count as selecting the rest of them.
+    pub fn is_empty(&self) -> bool {
+        self.vmids.is_empty()
+            && self.nodes.is_empty()
+            && self.tags.is_empty()
+            && self.pools.is_empty()
+            && self.storage.is_empty()
+            && self.status.is_none()
+    }
+
+    /// Will all the VMs be selected?
+    pub fn selects_all(&self) -> bool {
+        self.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Should the VMs on this node be examined at all?
+    pub fn matches_node(&self, node: &str) -> bool {
+        self.nodes.is_empty() || self.nodes.iter().any(|name| name == node)
+    }
+
+    /// Does the VM match the criteria that do not need any further information?
+    ///
+    /// The pool and storage criteria are checked separately by [`crate::guests::select`].
+    pub fn matches(&self, vm: &VmSummary) -> bool {
+        let vmid = vm.vmid();
+        (self.vmids.is_empty() || self.vmids.iter().any(|range| range.contains(&vmid)))
+            && !self.exclude.iter().any(|range| range.contains(&vmid))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| vm.tags().contains(tag)))
+            && self.status.map_or(true, |status| vm.status() == status)
+    }
+}
+
+/// Where to migrate the VMs to.
+#[derive(Debug, Clone)]
+pub enum MigrateTarget {
+    /// Pick the online node with the most free memory for each VM.
+    Auto,
+
+    /// Migrate all the VMs to the specified node.
+    Node(String),
+}
+
+/// Where to obtain the value of a new API token from.
+#[derive(Debug, Clone)]
+pub enum TokenSource {
+    /// Read the value from the standard input and store it in the configuration file.
+    Stdin,
+
+    /// Read the value from this file at runtime.
+    File(PathBuf),
+
+    /// Read the value from this environment variable at runtime.
+    Env(String),
+
+    /// Run this program with these arguments at runtime.
+    Command(Vec<String>),
+}
+
+/// How many of the scheduled snapshots to keep.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Keep the newest snapshot for this many of the most recent hours.
+    pub hourly: usize,
+
+    /// Keep the newest snapshot for this many of the most recent days.
+    pub daily: usize,
+
+    /// Keep the newest snapshot for this many of the most recent weeks.
+    pub weekly: usize,
+}
+
+/// The action requested by the command-line subcommands.
+#[derive(Debug, Clone)]
 pub enum Mode {
+    /// Check that the `StorPool` volumes are attached to the nodes where the VMs run.
+    CheckAttachments {
+        /// The VMs to check.
+        guests: GuestSelector,
+    },
+
+    /// Check that all the guests on a node can be migrated away from it.
+    CheckEvacuate {
+        /// The node to be evacuated.
+        node: String,
+
+        /// The VMs to check.
+        guests: GuestSelector,
+    },
+
+    /// Check that the HA-managed VMs can fail over to nodes with the `StorPool` client.
+    CheckHa,
+
+    /// Cross-check the Proxmox VE snapshots against the `StorPool` snapshots.
+    CheckSnapshots {
+        /// The VMs to check.
+        guests: GuestSelector,
+    },
+
+    /// Check the `storpool` storage definitions against the `StorPool` cluster.
+    CheckStorage,
+
     /// Check the configuration of `StorPool`-backed VM disks.
     CheckVms {
-        /// Which cluster to connect to, if not the default one.
-        cluster: Option<String>,
+        /// The VMs to check.
+        guests: GuestSelector,
+
+        /// Which version of the VM configuration to examine.
+        state: ConfigState,
+    },
+
+    /// Add a Proxmox VE cluster to the configuration files.
+    ConfigAddCluster {
+        /// The name of the new cluster.
+        name: String,
+
+        /// Where the cluster's API is located.
+        urls: Vec<String>,
+
+        /// The ID of the API token.
+        token_id: String,
+
+        /// Where to obtain the value of the API token from.
+        token: TokenSource,
+
+        /// Do not verify the TLS certificate of the cluster's API endpoints.
+        insecure: bool,
+
+        /// Test the connection to the cluster before saving the settings.
+        check: bool,
+    },
+
+    /// Write commented configuration file templates.
+    ConfigInit {
+        /// Overwrite any existing configuration files.
+        force: bool,
+    },
+
+    /// Convert the global configuration file to the latest format version.
+    ConfigMigrate {
+        /// Only display the converted configuration, do not write anything.
+        dry_run: bool,
+    },
+
+    /// Display the effective configuration with the secrets redacted.
+    ConfigShow {
+        /// The output format.
+        format: ConfigFormat,
+    },
+
+    /// Check the configuration files for errors.
+    ConfigValidate,
+
+    /// Live-migrate the running VMs off a node.
+    Migrate {
+        /// The node to migrate the VMs from.
+        source: String,
+
+        /// The VMs to migrate.
+        guests: GuestSelector,
+
+        /// Where to migrate the VMs to.
+        target: MigrateTarget,
+
+        /// The maximum number of migrations to run at the same time.
+        parallel: usize,
+
+        /// How long to wait for a single migration before considering it failed.
+        timeout: Duration,
+
+        /// Only display the migration plan, do not migrate anything.
+        dry_run: bool,
+    },
+
+    /// Report the usage of the `storpool` storage.
+    ReportUsage {
+        /// The VMs to report on.
+        guests: GuestSelector,
+
+        /// The output format.
+        format: Format,
+
+        /// Only display one of the report's tables.
+        table: Option<UsageTable>,
+    },
+
+    /// Take a snapshot of the selected VMs.
+    SnapshotCreate {
+        /// The VMs to take a snapshot of.
+        guests: GuestSelector,
+
+        /// The name of the snapshot.
+        name: String,
+
+        /// The description of the snapshot.
+        description: Option<String>,
+
+        /// Also save the memory state of running VMs.
+        vmstate: bool,
+    },
+
+    /// Delete a snapshot of the selected VMs.
+    SnapshotDelete {
+        /// The VMs to delete the snapshot of.
+        guests: GuestSelector,
+
+        /// The name of the snapshot.
+        name: String,
+    },
+
+    /// List the snapshots of the selected VMs.
+    SnapshotList {
+        /// The VMs to list the snapshots of.
+        guests: GuestSelector,
+
+        /// The output format.
+        format: Format,
+    },
+
+    /// Delete the old scheduled snapshots of the selected VMs.
+    SnapshotPrune {
+        /// The VMs to delete the snapshots of.
+        guests: GuestSelector,
+
+        /// The prefix of the names of the scheduled snapshots.
+        prefix: String,
+
+        /// The pattern of the timestamp following the prefix.
+        pattern: String,
+
+        /// How many of the snapshots to keep.
+        policy: RetentionPolicy,
+
+        /// Only display the snapshots that would be deleted.
+        dry_run: bool,
+    },
+
+    /// Roll the selected VMs back to a snapshot.
+    SnapshotRollback {
+        /// The VMs to roll back.
+        guests: GuestSelector,
+
+        /// The name of the snapshot.
+        name: String,
+    },
+
+    /// Display the state of the cluster's nodes, storage, VMs, and `StorPool` services.
+    Status {
+        /// The output format.
+        format: Format,
+    },
+
+    /// Display a VM's disks along with the `StorPool` volumes backing them.
+    VmShow {
+        /// The ID of the VM.
+        vmid: u32,
+
+        /// The output format.
+        format: Format,
+    },
+
+    /// Report the changes in the VM configuration since the previous run.
+    WatchConfigs {
+        /// The VMs to keep track of.
+        guests: GuestSelector,
+
+        /// The directory to record the VM configuration in.
+        state_dir: PathBuf,
     },
 }
 
+impl Mode {
+    /// Does the command operate on a cluster, or only on the local configuration?
+    pub const fn per_cluster(&self) -> bool {
+        !matches!(
+            *self,
+            Self::ConfigAddCluster { .. }
+                | Self::ConfigInit { .. }
+                | Self::ConfigMigrate { .. }
+                | Self::ConfigShow { .. }
+                | Self::ConfigValidate
+        )
+    }
+
+    /// Does the command modify the Proxmox VE cluster, so that it needs quorum?
+    pub const fn writes(&self) -> bool {
+        match *self {
+            Self::Migrate { dry_run, .. } | Self::SnapshotPrune { dry_run, .. } => !dry_run,
+            Self::SnapshotCreate { .. }
+            | Self::SnapshotDelete { .. }
+            | Self::SnapshotRollback { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The name of the check command, used to look up its policy in the configuration.
+    pub const fn check_name(&self) -> Option<&'static str> {
+        match *self {
+            Self::CheckAttachments { .. } => Some("attachments"),
+            Self::CheckEvacuate { .. } => Some("evacuate"),
+            Self::CheckHa => Some("ha"),
+            Self::CheckSnapshots { .. } => Some("snapshots"),
+            Self::CheckStorage => Some("storage"),
+            Self::CheckVms { .. } => Some("vms"),
+            _ => None,
+        }
+    }
+
+    /// The VMs that the command operates on, if it accepts a selection.
+    pub const fn guests(&self) -> Option<&GuestSelector> {
+        match *self {
+            Self::CheckAttachments { ref guests }
+            | Self::CheckEvacuate { ref guests, .. }
+            | Self::CheckSnapshots { ref guests }
+            | Self::CheckVms { ref guests, .. }
+            | Self::Migrate { ref guests, .. }
+            | Self::ReportUsage { ref guests, .. }
+            | Self::SnapshotCreate { ref guests, .. }
+            | Self::SnapshotDelete { ref guests, .. }
+            | Self::SnapshotList { ref guests, .. }
+            | Self::SnapshotPrune { ref guests, .. }
+            | Self::SnapshotRollback { ref guests, .. }
+            | Self::WatchConfigs { ref guests, .. } => Some(guests),
+            _ => None,
+        }
+    }
+
+    /// The Proxmox VE feature that the command needs, if any, and what needs it.
+    pub fn pve_feature(&self) -> Option<(PveFeature, &'static str)> {
+        if self
+            .guests()
+            .map_or(false, |guests| !guests.tags.is_empty())
+        {
+            Some((PveFeature::GuestTags, "Selecting VMs by tag"))
+        } else {
+            None
+        }
+    }
+}
+
+/// The parsed command line: what to do and where.
+#[derive(Debug)]
+pub struct Invocation {
+    /// Which clusters to run the command against.
+    pub clusters: Clusters,
+
+    /// Which configuration files to read.
+    pub cfg_target: Target,
+
+    /// Modify the clusters even if they are not quorate.
+    pub ignore_quorum: bool,
+
+    /// What to do.
+    pub mode: Mode,
+}
+
+/// A list of VM ID ranges specified on the command line.
+#[derive(Debug, Clone)]
+struct VmidRanges(Vec<RangeInclusive<u32>>);
+
+/// Parse a list of VM IDs and VM ID ranges, e.g. "100-199,305".
+fn parse_vmid_ranges(value: &str) -> StdResult<VmidRanges, String> {
+    let parse_id = |id: &str| {
+        id.trim()
+            .parse::<u32>()
+            .map_err(|err| format!("Invalid VM ID {id:?}: {err}"))
+    };
+    value
+        .split(',')
+        .map(|item| match item.split_once('-') {
+            Some((first_str, last_str)) => {
+                let (first, last) = (parse_id(first_str)?, parse_id(last_str)?);
+                if first > last {
+                    Err(format!("Invalid VM ID range {item:?}"))
+                } else {
+                    Ok(first..=last)
+                }
+            }
+            None => parse_id(item).map(|vmid| vmid..=vmid),
+        })
+        .collect::<StdResult<_, _>>()
+        .map(VmidRanges)
+}
+
+/// The state of the VMs to select.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CliVmStatus {
+    /// The running VMs.
+    Running,
+
+    /// The stopped VMs.
+    Stopped,
+}
+
+impl From<CliVmStatus> for VmStatus {
+    fn from(status: CliVmStatus) -> Self {
+        match status {
+            CliVmStatus::Running => Self::Running,
+            CliVmStatus::Stopped => Self::Stopped,
+        }
+    }
+}
+
+/// Command-line options for selecting the guests to operate on.
+#[derive(Debug, Args)]
+struct CliGuestSelector {
+    /// Only the VMs with IDs within these ranges, e.g. "100-199,305".
+    #[clap(long, value_parser = parse_vmid_ranges)]
+    vmid: Vec<VmidRanges>,
+
+    /// Only the VMs on this node; may be specified more than once.
+    #[clap(long)]
+    node: Vec<String>,
+
+    /// Only the VMs that have this tag; may be specified more than once.
+    #[clap(long)]
+    tag: Vec<String>,
+
+    /// Only the VMs in this resource pool; may be specified more than once.
+    #[clap(long)]
+    pool: Vec<String>,
+
+    /// Only the VMs with a disk on this storage; may be specified more than once.
+    #[clap(long)]
+    storage: Vec<String>,
+
+    /// Only the VMs in this state.
+    #[clap(long, value_enum)]
+    status: Option<CliVmStatus>,
+
+    /// Skip the VMs with IDs within these ranges, e.g. "0-99".
+    #[clap(long, value_parser = parse_vmid_ranges)]
+    exclude: Vec<VmidRanges>,
+}
+
+impl From<CliGuestSelector> for GuestSelector {
+    fn from(cli: CliGuestSelector) -> Self {
+        Self {
+            vmids: cli.vmid.into_iter().flat_map(|ranges| ranges.0).collect(),
+            nodes: cli.node,
+            tags: cli.tag,
+            pools: cli.pool,
+            storage: cli.storage,
+            status: cli.status.map(VmStatus::from),
+            exclude: cli
+                .exclude
+                .into_iter()
+                .flat_map(|ranges| ranges.0)
+                .collect(),
+        }
+    }
+}
+
 /// Subcommands for the `check` top-level command.
 #[derive(Debug, Subcommand)]
 enum CliCheckCommand {
+    /// Check that the `StorPool` volumes are attached to the nodes where the VMs run.
+    Attachments {
+        /// The VMs to check.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+    },
+
+    /// Check that all the guests on a node can be migrated away from it.
+    Evacuate {
+        /// The VMs to check.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The node to be evacuated.
+        #[clap(value_name = "NODE")]
+        evac_node: String,
+    },
+
+    /// Check that the HA-managed VMs can fail over to nodes with the `StorPool` client.
+    Ha,
+
+    /// Cross-check the Proxmox VE snapshots against the `StorPool` snapshots.
+    Snapshots {
+        /// The VMs to check.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+    },
+
+    /// Check the `storpool` storage definitions against the `StorPool` cluster.
+    Storage,
+
     /// Check the configuration of `StorPool`-backed VM disks.
-    Vms,
+    Vms {
+        /// The VMs to check.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// Which version of the VM configuration to examine.
+        #[clap(long, value_enum, default_value = "current")]
+        state: ConfigState,
+    },
+}
+
+/// Subcommands for the `config` top-level command.
+#[derive(Debug, Subcommand)]
+enum CliConfigCommand {
+    /// Add a Proxmox VE cluster to the configuration files and test the connection to it.
+    AddCluster {
+        /// The URL of the cluster's API, e.g. "https://pve1.example.com:8006"; may be repeated.
+        #[clap(long, required = true)]
+        url: Vec<String>,
+
+        /// The ID of the API token, e.g. "root@pam!spve".
+        #[clap(long)]
+        token_id: String,
+
+        /// Read the token value from this file at runtime instead of from the standard input.
+        #[clap(long, group = "token")]
+        token_file: Option<PathBuf>,
+
+        /// Read the token value from this environment variable at runtime.
+        #[clap(long, group = "token")]
+        token_env: Option<String>,
+
+        /// Run this command to obtain the token value at runtime, e.g. "pass show pve/spve".
+        ///
+        /// The command is split into words using the shell quoting rules, but it is not
+        /// passed to a shell.
+        #[clap(long, group = "token")]
+        token_command: Option<String>,
+
+        /// Do not verify the TLS certificate of the cluster's API endpoints.
+        #[clap(long)]
+        insecure: bool,
+
+        /// Do not test the connection to the cluster before saving the settings.
+        #[clap(long)]
+        no_check: bool,
+
+        /// The name of the new cluster.
+        name: String,
+    },
+
+    /// Write commented configuration file templates.
+    Init {
+        /// Overwrite any existing configuration files.
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Convert the configuration file to the latest format version, keeping a backup.
+    Migrate {
+        /// Only display the converted configuration, do not write anything.
+        #[clap(short = 'N', long)]
+        dry_run: bool,
+    },
+
+    /// Display the effective configuration with the secrets redacted.
+    Show {
+        /// The output format.
+        #[clap(short, long, value_enum, default_value = "toml")]
+        format: ConfigFormat,
+    },
+
+    /// Check the configuration files for errors.
+    Validate,
+}
+
+/// Subcommands for the `report` top-level command.
+#[derive(Debug, Subcommand)]
+enum CliReportCommand {
+    /// Report the usage of the `storpool` storage per storage and per VM.
+    Usage {
+        /// The VMs to report on.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The output format.
+        #[clap(short, long, value_enum, default_value = "table")]
+        format: Format,
+
+        /// Only display one of the tables; required with "--format csv", ignored with "--format json".
+        #[clap(long, value_enum)]
+        table: Option<UsageTable>,
+    },
+}
+
+/// Subcommands for the `snapshot` top-level command.
+#[derive(Debug, Subcommand)]
+enum CliSnapshotCommand {
+    /// Take a snapshot of the selected VMs.
+    Create {
+        /// The VMs to take a snapshot of.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The description of the snapshot.
+        #[clap(short, long)]
+        description: Option<String>,
+
+        /// Also save the memory state of running VMs.
+        #[clap(long)]
+        vmstate: bool,
+
+        /// The name of the snapshot.
+        name: String,
+    },
+
+    /// Delete a snapshot of the selected VMs.
+    Delete {
+        /// The VMs to delete the snapshot of.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The name of the snapshot.
+        name: String,
+    },
+
+    /// List the snapshots of the selected VMs.
+    List {
+        /// The VMs to list the snapshots of.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The output format.
+        #[clap(short, long, value_enum, default_value = "table")]
+        format: Format,
+    },
+
+    /// Delete the old scheduled snapshots of the selected VMs.
+    Prune {
+        /// The VMs to delete the snapshots of.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The prefix of the names of the scheduled snapshots.
+        #[clap(long, default_value = "spve-auto-")]
+        prefix: String,
+
+        /// The pattern of the UTC timestamp following the prefix (%Y, %m, %d, %H, %M, %S).
+        #[clap(long, default_value = "%Y%m%d-%H%M%S")]
+        pattern: String,
+
+        /// Keep the newest snapshot for this many of the most recent hours.
+        #[clap(long, default_value = "0")]
+        keep_hourly: usize,
+
+        /// Keep the newest snapshot for this many of the most recent days.
+        #[clap(long, default_value = "0")]
+        keep_daily: usize,
+
+        /// Keep the newest snapshot for this many of the most recent weeks.
+        #[clap(long, default_value = "0")]
+        keep_weekly: usize,
+
+        /// Only display the snapshots that would be deleted.
+        #[clap(short = 'N', long)]
+        dry_run: bool,
+    },
+
+    /// Roll the selected VMs back to a snapshot.
+    Rollback {
+        /// The VMs to roll back.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The name of the snapshot.
+        name: String,
+    },
+}
+
+/// Subcommands for the `vm` top-level command.
+#[derive(Debug, Subcommand)]
+enum CliVmCommand {
+    /// Display the VM's disks along with the StorPool volumes backing them.
+    Show {
+        /// The output format.
+        #[clap(short, long, value_enum, default_value = "table")]
+        format: Format,
+
+        /// The ID of the VM.
+        vmid: u32,
+    },
+}
+
+/// Subcommands for the `watch` top-level command.
+#[derive(Debug, Subcommand)]
+enum CliWatchCommand {
+    /// Report the changes in the VM configuration since the previous run.
+    Configs {
+        /// The VMs to keep track of.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The directory to record the VM configuration in.
+        #[clap(long)]
+        state_dir: PathBuf,
+    },
 }
 
 /// Top-level commands.
@@ -37,15 +800,105 @@ enum CliCommand {
         #[clap(subcommand)]
         subc: CliCheckCommand,
     },
+
+    /// Examine or modify the spve configuration files.
+    Config {
+        /// What to do with the configuration, exactly.
+        #[clap(subcommand)]
+        subc: CliConfigCommand,
+    },
+
+    /// Live-migrate the running VMs off a Proxmox VE node.
+    Migrate {
+        /// The node to migrate the VMs from.
+        #[clap(long)]
+        from: String,
+
+        /// The VMs to migrate.
+        #[clap(flatten)]
+        guests: CliGuestSelector,
+
+        /// The node to migrate the VMs to.
+        #[clap(long, conflicts_with = "auto", required_unless_present = "auto")]
+        to: Option<String>,
+
+        /// Pick the online node with the most free memory for each VM.
+        #[clap(long)]
+        auto: bool,
+
+        /// The maximum number of migrations to run at the same time.
+        #[clap(short = 'j', long, default_value = "1")]
+        parallel: usize,
+
+        /// How many seconds to wait for a single migration before considering it failed.
+        #[clap(long, default_value = "3600")]
+        timeout: u64,
+
+        /// Only display the migration plan, do not migrate anything.
+        #[clap(short = 'N', long)]
+        dry_run: bool,
+    },
+
+    /// Report on the Proxmox VE and StorPool resource usage.
+    Report {
+        /// What to report on, exactly.
+        #[clap(subcommand)]
+        subc: CliReportCommand,
+    },
+
+    /// Manage the snapshots of many VMs at once.
+    Snapshot {
+        /// What to do with the snapshots, exactly.
+        #[clap(subcommand)]
+        subc: CliSnapshotCommand,
+    },
+
+    /// Display the state of the Proxmox VE nodes, storage, VMs, and StorPool services.
+    Status {
+        /// The output format; "csv" is not supported.
+        #[clap(short, long, value_enum, default_value = "table")]
+        format: Format,
+    },
+
+    /// Examine a single virtual machine.
+    Vm {
+        /// What to do with the VM, exactly.
+        #[clap(subcommand)]
+        subc: CliVmCommand,
+    },
+
+    /// Keep track of changes between runs.
+    Watch {
+        /// What to keep track of, exactly.
+        #[clap(subcommand)]
+        subc: CliWatchCommand,
+    },
 }
 
 /// The top-level command-line parser.
 #[derive(Debug, Parser)]
+#[allow(clippy::struct_excessive_bools)]
 #[clap(about("manage StorPool-backed Proxmox VE storage"), author, version)]
 struct Cli {
-    /// Which cluster to connect to, if not the default one.
-    #[clap(short, long)]
-    cluster: Option<String>,
+    /// Which clusters to connect to, if not the default one or SPVE_CLUSTER, e.g. "pve1,pve2".
+    #[clap(short, long, value_delimiter = ',')]
+    cluster: Vec<String>,
+
+    /// Run the command against all the configured clusters.
+    #[clap(long, conflicts_with = "cluster")]
+    all_clusters: bool,
+
+    /// The path to the spve.toml configuration file; overrides SPVE_CONFIG.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// The path to the auth.toml authentication data file; overrides SPVE_AUTH_CONFIG.
+    #[clap(long)]
+    auth_config: Option<PathBuf>,
+
+    /// Force the commands that modify a cluster to run even if it is not quorate.
+    #[clap(long)]
+    ignore_quorum: bool,
 
     /// Verbose operation; display diagnostic output.
     #[clap(short, long)]
@@ -85,20 +938,262 @@ fn setup_tracing(cli: &Cli) -> Result<()> {
     Ok(())
 }
 
+/// Make sure that a modifying command will not operate on all the VMs by accident.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if no VMs were selected.
+fn require_selection(cli_guests: CliGuestSelector) -> Result<GuestSelector> {
+    let guests = GuestSelector::from(cli_guests);
+    if guests.is_empty() {
+        return Err(Error::Invoke(anyhow!(
+            "No VMs selected; please specify --vmid, --node, --tag, --pool, --storage, or --status"
+        )));
+    }
+    Ok(guests)
+}
+
+/// Restrict the selection to the VMs on the node that a command operates on.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if the `--node` option was also specified.
+fn on_node(cli_guests: CliGuestSelector, node: &str) -> Result<GuestSelector> {
+    let guests = GuestSelector::from(cli_guests);
+    if !guests.nodes.is_empty() {
+        return Err(Error::Invoke(anyhow!(
+            "The --node option may not be used when operating on the {node} node"
+        )));
+    }
+    Ok(GuestSelector {
+        nodes: vec![node.to_owned()],
+        ..guests
+    })
+}
+
+/// Parse the `config` subcommands.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if the token command is empty.
+fn parse_config(subc: CliConfigCommand) -> Result<Mode> {
+    match subc {
+        CliConfigCommand::AddCluster {
+            url,
+            token_id,
+            token_file,
+            token_env,
+            token_command,
+            insecure,
+            no_check,
+            name,
+        } => {
+            let token = match (token_file, token_env, token_command) {
+                (Some(path), _, _) => TokenSource::File(path),
+                (None, Some(var), _) => TokenSource::Env(var),
+                (None, None, Some(cmd)) => {
+                    let words = shell_words::split(&cmd)
+                        .with_context(|| format!("Could not parse the {cmd:?} token command"))
+                        .map_err(Error::Invoke)?;
+                    if words.is_empty() {
+                        return Err(Error::Invoke(anyhow!("Empty --token-command value")));
+                    }
+                    TokenSource::Command(words)
+                }
+                (None, None, None) => TokenSource::Stdin,
+            };
+            Ok(Mode::ConfigAddCluster {
+                name,
+                urls: url,
+                token_id,
+                token,
+                insecure,
+                check: !no_check,
+            })
+        }
+        CliConfigCommand::Init { force } => Ok(Mode::ConfigInit { force }),
+        CliConfigCommand::Migrate { dry_run } => Ok(Mode::ConfigMigrate { dry_run }),
+        CliConfigCommand::Show { format } => Ok(Mode::ConfigShow { format }),
+        CliConfigCommand::Validate => Ok(Mode::ConfigValidate),
+    }
+}
+
+/// Parse the `snapshot` subcommands.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if no VMs were selected for a modifying command.
+fn parse_snapshot(subc: CliSnapshotCommand) -> Result<Mode> {
+    match subc {
+        CliSnapshotCommand::Create {
+            guests,
+            description,
+            vmstate,
+            name,
+        } => Ok(Mode::SnapshotCreate {
+            guests: require_selection(guests)?,
+            name,
+            description,
+            vmstate,
+        }),
+        CliSnapshotCommand::Delete { guests, name } => Ok(Mode::SnapshotDelete {
+            guests: require_selection(guests)?,
+            name,
+        }),
+        CliSnapshotCommand::List { guests, format } => Ok(Mode::SnapshotList {
+            guests: guests.into(),
+            format,
+        }),
+        CliSnapshotCommand::Prune {
+            guests,
+            prefix,
+            pattern,
+            keep_hourly,
+            keep_daily,
+            keep_weekly,
+            dry_run,
+        } => {
+            if keep_hourly == 0 && keep_daily == 0 && keep_weekly == 0 {
+                return Err(Error::Invoke(anyhow!(
+                    "Refusing to delete all the snapshots; please specify at least one --keep-* option"
+                )));
+            }
+            Ok(Mode::SnapshotPrune {
+                guests: require_selection(guests)?,
+                prefix,
+                pattern,
+                policy: RetentionPolicy {
+                    hourly: keep_hourly,
+                    daily: keep_daily,
+                    weekly: keep_weekly,
+                },
+                dry_run,
+            })
+        }
+        CliSnapshotCommand::Rollback { guests, name } => Ok(Mode::SnapshotRollback {
+            guests: require_selection(guests)?,
+            name,
+        }),
+    }
+}
+
 /// Parse the command-line arguments: subcommands, options, etc.
 ///
 /// # Errors
 ///
 /// [`Error::Internal`] is all we can do for the present.
-pub fn parse() -> Result<Mode> {
+pub fn parse() -> Result<Invocation> {
     let cli = Cli::try_parse()
         .context("Could not parse the command-line parameters")
         .map_err(Error::Invoke)?;
     setup_tracing(&cli)?;
-    match cli.command {
+    let clusters = if cli.all_clusters {
+        Clusters::All
+    } else if cli.cluster.is_empty() {
+        Clusters::Default
+    } else {
+        Clusters::Named(cli.cluster)
+    };
+    Ok(Invocation {
+        clusters,
+        cfg_target: Target {
+            config: cli.config,
+            auth_config: cli.auth_config,
+            cluster: None,
+        },
+        ignore_quorum: cli.ignore_quorum,
+        mode: parse_mode(cli.command)?,
+    })
+}
+
+/// Parse the subcommands and their options.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if the options are inconsistent.
+fn parse_mode(command: CliCommand) -> Result<Mode> {
+    match command {
         CliCommand::Check { subc } => match subc {
-            CliCheckCommand::Vms => Ok(Mode::CheckVms {
-                cluster: cli.cluster,
+            CliCheckCommand::Attachments { guests } => Ok(Mode::CheckAttachments {
+                guests: guests.into(),
+            }),
+            CliCheckCommand::Evacuate { guests, evac_node } => Ok(Mode::CheckEvacuate {
+                guests: on_node(guests, &evac_node)?,
+                node: evac_node,
+            }),
+            CliCheckCommand::Ha => Ok(Mode::CheckHa),
+            CliCheckCommand::Snapshots { guests } => Ok(Mode::CheckSnapshots {
+                guests: guests.into(),
+            }),
+            CliCheckCommand::Storage => Ok(Mode::CheckStorage),
+            CliCheckCommand::Vms { guests, state } => Ok(Mode::CheckVms {
+                guests: guests.into(),
+                state,
+            }),
+        },
+        CliCommand::Config { subc } => parse_config(subc),
+        CliCommand::Migrate {
+            from,
+            guests,
+            to,
+            auto: _,
+            parallel,
+            timeout,
+            dry_run,
+        } => {
+            if parallel == 0 {
+                return Err(Error::Invoke(anyhow!(
+                    "The number of parallel migrations must be positive"
+                )));
+            }
+            if timeout == 0 {
+                return Err(Error::Invoke(anyhow!(
+                    "The migration timeout must be positive"
+                )));
+            }
+            Ok(Mode::Migrate {
+                guests: on_node(guests, &from)?,
+                source: from,
+                target: to.map_or(MigrateTarget::Auto, MigrateTarget::Node),
+                parallel,
+                timeout: Duration::from_secs(timeout),
+                dry_run,
+            })
+        }
+        CliCommand::Report { subc } => match subc {
+            CliReportCommand::Usage {
+                guests,
+                format,
+                table,
+            } => {
+                if format == Format::Csv && table.is_none() {
+                    return Err(Error::Invoke(anyhow!(
+                        "The CSV output holds a single table; please specify --table storage or --table vms"
+                    )));
+                }
+                Ok(Mode::ReportUsage {
+                    guests: guests.into(),
+                    format,
+                    table,
+                })
+            }
+        },
+        CliCommand::Snapshot { subc } => parse_snapshot(subc),
+        CliCommand::Status { format } => {
+            if format == Format::Csv {
+                return Err(Error::Invoke(anyhow!(
+                    "The status report holds several tables; please use --format table or --format json"
+                )));
+            }
+            Ok(Mode::Status { format })
+        }
+        CliCommand::Vm { subc } => match subc {
+            CliVmCommand::Show { vmid, format } => Ok(Mode::VmShow { vmid, format }),
+        },
+        CliCommand::Watch { subc } => match subc {
+            CliWatchCommand::Configs { guests, state_dir } => Ok(Mode::WatchConfigs {
+                guests: guests.into(),
+                state_dir,
             }),
         },
     }
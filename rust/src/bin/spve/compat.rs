@@ -0,0 +1,65 @@
+//! Make sure that a Proxmox VE cluster is recent enough for the requested command.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use proxmoxy::defs::Error as PmError;
+use proxmoxy::types::PveVersion;
+use proxmoxy::Proxmoxy;
+
+use crate::cli::Mode;
+use crate::defs::{Error, Result};
+
+/// The oldest Proxmox VE release that the spve tool works with at all.
+const MIN_PVE_VERSION: (u32, u32, &str) = (7, 0, "The spve tool");
+
+/// A Proxmox VE feature that only some of the commands need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PveFeature {
+    /// The guests may be tagged, and the guest listing includes the tags.
+    GuestTags,
+}
+
+impl PveFeature {
+    /// Does the specified Proxmox VE version support this feature?
+    pub const fn supported(self, version: &PveVersion) -> bool {
+        match self {
+            Self::GuestTags => version.has_guest_tags(),
+        }
+    }
+
+    /// The first Proxmox VE release that supports this feature, for the error messages.
+    pub const fn since(self) -> (u32, u32) {
+        match self {
+            Self::GuestTags => PveVersion::GUEST_TAGS,
+        }
+    }
+}
+
+/// Make sure that the cluster runs a Proxmox VE version that supports the command.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE version could not be obtained.
+/// [`Error::TooOld`] if the Proxmox VE version is older than the command needs.
+pub async fn check(api: &Proxmoxy, cl_name: &str, mode: &Mode) -> Result<()> {
+    let (major, minor, what) = MIN_PVE_VERSION;
+    match api.require_version(major, minor, what).await {
+        Ok(()) => (),
+        Err(err @ PmError::TooOld(_)) => return Err(Error::TooOld(cl_name.to_owned(), err)),
+        Err(err) => return Err(Error::Api(err)),
+    }
+
+    if let Some((feature, what)) = mode.pve_feature() {
+        let version = api.version().await.map_err(Error::Api)?;
+        if !feature.supported(version) {
+            let (major, minor) = feature.since();
+            return Err(Error::TooOld(
+                cl_name.to_owned(),
+                PmError::TooOld(format!(
+                    "{what} needs Proxmox VE {major}.{minor} or later, found {version}"
+                )),
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1058 @@
+//! Parse the configuration files for the spve tool.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::iter;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use xdg::BaseDirectories;
+
+use proxmoxy::{Auth as PmAuth, BackendConfig, HttpsOptions, Proxmoxy};
+
+use crate::defs::{Error, Result};
+use crate::storpool::StorPool;
+
+pub mod v0_1;
+
+/// The latest minor version of the configuration file format.
+pub const FORMAT_MINOR_LATEST: u32 = 2;
+
+/// The directory containing the system-wide configuration files.
+pub const SYSTEM_DIR: &str = "/etc/spve";
+
+/// The placeholder displayed instead of a secret value.
+pub const REDACTED: &str = "(redacted)";
+
+/// The environment variable that overrides the default cluster.
+pub const ENV_CLUSTER: &str = "SPVE_CLUSTER";
+
+/// The environment variable that overrides the API URL of the selected cluster.
+pub const ENV_URL: &str = "SPVE_URL";
+
+/// The environment variable that overrides the API token ID for the selected cluster.
+pub const ENV_TOKEN_ID: &str = "SPVE_TOKEN_ID";
+
+/// The environment variable that overrides the API token value for the selected cluster.
+pub const ENV_TOKEN: &str = "SPVE_TOKEN";
+
+/// The cluster name used if there is no `spve.toml` file and `SPVE_CLUSTER` is not set.
+pub const ENV_ONLY_CLUSTER: &str = "default";
+
+/// One of the spve configuration files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFile {
+    /// The global configuration file, `spve.toml`.
+    Global,
+
+    /// The authentication data file, `auth.toml`.
+    Auth,
+}
+
+impl ConfigFile {
+    /// The name of the file without any directories.
+    #[must_use]
+    pub const fn filename(self) -> &'static str {
+        match self {
+            Self::Global => "spve.toml",
+            Self::Auth => "auth.toml",
+        }
+    }
+
+    /// The path to the file within the XDG configuration directories.
+    #[must_use]
+    pub const fn relpath(self) -> &'static str {
+        match self {
+            Self::Global => "spve/spve.toml",
+            Self::Auth => "spve/auth.toml",
+        }
+    }
+
+    /// The command-line option that specifies the path to the file.
+    #[must_use]
+    pub const fn option(self) -> &'static str {
+        match self {
+            Self::Global => "--config",
+            Self::Auth => "--auth-config",
+        }
+    }
+
+    /// The environment variable that specifies the path to the file.
+    #[must_use]
+    pub const fn env_var(self) -> &'static str {
+        match self {
+            Self::Global => "SPVE_CONFIG",
+            Self::Auth => "SPVE_AUTH_CONFIG",
+        }
+    }
+
+    /// The path specified on the command line or in the environment, if any.
+    fn explicit_path(self, target: &Target) -> Option<PathBuf> {
+        let option = match self {
+            Self::Global => target.config.as_ref(),
+            Self::Auth => target.auth_config.as_ref(),
+        };
+        option
+            .cloned()
+            .or_else(|| env::var_os(self.env_var()).map(PathBuf::from))
+            .filter(|path| !path.as_os_str().is_empty())
+    }
+}
+
+/// Which configuration files to read and which cluster to manage.
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    /// The path to the global configuration file, if specified on the command line.
+    pub config: Option<PathBuf>,
+
+    /// The path to the authentication data file, if specified on the command line.
+    pub auth_config: Option<PathBuf>,
+
+    /// The cluster to manage, if not the default one.
+    pub cluster: Option<String>,
+}
+
+/// Get the value of an environment variable if it is set and not empty.
+fn env_value(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Get the names of the connection overrides set in the environment.
+#[must_use]
+pub fn env_overrides() -> Vec<&'static str> {
+    [ENV_URL, ENV_TOKEN_ID, ENV_TOKEN]
+        .into_iter()
+        .filter(|name| env_value(name).is_some())
+        .collect()
+}
+
+/// Check whether the environment describes the whole connection to a cluster.
+fn env_connection() -> bool {
+    env_overrides().len() == 3
+}
+
+/// The settings that specify where to obtain a secret value from.
+#[derive(Debug, Clone, Copy)]
+pub struct SecretSources<'data> {
+    /// The name of the setting that holds the value itself, e.g. "value" or "token".
+    pub key: &'static str,
+
+    /// The value itself.
+    pub value: Option<&'data str>,
+
+    /// The path to a file containing the value.
+    pub file: Option<&'data Path>,
+
+    /// The name of an environment variable containing the value.
+    pub env: Option<&'data str>,
+
+    /// A program and its arguments that will output the value.
+    pub command: Option<&'data [String]>,
+}
+
+impl SecretSources<'_> {
+    /// The names of the settings that are actually specified.
+    #[must_use]
+    pub fn names(&self) -> Vec<String> {
+        let key = self.key;
+        [
+            (key.to_owned(), self.value.is_some()),
+            (format!("{key}_file"), self.file.is_some()),
+            (format!("{key}_env"), self.env.is_some()),
+            (format!("{key}_command"), self.command.is_some()),
+        ]
+        .into_iter()
+        .filter(|&(_, present)| present)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    /// The message to show if not exactly one of the settings is specified.
+    #[must_use]
+    pub fn exactly_one(&self) -> String {
+        let key = self.key;
+        format!("Exactly one of {key}, {key}_file, {key}_env, or {key}_command must be specified")
+    }
+
+    /// Describe where the value is obtained from without revealing it.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        match (self.value, self.file, self.env, self.command) {
+            (Some(_), None, None, None) => REDACTED.to_owned(),
+            (None, Some(path), None, None) => format!("file {path}", path = path.display()),
+            (None, None, Some(var), None) => format!("environment variable {var}"),
+            (None, None, None, Some(cmd)) => format!("command {cmd}", cmd = cmd.join(" ")),
+            _ => format!("invalid: {names}", names = self.names().join(", ")),
+        }
+    }
+
+    /// Obtain the value from the single source specified in the configuration.
+    ///
+    /// The `what` string describes the secret in error messages, e.g. "the pve token".
+    ///
+    /// # Errors
+    ///
+    /// [`Error::ConfigSecret`] if there is not exactly one source or it could not be read.
+    pub fn read(&self, what: &str) -> Result<String> {
+        let secret_err = |msg: String| Error::ConfigSecret(anyhow!(msg));
+        let key = self.key;
+        let value = match (self.value, self.file, self.env, self.command) {
+            (Some(value), None, None, None) => value.to_owned(),
+            (None, Some(path), None, None) => {
+                check_permissions(path)?;
+                fs::read_to_string(path)
+                    .with_context(|| {
+                        format!(
+                            "Could not read the {path} file for {what}",
+                            path = path.display()
+                        )
+                    })
+                    .map_err(Error::ConfigSecret)?
+            }
+            (None, None, Some(var), None) => env::var(var)
+                .with_context(|| {
+                    format!("Could not read the {var} environment variable for {what}")
+                })
+                .map_err(Error::ConfigSecret)?,
+            (None, None, None, Some(cmd)) => {
+                let (prog, args) = cmd
+                    .split_first()
+                    .ok_or_else(|| secret_err(format!("Empty {key}_command for {what}")))?;
+                let output = Command::new(prog)
+                    .args(args)
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .with_context(|| format!("Could not run `{prog}` for {what}"))
+                    .map_err(Error::ConfigSecret)?;
+                if !output.status.success() {
+                    return Err(secret_err(format!(
+                        "`{prog}` failed for {what}: {status}",
+                        status = output.status
+                    )));
+                }
+                String::from_utf8(output.stdout)
+                    .with_context(|| format!("`{prog}` output an invalid value for {what}"))
+                    .map_err(Error::ConfigSecret)?
+            }
+            _ => {
+                return Err(secret_err(format!(
+                    "{exactly} for {what}",
+                    exactly = self.exactly_one()
+                )))
+            }
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(secret_err(format!("Empty value for {what}")));
+        }
+        Ok(value.to_owned())
+    }
+}
+
+/// Token authentication data for the Proxmox VE API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthToken {
+    /// The token name.
+    pub id: String,
+
+    /// The token value itself.
+    #[serde(default)]
+    pub value: Option<String>,
+
+    /// The path to a file containing the token value.
+    #[serde(default)]
+    pub value_file: Option<PathBuf>,
+
+    /// The name of an environment variable containing the token value.
+    #[serde(default)]
+    pub value_env: Option<String>,
+
+    /// A program and its arguments that will output the token value.
+    #[serde(default)]
+    pub value_command: Option<Vec<String>>,
+}
+
+impl AuthToken {
+    /// The settings that specify where to obtain the token value from.
+    #[must_use]
+    pub fn sources(&self) -> SecretSources<'_> {
+        SecretSources {
+            key: "value",
+            value: self.value.as_deref(),
+            file: self.value_file.as_deref(),
+            env: self.value_env.as_deref(),
+            command: self.value_command.as_deref(),
+        }
+    }
+
+    /// Obtain the token value from the single source specified in the configuration.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::ConfigSecret`] if there is not exactly one source or it could not be read.
+    pub fn get_value(&self) -> Result<String> {
+        self.sources()
+            .read(&format!("the {id} token", id = self.id))
+    }
+}
+
+/// Cluster authentication data for the Proxmox VE API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "auth_type")]
+pub enum AuthCluster {
+    /// Authenticate using an API token.
+    #[serde(rename = "token")]
+    Token(AuthToken),
+}
+
+/// Authentication data for a `StorPool` cluster's API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthStorPool {
+    /// The `StorPool` API token itself.
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// The path to a file containing the `StorPool` API token.
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+
+    /// The name of an environment variable containing the `StorPool` API token.
+    #[serde(default)]
+    pub token_env: Option<String>,
+
+    /// A program and its arguments that will output the `StorPool` API token.
+    #[serde(default)]
+    pub token_command: Option<Vec<String>>,
+}
+
+impl AuthStorPool {
+    /// The settings that specify where to obtain the token from.
+    #[must_use]
+    pub fn sources(&self) -> SecretSources<'_> {
+        SecretSources {
+            key: "token",
+            value: self.token.as_deref(),
+            file: self.token_file.as_deref(),
+            env: self.token_env.as_deref(),
+            command: self.token_command.as_deref(),
+        }
+    }
+
+    /// Obtain the token from the single source specified in the configuration.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::ConfigSecret`] if there is not exactly one source or it could not be read.
+    pub fn get_token(&self, cluster: &str) -> Result<String> {
+        self.sources()
+            .read(&format!("the StorPool API token for the {cluster} cluster"))
+    }
+}
+
+/// Authentication data for the Proxmox VE clusters.
+#[derive(Debug, Deserialize)]
+pub struct AuthSnippet {
+    /// Authentication data for each cluster.
+    pub clusters: HashMap<String, AuthCluster>,
+
+    /// Authentication data for the `StorPool` API of each cluster, if configured.
+    #[serde(default)]
+    pub storpool: HashMap<String, AuthStorPool>,
+}
+
+/// Default settings if not overridden at each invocation.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpveDefaults {
+    /// The cluster name to manage.
+    pub cluster: String,
+}
+
+/// The way in which to connect to the Proxmox VE API.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ApiMode {
+    /// Use the JSON-over-HTTPS interface.
+    #[serde(rename = "https")]
+    Https,
+}
+
+/// How to reach the `StorPool` API for the storage backing a Proxmox VE cluster.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpveStorPoolSnippet {
+    /// Where the `StorPool` API is located, e.g. `http://10.1.2.3:81/ctrl/1.0`.
+    pub url: String,
+
+    /// The `StorPool` client ID of each Proxmox VE node.
+    #[serde(default)]
+    pub nodes: BTreeMap<String, u32>,
+}
+
+/// How to verify the TLS certificate presented by a cluster's API endpoints.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsSnippet {
+    /// Verify the certificate at all.
+    #[serde(default = "TlsSnippet::default_verify")]
+    pub verify: bool,
+
+    /// The path to an additional CA certificate in PEM format to trust.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+}
+
+impl TlsSnippet {
+    /// Verify the certificates unless explicitly told not to.
+    const fn default_verify() -> bool {
+        true
+    }
+}
+
+impl Default for TlsSnippet {
+    fn default() -> Self {
+        Self {
+            verify: Self::default_verify(),
+            ca_file: None,
+        }
+    }
+}
+
+/// How long to wait for a cluster's API to respond, in seconds.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TimeoutsSnippet {
+    /// How long to wait for a connection to be established.
+    #[serde(default)]
+    pub connect: Option<u64>,
+
+    /// How long to wait for a whole request to complete.
+    #[serde(default)]
+    pub request: Option<u64>,
+}
+
+/// What to do with the problems found by a check command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CheckPolicy {
+    /// Report the problems and fail.
+    #[serde(rename = "fail")]
+    Fail,
+
+    /// Report the problems, but do not fail.
+    #[serde(rename = "warn")]
+    Warn,
+
+    /// Do not run the check at all.
+    #[serde(rename = "skip")]
+    Skip,
+}
+
+impl Default for CheckPolicy {
+    fn default() -> Self {
+        Self::Fail
+    }
+}
+
+/// What to do with the problems found by each of the check commands.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChecksSnippet {
+    /// The `check attachments` command.
+    #[serde(default)]
+    pub attachments: CheckPolicy,
+
+    /// The `check evacuate` command.
+    #[serde(default)]
+    pub evacuate: CheckPolicy,
+
+    /// The `check ha` command.
+    #[serde(default)]
+    pub ha: CheckPolicy,
+
+    /// The `check snapshots` command.
+    #[serde(default)]
+    pub snapshots: CheckPolicy,
+
+    /// The `check storage` command.
+    #[serde(default)]
+    pub storage: CheckPolicy,
+
+    /// The `check vms` command.
+    #[serde(default)]
+    pub vms: CheckPolicy,
+}
+
+impl ChecksSnippet {
+    /// The policy for the check command with the specified name.
+    #[must_use]
+    pub fn policy(&self, check: &str) -> CheckPolicy {
+        match check {
+            "attachments" => self.attachments,
+            "evacuate" => self.evacuate,
+            "ha" => self.ha,
+            "snapshots" => self.snapshots,
+            "storage" => self.storage,
+            "vms" => self.vms,
+            _ => CheckPolicy::Fail,
+        }
+    }
+}
+
+/// General configuration settings for a Proxmox VE cluster managed by the spve tool.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpveClusterSnippet {
+    /// How to connect to the cluster's API.
+    pub api_mode: ApiMode,
+
+    /// Where the cluster's API is located; the rest are tried if the first one is unreachable.
+    pub endpoints: Vec<String>,
+
+    /// How to verify the TLS certificate of the cluster's API endpoints.
+    #[serde(default)]
+    pub tls: TlsSnippet,
+
+    /// How long to wait for the cluster's API to respond.
+    #[serde(default)]
+    pub timeouts: TimeoutsSnippet,
+
+    /// What to do with the problems found by the check commands.
+    #[serde(default)]
+    pub checks: ChecksSnippet,
+
+    /// How to connect to the `StorPool` API, if at all.
+    pub storpool: Option<SpveStorPoolSnippet>,
+}
+
+/// General configuration settings for the spve tool.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SpveSnippet {
+    /// Some default values.
+    pub defaults: SpveDefaults,
+
+    /// Per-cluster configuration settings.
+    pub clusters: BTreeMap<String, SpveClusterSnippet>,
+}
+
+/// The format of the `spve.toml` global configuration file, version 0.2.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GlobalSnippet {
+    /// General settings for the spve tool.
+    pub spve: SpveSnippet,
+}
+
+/// The aggregated information about the Proxmox VE cluster to manage.
+#[derive(Debug)]
+pub struct Cluster {
+    /// The name of the cluster.
+    pub name: String,
+
+    /// The authentication information for the cluster.
+    pub auth: AuthCluster,
+
+    /// The API endpoint information for the cluster.
+    pub spve: SpveClusterSnippet,
+}
+
+impl Cluster {
+    /// Build a proxy for sending requests to the Proxmox VE API of this cluster.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the `proxmoxy` crate's methods failed.
+    /// [`Error::ConfigParse`] if there are no endpoints or the CA certificate could not be read.
+    /// [`Error::ConfigSecret`] if the authentication token could not be obtained.
+    pub fn get_proxmox_api(&self) -> Result<Proxmoxy> {
+        let (url, fallback) = self.spve.endpoints.split_first().ok_or_else(|| {
+            Error::ConfigParse(anyhow!(
+                "No API endpoints defined for the {name} cluster",
+                name = self.name
+            ))
+        })?;
+        let mut opts = HttpsOptions::default();
+        opts.verify_tls = self.spve.tls.verify;
+        if let Some(ref path) = self.spve.tls.ca_file {
+            opts.ca_cert_pem = Some(
+                fs::read(path)
+                    .with_context(|| {
+                        format!(
+                            "Could not read the {path} CA certificate file",
+                            path = path.display()
+                        )
+                    })
+                    .map_err(Error::ConfigParse)?,
+            );
+        }
+        opts.connect_timeout = self.spve.timeouts.connect.map(Duration::from_secs);
+        opts.timeout = self.spve.timeouts.request.map(Duration::from_secs);
+        opts.fallback_urls = fallback.to_vec();
+
+        match self.spve.api_mode {
+            ApiMode::Https => match self.auth {
+                AuthCluster::Token(ref token) => {
+                    let cfg = BackendConfig {
+                        auth: PmAuth::Token(token.id.clone(), token.get_value()?),
+                        url: url.clone(),
+                    };
+                    Proxmoxy::get_https_api_with(cfg, opts).map_err(Error::Api)
+                }
+            },
+        }
+    }
+}
+
+/// The parsed configuration files before a cluster has been selected.
+#[derive(Debug)]
+pub struct Files {
+    /// The path to the global configuration file, if there is one.
+    pub global_path: Option<PathBuf>,
+
+    /// The general configuration settings.
+    pub global: SpveSnippet,
+
+    /// The path to the authentication data file, if there is one.
+    pub auth_path: Option<PathBuf>,
+
+    /// How to authenticate to the various clusters' APIs.
+    pub auth: AuthSnippet,
+}
+
+impl Files {
+    /// Apply the `SPVE_*` environment variable overrides, return the name of the selected cluster.
+    ///
+    /// The `SPVE_CLUSTER` variable overrides the default cluster, but not the one specified
+    /// on the command line. The `SPVE_URL`, `SPVE_TOKEN_ID`, and `SPVE_TOKEN` variables
+    /// override the settings of the selected cluster, or even define it if it is not
+    /// present in the configuration files at all; they only make sense for a single
+    /// cluster, so they are refused when more than one cluster is selected.
+    pub fn apply_env(&mut self, cluster: Option<&str>) -> String {
+        if let Some(name) = env_value(ENV_CLUSTER) {
+            self.global.defaults.cluster = name;
+        }
+        let cl_name = cluster.unwrap_or(&self.global.defaults.cluster).to_owned();
+
+        if let Some(url) = env_value(ENV_URL) {
+            match self.global.clusters.get_mut(&cl_name) {
+                Some(cl_cfg) => cl_cfg.endpoints = vec![url],
+                None => {
+                    self.global.clusters.insert(
+                        cl_name.clone(),
+                        SpveClusterSnippet {
+                            api_mode: ApiMode::Https,
+                            endpoints: vec![url],
+                            tls: TlsSnippet::default(),
+                            timeouts: TimeoutsSnippet::default(),
+                            checks: ChecksSnippet::default(),
+                            storpool: None,
+                        },
+                    );
+                }
+            }
+        }
+
+        let token_id = env_value(ENV_TOKEN_ID);
+        let token_value = env_value(ENV_TOKEN);
+        match self.auth.clusters.get_mut(&cl_name) {
+            Some(&mut AuthCluster::Token(ref mut token)) => {
+                if let Some(id) = token_id {
+                    token.id = id;
+                }
+                if let Some(value) = token_value {
+                    *token = AuthToken {
+                        id: token.id.clone(),
+                        value: Some(value),
+                        value_file: None,
+                        value_env: None,
+                        value_command: None,
+                    };
+                }
+            }
+            None => {
+                if let (Some(id), Some(value)) = (token_id, token_value) {
+                    self.auth.clusters.insert(
+                        cl_name.clone(),
+                        AuthCluster::Token(AuthToken {
+                            id,
+                            value: Some(value),
+                            value_file: None,
+                            value_env: None,
+                            value_command: None,
+                        }),
+                    );
+                }
+            }
+        }
+
+        cl_name
+    }
+}
+
+/// Runtime configuration for the `spve` tool.
+#[derive(Debug)]
+pub struct Config {
+    /// How to authenticate to the various clusters' APIs.
+    pub auth: AuthSnippet,
+
+    /// The selected cluster.
+    pub cluster: Cluster,
+}
+
+impl Config {
+    /// Build a proxy for sending requests to the Proxmox VE API using this cluster configuration.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the `proxmoxy` crate's methods failed.
+    /// [`Error::ConfigSecret`] if the authentication token could not be obtained.
+    pub fn get_proxmox_api(&self) -> Result<Proxmoxy> {
+        self.cluster.get_proxmox_api()
+    }
+
+    /// Build a client for sending requests to the `StorPool` API for this cluster.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::StorPoolNotConfigured`] if there is no `StorPool` API URL or token.
+    /// [`Error::StorPool`] if the HTTP client could not be initialized.
+    pub fn get_storpool_api(&self) -> Result<StorPool> {
+        let name = &self.cluster.name;
+        let sp_cfg = self
+            .cluster
+            .spve
+            .storpool
+            .as_ref()
+            .ok_or_else(|| Error::StorPoolNotConfigured(name.clone()))?;
+        let sp_auth = self
+            .auth
+            .storpool
+            .get(name)
+            .ok_or_else(|| Error::StorPoolNotConfigured(name.clone()))?;
+        StorPool::new(
+            &sp_cfg.url,
+            &sp_auth.get_token(name)?,
+            &self.cluster.spve.timeouts,
+        )
+    }
+
+    /// Map the `StorPool` client IDs to the names of the Proxmox VE nodes.
+    #[must_use]
+    pub fn storpool_node_names(&self) -> HashMap<u32, String> {
+        self.cluster
+            .spve
+            .storpool
+            .as_ref()
+            .map(|sp_cfg| {
+                sp_cfg
+                    .nodes
+                    .iter()
+                    .map(|(node, sp_id)| (*sp_id, node.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Get the format version of a configuration file, make sure it is supported.
+///
+/// Version 0.0 is still accepted and treated the same as version 0.1, as earlier
+/// versions of the spve tool did.
+///
+/// # Errors
+///
+/// [`Error::ConfigParse`] if the format version could not be parsed or is not supported.
+pub fn get_format_version(path: &Path, contents: &str) -> Result<(u32, u32)> {
+    let fver = typed_format_version::get_version_from_str(contents, toml::from_str)
+        .with_context(|| {
+            format!(
+                "Could not parse the format version of the {path} file",
+                path = path.display()
+            )
+        })
+        .map_err(Error::ConfigParse)?;
+    if fver.major() != 0 || fver.minor() > FORMAT_MINOR_LATEST {
+        return Err(Error::ConfigParse(anyhow!(
+            "Unsupported format version {major}.{minor} for the {path} file",
+            major = fver.major(),
+            minor = fver.minor(),
+            path = path.display(),
+        )));
+    }
+    Ok((fver.major(), fver.minor()))
+}
+
+/// Parse the contents of the global configuration file according to its format version.
+///
+/// # Errors
+///
+/// [`Error::ConfigParse`] if the contents could not be parsed.
+pub fn parse_global(path: &Path, contents: &str) -> Result<GlobalSnippet> {
+    let res = match get_format_version(path, contents)? {
+        (0, 0 | 1) => toml::from_str::<v0_1::GlobalSnippet>(contents).map(GlobalSnippet::from),
+        _ => toml::from_str::<GlobalSnippet>(contents),
+    };
+    res.with_context(|| format!("Could not parse the {path} file", path = path.display()))
+        .map_err(Error::ConfigParse)
+}
+
+/// Parse the contents of the authentication data file; its layout is the same in all versions.
+///
+/// # Errors
+///
+/// [`Error::ConfigParse`] if the contents could not be parsed.
+pub fn parse_auth(path: &Path, contents: &str) -> Result<AuthSnippet> {
+    get_format_version(path, contents)?;
+    toml::from_str::<AuthSnippet>(contents)
+        .with_context(|| format!("Could not parse the {path} file", path = path.display()))
+        .map_err(Error::ConfigParse)
+}
+
+/// Make sure that a file containing secrets may only be read by its owner.
+///
+/// The check may be skipped by setting the `SPVE_ALLOW_INSECURE_AUTH` environment variable.
+///
+/// # Errors
+///
+/// [`Error::ConfigRead`] if the file's metadata could not be examined.
+/// [`Error::ConfigSecret`] if the file is readable by other accounts.
+#[cfg(unix)]
+pub fn check_permissions(path: &Path) -> Result<()> {
+    if env::var_os("SPVE_ALLOW_INSECURE_AUTH").is_some() {
+        return Ok(());
+    }
+    let mode = fs::metadata(path)
+        .map_err(Error::ConfigRead)?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(Error::ConfigSecret(anyhow!(
+            "The {path} file may be accessed by other accounts (mode {mode:04o}); \
+             run `chmod 600` on it or set SPVE_ALLOW_INSECURE_AUTH to skip this check",
+            path = path.display(),
+            mode = mode & 0o7777
+        )));
+    }
+    Ok(())
+}
+
+/// Make sure that a file containing secrets may only be read by its owner.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+pub const fn check_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Prepare to look for the configuration files in the XDG directories.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+fn config_dirs() -> Result<BaseDirectories> {
+    BaseDirectories::new()
+        .context("Could not initialize the XDG base directories parser")
+        .map_err(Error::ConfigEnv)
+}
+
+/// Find a configuration file: explicitly specified, in the XDG directories, or system-wide.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+/// [`Error::ConfigFileMissing`] if the config file could not be found.
+pub fn find_file(target: &Target, which: ConfigFile) -> Result<PathBuf> {
+    let candidates = if let Some(path) = which.explicit_path(target) {
+        vec![path]
+    } else {
+        let dirs = config_dirs()?;
+        iter::once(dirs.get_config_home())
+            .chain(dirs.get_config_dirs())
+            .map(|dir| dir.join(which.relpath()))
+            .chain(iter::once(Path::new(SYSTEM_DIR).join(which.filename())))
+            .collect()
+    };
+    if let Some(path) = candidates.iter().find(|path| path.is_file()) {
+        return Ok(path.clone());
+    }
+    Err(Error::ConfigFileMissing(
+        which.relpath().to_owned(),
+        format!(
+            "looked for the {option} option, the {env_var} environment variable, \
+             the XDG configuration directories, and the {SYSTEM_DIR} directory \
+             in this order; tried {tried}",
+            option = which.option(),
+            env_var = which.env_var(),
+            tried = candidates
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    ))
+}
+
+/// Figure out where a new configuration file should be created.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+/// [`Error::ConfigWrite`] if the configuration directory could not be created.
+pub fn place_file(target: &Target, which: ConfigFile) -> Result<PathBuf> {
+    match which.explicit_path(target) {
+        Some(path) => Ok(path),
+        None => config_dirs()?
+            .place_config_file(which.relpath())
+            .with_context(|| {
+                format!(
+                    "Could not create the directory for the {relpath} file",
+                    relpath = which.relpath()
+                )
+            })
+            .map_err(Error::ConfigWrite),
+    }
+}
+
+/// Find the global spve configuration file, parse it.
+///
+/// # Errors
+///
+/// [`Error::ConfigFileMissing`] if the config file could not be found.
+/// [`Error::ConfigParse`] if the config file could not be parsed.
+pub fn read_global(target: &Target) -> Result<(PathBuf, GlobalSnippet)> {
+    let global_path = find_file(target, ConfigFile::Global)?;
+    let contents = fs::read_to_string(&global_path).map_err(Error::ConfigRead)?;
+    let global = parse_global(&global_path, &contents)?;
+    Ok((global_path, global))
+}
+
+/// Find the spve authentication data file, parse it.
+///
+/// # Errors
+///
+/// [`Error::ConfigFileMissing`] if the config file could not be found.
+/// [`Error::ConfigParse`] if the config file could not be parsed.
+/// [`Error::ConfigSecret`] if the config file is readable by other accounts.
+pub fn read_auth(target: &Target) -> Result<(PathBuf, AuthSnippet)> {
+    let auth_path = find_file(target, ConfigFile::Auth)?;
+    check_permissions(&auth_path)?;
+    let contents = fs::read_to_string(&auth_path).map_err(Error::ConfigRead)?;
+    let auth = parse_auth(&auth_path, &contents)?;
+    Ok((auth_path, auth))
+}
+
+/// Find and parse both the global configuration file and the authentication data one.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+/// [`Error::ConfigFileMissing`] if any of the config files could not be found and
+/// the `SPVE_URL`, `SPVE_TOKEN_ID`, and `SPVE_TOKEN` variables are not all set.
+/// [`Error::ConfigParse`] if any of the config files could not be parsed.
+/// [`Error::ConfigSecret`] if the authentication file is readable by other accounts.
+pub fn read_files(target: &Target) -> Result<Files> {
+    let (global_path, global) = match env_optional(read_global(target))? {
+        Some((path, global)) => (Some(path), global.spve),
+        None => (
+            None,
+            SpveSnippet {
+                defaults: SpveDefaults {
+                    cluster: ENV_ONLY_CLUSTER.to_owned(),
+                },
+                clusters: BTreeMap::new(),
+            },
+        ),
+    };
+    let (auth_path, auth) = match env_optional(read_auth(target))? {
+        Some((path, auth)) => (Some(path), auth),
+        None => (
+            None,
+            AuthSnippet {
+                clusters: HashMap::new(),
+                storpool: HashMap::new(),
+            },
+        ),
+    };
+    Ok(Files {
+        global_path,
+        global,
+        auth_path,
+        auth,
+    })
+}
+
+/// Allow a configuration file to be missing if the environment describes the whole connection.
+fn env_optional<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::ConfigFileMissing(..)) if env_connection() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Describe where a cluster's settings should have been found.
+fn describe_source(path: Option<&Path>, which: ConfigFile) -> String {
+    match path {
+        Some(path) => format!("the {path} config file", path = path.display()),
+        None => format!(
+            "the environment, no {relpath} file",
+            relpath = which.relpath()
+        ),
+    }
+}
+
+/// Get the names of all the clusters defined in the spve configuration file.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+/// [`Error::ConfigFileMissing`] if the config file could not be found.
+/// [`Error::ConfigParse`] if the config file could not be parsed.
+pub fn cluster_names(target: &Target) -> Result<Vec<String>> {
+    let (_, global) = read_global(target)?;
+    let mut names: Vec<String> = global.spve.clusters.into_keys().collect();
+    names.sort_unstable();
+    Ok(names)
+}
+
+/// Find the spve configuration files, parse them, apply the environment overrides.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if something goes wrong during initialization.
+/// [`Error::ConfigFileMissing`] if any of the config files could not be found.
+/// [`Error::ConfigSecret`] if the authentication file is readable by other accounts.
+pub fn parse(target: &Target) -> Result<Config> {
+    let mut files = read_files(target)?;
+    let cl_name = files.apply_env(target.cluster.as_deref());
+    let Files {
+        global_path,
+        global,
+        auth_path,
+        auth,
+    } = files;
+
+    let cl_global = (*(global.clusters.get(&cl_name).ok_or_else(|| {
+        Error::ConfigParse(anyhow!(
+            "No {cl_name} in {source}",
+            source = describe_source(global_path.as_deref(), ConfigFile::Global)
+        ))
+    })?))
+    .clone();
+
+    let cl_auth = (*(auth.clusters.get(&cl_name).ok_or_else(|| {
+        Error::ConfigParse(anyhow!(
+            "No {cl_name} in {source}",
+            source = describe_source(auth_path.as_deref(), ConfigFile::Auth)
+        ))
+    })?))
+    .clone();
+
+    Ok(Config {
+        auth,
+        cluster: Cluster {
+            name: cl_name,
+            auth: cl_auth,
+            spve: cl_global,
+        },
+    })
+}
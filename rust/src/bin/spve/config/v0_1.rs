@@ -0,0 +1,145 @@
+//! The layout of the version 0.1 `spve.toml` global configuration file.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use toml_edit::{value, Array, Decor, Document, Item, Table, Value};
+
+use super::{
+    ApiMode, ChecksSnippet, SpveDefaults, SpveStorPoolSnippet, TimeoutsSnippet, TlsSnippet,
+    FORMAT_MINOR_LATEST,
+};
+use crate::defs::{Error, Result};
+
+/// General configuration settings for a Proxmox VE cluster managed by the spve tool.
+#[derive(Debug, Deserialize)]
+pub struct SpveClusterSnippet {
+    /// How to connect to the cluster's API.
+    pub api_mode: ApiMode,
+
+    /// Where the cluster's API is located.
+    pub url: String,
+
+    /// How to connect to the `StorPool` API, if at all.
+    pub storpool: Option<SpveStorPoolSnippet>,
+}
+
+/// General configuration settings for the spve tool.
+#[derive(Debug, Deserialize)]
+pub struct SpveSnippet {
+    /// Some default values.
+    pub defaults: SpveDefaults,
+
+    /// Per-cluster configuration settings.
+    pub clusters: BTreeMap<String, SpveClusterSnippet>,
+}
+
+/// The format of the `spve.toml` global configuration file, version 0.1.
+#[derive(Debug, Deserialize)]
+pub struct GlobalSnippet {
+    /// General settings for the spve tool.
+    pub spve: SpveSnippet,
+}
+
+impl From<SpveClusterSnippet> for super::SpveClusterSnippet {
+    /// Version 0.1 had a single endpoint and never verified the TLS certificates.
+    fn from(old: SpveClusterSnippet) -> Self {
+        Self {
+            api_mode: old.api_mode,
+            endpoints: vec![old.url],
+            tls: TlsSnippet {
+                verify: false,
+                ca_file: None,
+            },
+            timeouts: TimeoutsSnippet::default(),
+            checks: ChecksSnippet::default(),
+            storpool: old.storpool,
+        }
+    }
+}
+
+impl From<GlobalSnippet> for super::GlobalSnippet {
+    fn from(old: GlobalSnippet) -> Self {
+        Self {
+            spve: super::SpveSnippet {
+                defaults: old.spve.defaults,
+                clusters: old
+                    .spve
+                    .clusters
+                    .into_iter()
+                    .map(|(name, cl_cfg)| (name, cl_cfg.into()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Replace a value, keeping the comments and whitespace around it.
+fn replace_decorated(item: &mut Item, mut new: Value) {
+    if let Some(old) = item.as_value() {
+        *new.decor_mut() = old.decor().clone();
+    }
+    *item = Item::Value(new);
+}
+
+/// Convert a version 0.0 or 0.1 `spve.toml` file to the latest layout, keeping the comments.
+///
+/// Only the settings that changed are rewritten: the single `url` of each cluster becomes
+/// the `endpoints` list, and `tls.verify` is explicitly disabled as it was in version 0.1.
+///
+/// # Errors
+///
+/// [`Error::ConfigParse`] if the file is not valid TOML or does not have the expected layout.
+pub fn upgrade(contents: &str) -> Result<String> {
+    let mut doc: Document = contents
+        .parse()
+        .context("Could not parse the file as TOML")
+        .map_err(Error::ConfigParse)?;
+
+    let clusters = doc
+        .get_mut("spve")
+        .and_then(|spve| spve.get_mut("clusters"))
+        .and_then(Item::as_table_like_mut)
+        .ok_or_else(|| Error::ConfigParse(anyhow!("No spve.clusters table")))?;
+    for (name, cl_item) in clusters.iter_mut() {
+        let inline = cl_item.is_inline_table();
+        let cl_cfg = cl_item
+            .as_table_like_mut()
+            .ok_or_else(|| Error::ConfigParse(anyhow!("spve.clusters.{name} is not a table")))?;
+
+        let key_decor = cl_cfg.key_decor("url").cloned();
+        let mut url = cl_cfg
+            .remove("url")
+            .and_then(|item| item.into_value().ok())
+            .ok_or_else(|| Error::ConfigParse(anyhow!("No spve.clusters.{name}.url value")))?;
+        let decor = url.decor().clone();
+        *url.decor_mut() = Decor::default();
+        let mut endpoints = Array::new();
+        endpoints.push_formatted(url);
+        *endpoints.decor_mut() = decor;
+        cl_cfg.insert("endpoints", Item::Value(Value::Array(endpoints)));
+        if let (Some(old), Some(new)) = (key_decor, cl_cfg.key_decor_mut("endpoints")) {
+            *new = old;
+        }
+
+        let mut tls = Table::new();
+        tls.insert("verify", value(false));
+        let tls = if inline {
+            Item::Value(Value::InlineTable(tls.into_inline_table()))
+        } else {
+            Item::Table(tls)
+        };
+        cl_cfg.insert("tls", tls);
+    }
+
+    let minor = doc
+        .get_mut("format")
+        .and_then(|format| format.get_mut("version"))
+        .and_then(|version| version.get_mut("minor"))
+        .ok_or_else(|| Error::ConfigParse(anyhow!("No format.version.minor value")))?;
+    replace_decorated(minor, Value::from(i64::from(FORMAT_MINOR_LATEST)));
+    Ok(doc.to_string())
+}
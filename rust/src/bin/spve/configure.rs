@@ -0,0 +1,856 @@
+//! Examine or modify the spve configuration files.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error as AnyError};
+use serde::Serialize;
+
+use crate::cli::{ConfigFormat, TokenSource};
+use crate::config::{
+    self, v0_1, ApiMode, AuthCluster, AuthSnippet, AuthToken, ChecksSnippet, Cluster, ConfigFile,
+    Files, GlobalSnippet, SecretSources, SpveClusterSnippet, Target, TimeoutsSnippet, TlsSnippet,
+};
+use crate::defs::{Error, Result};
+use crate::output;
+use crate::MainExit;
+
+/// The header of a configuration file with the latest format version.
+const FORMAT_HEADER: &str = "[format.version]
+major = 0
+minor = 2
+";
+
+/// The commented template for the global configuration file.
+const GLOBAL_TEMPLATE: &str = r#"# The spve configuration file: which Proxmox VE clusters to manage.
+[format.version]
+major = 0
+minor = 2
+
+[spve.defaults]
+# The cluster to manage if neither "--cluster" nor SPVE_CLUSTER is specified.
+cluster = "pve"
+
+# A Proxmox VE cluster; add more [spve.clusters.NAME] sections as needed.
+[spve.clusters.pve]
+# How to connect to the cluster's API; only "https" is supported for the present.
+api_mode = "https"
+
+# Where the cluster's API is located; the rest are tried if the first one is unreachable.
+endpoints = ["https://pve1.example.com:8006", "https://pve2.example.com:8006"]
+
+# How to verify the TLS certificate of the cluster's API endpoints.
+[spve.clusters.pve.tls]
+# Set to false to accept any certificate, e.g. the self-signed one generated by Proxmox VE.
+verify = true
+# An additional CA certificate to trust, e.g. the Proxmox VE cluster's own one.
+#ca_file = "/etc/spve/pve-root-ca.pem"
+
+# How long to wait for the cluster's API to respond, in seconds.
+[spve.clusters.pve.timeouts]
+#connect = 10
+#request = 60
+
+# What to do with the problems found by the check commands: "fail", "warn", or "skip".
+[spve.clusters.pve.checks]
+#attachments = "fail"
+#evacuate = "fail"
+#ha = "fail"
+#snapshots = "fail"
+#storage = "fail"
+#vms = "fail"
+
+# How to reach the StorPool API for the storage backing the cluster, if at all.
+#[spve.clusters.pve.storpool]
+#url = "http://10.1.2.3:81/ctrl/1.0"
+
+# The StorPool client ID of each Proxmox VE node.
+#[spve.clusters.pve.storpool.nodes]
+#pve1 = 1
+#pve2 = 2
+"#;
+
+/// The commented template for the authentication data file.
+const AUTH_TEMPLATE: &str = r#"# The spve authentication data file; keep it readable only by its owner.
+[format.version]
+major = 0
+minor = 2
+
+# The API token for a Proxmox VE cluster defined in the spve.toml file.
+[clusters.pve]
+auth_type = "token"
+id = "root@pam!spve"
+
+# Exactly one of the following settings specifies where to obtain the token value from.
+value = "00000000-0000-0000-0000-000000000000"
+#value_file = "/etc/spve/pve.token"
+#value_env = "SPVE_PVE_TOKEN"
+#value_command = ["pass", "show", "pve/spve"]
+
+# The StorPool API token for the cluster, if the StorPool API is configured.
+# As above, exactly one of token, token_file, token_env, or token_command.
+#[storpool.pve]
+#token = "1234567890123456789"
+#token_file = "/etc/spve/storpool.token"
+"#;
+
+/// The redacted `StorPool` API settings for a cluster.
+#[derive(Debug, Serialize)]
+struct ShownStorPool {
+    /// Where the `StorPool` API is located.
+    url: String,
+
+    /// Where the `StorPool` API token is obtained from, if configured.
+    token: Option<String>,
+
+    /// The `StorPool` client ID of each Proxmox VE node.
+    nodes: BTreeMap<String, u32>,
+}
+
+/// The redacted settings for a single cluster.
+#[derive(Debug, Serialize)]
+struct ShownCluster {
+    /// How to connect to the cluster's API.
+    api_mode: ApiMode,
+
+    /// Where the cluster's API is located.
+    endpoints: Vec<String>,
+
+    /// The ID of the API token, if configured.
+    token_id: Option<String>,
+
+    /// Where the API token value is obtained from.
+    token: Option<String>,
+
+    /// How to verify the TLS certificate of the cluster's API endpoints.
+    tls: TlsSnippet,
+
+    /// How long to wait for the cluster's API to respond.
+    timeouts: TimeoutsSnippet,
+
+    /// What to do with the problems found by the check commands.
+    checks: ChecksSnippet,
+
+    /// How to connect to the `StorPool` API, if at all.
+    storpool: Option<ShownStorPool>,
+}
+
+/// The redacted effective configuration.
+#[derive(Debug, Serialize)]
+struct ShownConfig {
+    /// The path to the global configuration file, if there is one.
+    config_file: Option<String>,
+
+    /// The path to the authentication data file, if there is one.
+    auth_file: Option<String>,
+
+    /// The cluster to manage if none is specified.
+    default_cluster: String,
+
+    /// The settings for each cluster.
+    clusters: BTreeMap<String, ShownCluster>,
+}
+
+/// Display the effective configuration with the secrets redacted.
+///
+/// The `SPVE_*` environment variable overrides are applied, too.
+///
+/// # Errors
+///
+/// Propagate errors from [`config::read_files`].
+/// [`Error::Internal`] if the configuration could not be serialized.
+#[allow(clippy::unused_async)]
+pub async fn cmd_config_show(cfg_target: Target, format: ConfigFormat) -> Result<MainExit> {
+    let mut files = config::read_files(&cfg_target)?;
+    files.apply_env(cfg_target.cluster.as_deref());
+    let Files {
+        global_path,
+        global,
+        auth_path,
+        auth,
+    } = files;
+    let clusters = global
+        .clusters
+        .into_iter()
+        .map(|(name, cl_cfg)| {
+            let token = auth.clusters.get(&name).map(|cl_auth| match *cl_auth {
+                AuthCluster::Token(ref token) => token,
+            });
+            let storpool = cl_cfg.storpool.map(|sp_cfg| ShownStorPool {
+                url: sp_cfg.url,
+                token: auth
+                    .storpool
+                    .get(&name)
+                    .map(|sp_auth| sp_auth.sources().describe()),
+                nodes: sp_cfg.nodes.into_iter().collect(),
+            });
+            let shown = ShownCluster {
+                api_mode: cl_cfg.api_mode,
+                endpoints: cl_cfg.endpoints,
+                token_id: token.map(|tok| tok.id.clone()),
+                token: token.map(|tok| tok.sources().describe()),
+                tls: cl_cfg.tls,
+                timeouts: cl_cfg.timeouts,
+                checks: cl_cfg.checks,
+                storpool,
+            };
+            (name, shown)
+        })
+        .collect();
+    let shown = ShownConfig {
+        config_file: global_path.map(|path| path.display().to_string()),
+        auth_file: auth_path.map(|path| path.display().to_string()),
+        default_cluster: global.defaults.cluster,
+        clusters,
+    };
+
+    match format {
+        ConfigFormat::Toml => {
+            let text = toml::to_string(&shown).map_err(|err| {
+                Error::Internal(format!("Could not serialize the configuration: {err}"))
+            })?;
+            output::emit(text.trim_end());
+        }
+        ConfigFormat::Json => output::print_json(&shown)?,
+    }
+    Ok(MainExit::Ok)
+}
+
+/// Report a problem found in a configuration file, pointing at the line and column if known.
+fn report(path: &Path, pos: Option<(usize, usize)>, msg: &str) {
+    match pos {
+        Some((line, col)) => output::emit(&format!(
+            "{path}:{line}:{col}: {msg}",
+            path = path.display(),
+            line = line + 1,
+            col = col + 1
+        )),
+        None => output::emit(&format!("{path}: {msg}", path = path.display())),
+    }
+}
+
+/// Report an error that occurred while parsing a configuration file.
+///
+/// If the TOML parser said where the error is, point at it in the standard
+/// `file:line:column` form instead of at the end of the message.
+fn report_parse_error(path: &Path, err: &AnyError) {
+    match err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<toml::de::Error>())
+    {
+        Some(toml_err) => {
+            let msg = toml_err.to_string();
+            let msg = msg
+                .rsplit_once(" at line ")
+                .map_or(msg.as_str(), |pair| pair.0);
+            report(path, toml_err.line_col(), msg);
+        }
+        None => report(path, None, &format!("{err:#}")),
+    }
+}
+
+/// Find the line of a `[table]` header in a configuration file.
+fn find_table(contents: &str, table: &str) -> Option<(usize, usize)> {
+    let header = format!("[{table}]");
+    contents
+        .lines()
+        .position(|line| line.trim() == header)
+        .map(|idx| (idx, 0))
+}
+
+/// Check that exactly one source of a secret value is specified and that it is safe to use.
+fn check_sources(sources: &SecretSources<'_>, what: &str) -> Option<String> {
+    let count = sources.names().len();
+    if count != 1 {
+        Some(format!(
+            "{exactly} for {what}, got {count}",
+            exactly = sources.exactly_one()
+        ))
+    } else if sources.command.map_or(false, <[String]>::is_empty) {
+        Some(format!("Empty {key}_command for {what}", key = sources.key))
+    } else if let Some(Err(err)) = sources.file.map(config::check_permissions) {
+        Some(format!(
+            "{key}_file for {what}: {err:#}",
+            key = sources.key,
+            err = AnyError::from(err)
+        ))
+    } else {
+        None
+    }
+}
+
+/// Read and parse a configuration file, reporting any problems.
+fn parse_file<T, F>(path: &Path, parser: F) -> Option<(String, T)>
+where
+    F: FnOnce(&Path, &str) -> Result<T>,
+{
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            report(path, None, &format!("Could not read the file: {err}"));
+            return None;
+        }
+    };
+    let parsed = toml::from_str::<toml::Value>(&contents)
+        .map_err(AnyError::from)
+        .and_then(|_| parser(path, &contents).map_err(AnyError::from));
+    match parsed {
+        Ok(value) => Some((contents, value)),
+        Err(err) => {
+            report_parse_error(path, &err);
+            None
+        }
+    }
+}
+
+/// Check that the authentication data matches the clusters defined in the global configuration.
+fn validate_auth(
+    global_path: &Path,
+    global: &GlobalSnippet,
+    auth_path: &Path,
+    auth_text: &str,
+    auth: &AuthSnippet,
+) -> bool {
+    let mut problems = false;
+    let spve = &global.spve;
+
+    let mut names: Vec<&String> = auth.clusters.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        let cl_pos = find_table(auth_text, &format!("clusters.{name}"));
+        if !spve.clusters.contains_key(name) {
+            report(
+                auth_path,
+                cl_pos,
+                &format!(
+                    "Authentication data for the {name} cluster not defined in the {global} file",
+                    global = global_path.display()
+                ),
+            );
+            problems = true;
+        }
+        match auth.clusters[name] {
+            AuthCluster::Token(ref token) => {
+                if let Some(msg) = check_sources(&token.sources(), &format!("the {name} cluster")) {
+                    report(auth_path, cl_pos, &msg);
+                    problems = true;
+                }
+            }
+        }
+    }
+
+    let sp_clusters: HashSet<&String> = spve
+        .clusters
+        .iter()
+        .filter(|&(_, cl_cfg)| cl_cfg.storpool.is_some())
+        .map(|(name, _)| name)
+        .collect();
+    let mut names: Vec<&String> = auth.storpool.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        let sp_pos = find_table(auth_text, &format!("storpool.{name}"));
+        if !sp_clusters.contains(name) {
+            report(
+                auth_path,
+                sp_pos,
+                &format!(
+                    "A StorPool API token for the {name} cluster that has no StorPool API settings"
+                ),
+            );
+            problems = true;
+        }
+        if let Some(msg) = check_sources(
+            &auth.storpool[name].sources(),
+            &format!("the StorPool API of the {name} cluster"),
+        ) {
+            report(auth_path, sp_pos, &msg);
+            problems = true;
+        }
+    }
+    problems
+}
+
+/// Cross-check the global configuration against the authentication data.
+fn validate_files(
+    global_path: &Path,
+    global_text: &str,
+    global: &GlobalSnippet,
+    auth_path: &Path,
+    auth_text: &str,
+    auth: &AuthSnippet,
+) -> bool {
+    let mut problems = false;
+    let spve = &global.spve;
+
+    let default = &spve.defaults.cluster;
+    if !spve.clusters.contains_key(default) {
+        report(
+            global_path,
+            find_table(global_text, "spve.defaults"),
+            &format!("The default cluster {default} is not defined in the [spve.clusters] table"),
+        );
+        problems = true;
+    }
+
+    let mut names: Vec<&String> = spve.clusters.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        let cl_cfg = &spve.clusters[name];
+        let cl_pos = find_table(global_text, &format!("spve.clusters.{name}"));
+        if cl_cfg.endpoints.is_empty() {
+            report(
+                global_path,
+                cl_pos,
+                &format!("No API endpoints for the {name} cluster"),
+            );
+            problems = true;
+        }
+        for url in cl_cfg
+            .endpoints
+            .iter()
+            .filter(|url| !(url.starts_with("https://") || url.starts_with("http://")))
+        {
+            report(
+                global_path,
+                cl_pos,
+                &format!("Invalid API URL '{url}' for the {name} cluster"),
+            );
+            problems = true;
+        }
+        if let Some(ref ca_file) = cl_cfg.tls.ca_file {
+            if !ca_file.is_file() {
+                report(
+                    global_path,
+                    find_table(global_text, &format!("spve.clusters.{name}.tls")),
+                    &format!(
+                        "The {ca_file} CA certificate file for the {name} cluster does not exist",
+                        ca_file = ca_file.display()
+                    ),
+                );
+                problems = true;
+            }
+        }
+        if !auth.clusters.contains_key(name) {
+            report(
+                auth_path,
+                None,
+                &format!("No authentication data for the {name} cluster"),
+            );
+            problems = true;
+        }
+        if cl_cfg.storpool.is_some() && !auth.storpool.contains_key(name) {
+            report(
+                auth_path,
+                None,
+                &format!("No StorPool API token for the {name} cluster"),
+            );
+            problems = true;
+        }
+    }
+
+    if validate_auth(global_path, global, auth_path, auth_text, auth) {
+        problems = true;
+    }
+
+    if let Err(err) = config::check_permissions(auth_path) {
+        report(
+            auth_path,
+            None,
+            &format!("{err:#}", err = AnyError::from(err)),
+        );
+        problems = true;
+    }
+
+    problems
+}
+
+/// Check the configuration files for errors.
+///
+/// # Errors
+///
+/// [`Error::ConfigEnv`] if the configuration directories could not be examined.
+#[allow(clippy::unused_async)]
+pub async fn cmd_config_validate(cfg_target: Target) -> Result<MainExit> {
+    let mut problems = false;
+    let global_path = config::find_file(&cfg_target, ConfigFile::Global);
+    let auth_path = config::find_file(&cfg_target, ConfigFile::Auth);
+    for path_res in [&global_path, &auth_path] {
+        if let Err(ref err) = *path_res {
+            output::emit(&err.to_string());
+            problems = true;
+        }
+    }
+    if let (Ok(global_path), Ok(auth_path)) = (global_path, auth_path) {
+        let global = parse_file(&global_path, config::parse_global);
+        let auth = parse_file(&auth_path, config::parse_auth);
+        match (global, auth) {
+            (Some((global_text, global)), Some((auth_text, auth))) => {
+                if let Ok((major, minor)) = config::get_format_version(&global_path, &global_text) {
+                    if minor < config::FORMAT_MINOR_LATEST {
+                        output::emit(&format!(
+                            "{path}: format version {major}.{minor}; run `spve config migrate` to upgrade it",
+                            path = global_path.display()
+                        ));
+                    }
+                }
+                if validate_files(
+                    &global_path,
+                    &global_text,
+                    &global,
+                    &auth_path,
+                    &auth_text,
+                    &auth,
+                ) {
+                    problems = true;
+                } else {
+                    output::emit(&format!(
+                        "The {global} and {auth} files are valid",
+                        global = global_path.display(),
+                        auth = auth_path.display()
+                    ));
+                }
+            }
+            _ => problems = true,
+        }
+    }
+    Ok(MainExit::from_problems(problems))
+}
+
+/// Create a configuration file, making sure that only its owner may read it if requested.
+///
+/// # Errors
+///
+/// [`Error::ConfigWrite`] if the file could not be written.
+fn write_file(path: &Path, contents: &str, private: bool, append: bool) -> Result<()> {
+    let mut opts = OpenOptions::new();
+    opts.create(true);
+    if append {
+        opts.append(true);
+    } else {
+        opts.write(true).truncate(true);
+    }
+    #[cfg(unix)]
+    if private {
+        opts.mode(0o600);
+    }
+    let mut file = opts
+        .open(path)
+        .with_context(|| format!("Could not open the {path} file", path = path.display()))
+        .map_err(Error::ConfigWrite)?;
+    #[cfg(unix)]
+    if private {
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| {
+                format!(
+                    "Could not set the permissions of the {path} file",
+                    path = path.display()
+                )
+            })
+            .map_err(Error::ConfigWrite)?;
+    }
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Could not write to the {path} file", path = path.display()))
+        .map_err(Error::ConfigWrite)
+}
+
+/// Write commented configuration file templates.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if a configuration file already exists and `force` was not specified.
+/// [`Error::ConfigWrite`] if the files could not be written.
+#[allow(clippy::unused_async)]
+pub async fn cmd_config_init(cfg_target: Target, force: bool) -> Result<MainExit> {
+    let global_path = config::place_file(&cfg_target, ConfigFile::Global)?;
+    let auth_path = config::place_file(&cfg_target, ConfigFile::Auth)?;
+    if !force {
+        if let Some(path) = [&global_path, &auth_path]
+            .into_iter()
+            .find(|path| path.exists())
+        {
+            return Err(Error::Invoke(anyhow!(
+                "The {path} file already exists; use --force to overwrite it",
+                path = path.display()
+            )));
+        }
+    }
+
+    write_file(&global_path, GLOBAL_TEMPLATE, false, false)?;
+    write_file(&auth_path, AUTH_TEMPLATE, true, false)?;
+    output::emit(&format!(
+        "Wrote the {global} and {auth} templates; edit them and run `spve config validate`",
+        global = global_path.display(),
+        auth = auth_path.display()
+    ));
+    Ok(MainExit::Ok)
+}
+
+/// Convert the global configuration file to the latest format version.
+///
+/// Only the changed settings are rewritten, the comments and the rest of the formatting
+/// are kept. The original file is kept with a `.0.1.bak` suffix appended to its name.
+///
+/// # Errors
+///
+/// [`Error::ConfigFileMissing`] if the configuration file could not be found.
+/// [`Error::ConfigParse`] if the configuration file could not be parsed.
+/// [`Error::ConfigWrite`] if the converted file could not be written.
+#[allow(clippy::unused_async)]
+pub async fn cmd_config_migrate(cfg_target: Target, dry_run: bool) -> Result<MainExit> {
+    let path = config::find_file(&cfg_target, ConfigFile::Global)?;
+    let contents = fs::read_to_string(&path).map_err(Error::ConfigRead)?;
+    let (major, minor) = config::get_format_version(&path, &contents)?;
+    if minor == config::FORMAT_MINOR_LATEST {
+        output::emit(&format!(
+            "The {path} file already uses format version {major}.{minor}",
+            path = path.display()
+        ));
+        return Ok(MainExit::Ok);
+    }
+
+    config::parse_global(&path, &contents)?;
+    let converted = v0_1::upgrade(&contents)
+        .and_then(|converted| {
+            config::parse_global(&path, &converted)?;
+            Ok(converted)
+        })
+        .map_err(|err| {
+            Error::ConfigParse(anyhow!(
+                "Could not convert the {path} file: {err:#}",
+                path = path.display(),
+                err = AnyError::from(err)
+            ))
+        })?;
+    if dry_run {
+        output::emit(converted.trim_end());
+        return Ok(MainExit::Ok);
+    }
+
+    let mut backup = path.clone().into_os_string();
+    backup.push(format!(".{major}.{minor}.bak"));
+    let backup = PathBuf::from(backup);
+    fs::copy(&path, &backup)
+        .with_context(|| {
+            format!(
+                "Could not back up the {path} file to {backup}",
+                path = path.display(),
+                backup = backup.display()
+            )
+        })
+        .map_err(Error::ConfigWrite)?;
+    write_file(&path, &converted, false, false)?;
+    output::emit(&format!(
+        "Converted the {path} file to format version 0.{latest}, the original is in {backup}",
+        path = path.display(),
+        latest = config::FORMAT_MINOR_LATEST,
+        backup = backup.display()
+    ));
+    Ok(MainExit::Ok)
+}
+
+/// Format a string as a TOML value.
+fn toml_str(value: &str) -> String {
+    toml::Value::String(value.to_owned()).to_string()
+}
+
+/// Find the existing configuration files, making sure the cluster is not already defined in them.
+fn find_existing(cfg_target: &Target, name: &str) -> Result<(Option<PathBuf>, Option<PathBuf>)> {
+    let global_path = match config::find_file(cfg_target, ConfigFile::Global) {
+        Ok(path) => {
+            let contents = fs::read_to_string(&path).map_err(Error::ConfigRead)?;
+            if config::get_format_version(&path, &contents)? != (0, config::FORMAT_MINOR_LATEST) {
+                return Err(Error::Invoke(anyhow!(
+                    "The {path} file uses an older format version; run `spve config migrate` first",
+                    path = path.display()
+                )));
+            }
+            let global = config::parse_global(&path, &contents)?;
+            if global.spve.clusters.contains_key(name) {
+                return Err(Error::Invoke(anyhow!(
+                    "The {name} cluster is already defined in the {path} file",
+                    path = path.display()
+                )));
+            }
+            Some(path)
+        }
+        Err(Error::ConfigFileMissing(..)) => None,
+        Err(err) => return Err(err),
+    };
+    let auth_path = match config::find_file(cfg_target, ConfigFile::Auth) {
+        Ok(path) => {
+            let (_, auth) = config::read_auth(cfg_target)?;
+            if auth.clusters.contains_key(name) {
+                return Err(Error::Invoke(anyhow!(
+                    "The {name} cluster is already defined in the {path} file",
+                    path = path.display()
+                )));
+            }
+            Some(path)
+        }
+        Err(Error::ConfigFileMissing(..)) => None,
+        Err(err) => return Err(err),
+    };
+
+    Ok((global_path, auth_path))
+}
+
+/// Build the authentication token settings and the TOML key and value for the token source.
+fn build_token(token_id: String, token: TokenSource) -> Result<(AuthToken, &'static str, String)> {
+    let mut auth_token = AuthToken {
+        id: token_id,
+        value: None,
+        value_file: None,
+        value_env: None,
+        value_command: None,
+    };
+    let (source_key, source_value) = match token {
+        TokenSource::Stdin => {
+            let mut line = String::new();
+            io::stdin()
+                .read_line(&mut line)
+                .context("Could not read the token value from the standard input")
+                .map_err(Error::Invoke)?;
+            let value = line.trim().to_owned();
+            let res = ("value", toml_str(&value));
+            auth_token.value = Some(value);
+            res
+        }
+        TokenSource::File(path) => {
+            let res = ("value_file", toml_str(&path.display().to_string()));
+            auth_token.value_file = Some(path);
+            res
+        }
+        TokenSource::Env(var) => {
+            let res = ("value_env", toml_str(&var));
+            auth_token.value_env = Some(var);
+            res
+        }
+        TokenSource::Command(words) => {
+            let res = (
+                "value_command",
+                toml::Value::Array(
+                    words
+                        .iter()
+                        .map(|word| toml::Value::String(word.clone()))
+                        .collect(),
+                )
+                .to_string(),
+            );
+            auth_token.value_command = Some(words);
+            res
+        }
+    };
+    Ok((auth_token, source_key, source_value))
+}
+
+/// Add a Proxmox VE cluster to the configuration files, testing the connection to it first.
+///
+/// # Errors
+///
+/// [`Error::Invoke`] if the cluster name is invalid or the cluster is already defined.
+/// [`Error::Api`] if the connection test failed.
+/// [`Error::ConfigWrite`] if the configuration files could not be updated.
+pub async fn cmd_config_add_cluster(
+    cfg_target: Target,
+    name: String,
+    urls: Vec<String>,
+    token_id: String,
+    token: TokenSource,
+    insecure: bool,
+    check: bool,
+) -> Result<MainExit> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|chr| chr.is_ascii_alphanumeric() || chr == '-' || chr == '_')
+    {
+        return Err(Error::Invoke(anyhow!(
+            "Invalid cluster name '{name}'; only letters, digits, '-', and '_' are allowed"
+        )));
+    }
+
+    let (global_path, auth_path) = find_existing(&cfg_target, &name)?;
+    let (auth_token, source_key, source_value) = build_token(token_id, token)?;
+    let cluster = Cluster {
+        name: name.clone(),
+        auth: AuthCluster::Token(auth_token.clone()),
+        spve: SpveClusterSnippet {
+            api_mode: ApiMode::Https,
+            endpoints: urls.clone(),
+            tls: TlsSnippet {
+                verify: !insecure,
+                ca_file: None,
+            },
+            timeouts: TimeoutsSnippet::default(),
+            checks: ChecksSnippet::default(),
+            storpool: None,
+        },
+    };
+
+    if check {
+        let api = cluster.get_proxmox_api()?;
+        let nodes = api.get(api.path().nodes()).await.map_err(Error::Api)?;
+        output::emit(&format!(
+            "Connected to the {name} cluster via {endpoints}: {count} node(s)",
+            endpoints = cluster.spve.endpoints.join(", "),
+            count = nodes.len()
+        ));
+    }
+
+    let tls_section = if insecure {
+        format!("\n[spve.clusters.{name}.tls]\nverify = false\n")
+    } else {
+        String::new()
+    };
+    let global_section = format!(
+        "\n[spve.clusters.{name}]\napi_mode = \"https\"\nendpoints = {endpoints}\n{tls_section}",
+        endpoints = toml::Value::Array(urls.into_iter().map(toml::Value::String).collect())
+    );
+    let global_path = if let Some(path) = global_path {
+        write_file(&path, &global_section, false, true)?;
+        path
+    } else {
+        let path = config::place_file(&cfg_target, ConfigFile::Global)?;
+        write_file(
+            &path,
+            &format!(
+                "{FORMAT_HEADER}\n[spve.defaults]\ncluster = {name_str}\n{global_section}",
+                name_str = toml_str(&name)
+            ),
+            false,
+            false,
+        )?;
+        path
+    };
+
+    let auth_section = format!(
+        "\n[clusters.{name}]\nauth_type = \"token\"\nid = {id}\n{source_key} = {source_value}\n",
+        id = toml_str(&auth_token.id)
+    );
+    let auth_path = if let Some(path) = auth_path {
+        write_file(&path, &auth_section, true, true)?;
+        path
+    } else {
+        let path = config::place_file(&cfg_target, ConfigFile::Auth)?;
+        write_file(
+            &path,
+            &format!("{FORMAT_HEADER}{auth_section}"),
+            true,
+            false,
+        )?;
+        path
+    };
+
+    output::emit(&format!(
+        "Added the {name} cluster to the {global} and {auth} files",
+        global = global_path.display(),
+        auth = auth_path.display()
+    ));
+    Ok(MainExit::Ok)
+}
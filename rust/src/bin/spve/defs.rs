@@ -22,9 +22,9 @@ pub enum Error {
     #[error("Could not examine the spve execution environment")]
     ConfigEnv(#[source] AnyError),
 
-    /// A required configuration file was missing.
-    #[error("Could not find the {0} spve configuration file")]
-    ConfigFileMissing(String),
+    /// A required configuration file was missing; the second field lists the paths tried.
+    #[error("Could not find the {0} spve configuration file; {1}")]
+    ConfigFileMissing(String, String),
 
     /// Could not parse a configuration file's contents.
     #[error("Could not parse the spve configuration")]
@@ -34,10 +34,38 @@ pub enum Error {
     #[error("Could not read a configuration file")]
     ConfigRead(#[source] IoError),
 
+    /// Could not obtain an authentication secret.
+    #[error("Could not obtain an spve authentication secret")]
+    ConfigSecret(#[source] AnyError),
+
+    /// Could not write a configuration file.
+    #[error("Could not write an spve configuration file")]
+    ConfigWrite(#[source] AnyError),
+
+    /// The Proxmox VE cluster does not have quorum, so nothing should be modified.
+    #[error("The {0} Proxmox VE cluster is not quorate")]
+    NoQuorum(String),
+
+    /// The Proxmox VE cluster runs a version that does not support the command.
+    #[error("The {0} Proxmox VE cluster is too old")]
+    TooOld(String, #[source] PmError),
+
     /// Something went really, really wrong...
     #[error("spve internal error: {0}")]
     Internal(String),
 
+    /// Could not read or write the files in the state directory.
+    #[error("Could not access the spve state directory")]
+    StateDir(#[source] AnyError),
+
+    /// A request to the `StorPool` API failed.
+    #[error("StorPool API request failed")]
+    StorPool(#[source] AnyError),
+
+    /// The `StorPool` API access was not configured for a cluster.
+    #[error("No StorPool API settings for the {0} cluster")]
+    StorPoolNotConfigured(String),
+
     /// The spve tool was invoked incorrectly.
     #[error("spve invocation error")]
     Invoke(#[source] AnyError),
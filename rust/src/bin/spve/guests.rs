@@ -0,0 +1,185 @@
+//! Find the guests selected by the command-line options.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::HashMap;
+
+use tracing::debug;
+
+use proxmoxy::types::{NodeStatus, PoolMember, VmSummary};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::defs::{Error, Result};
+
+/// A selected VM along with the node that it is on.
+#[derive(Debug)]
+pub struct SelectedVm {
+    /// The name of the node that the VM is on.
+    pub node: String,
+
+    /// The resource pool that the VM belongs to, if any and if it was looked up.
+    pub pool: Option<String>,
+
+    /// The summary information about the VM.
+    pub vm: VmSummary,
+}
+
+impl SelectedVm {
+    /// The VM's tags in a predictable order.
+    pub fn sorted_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self.vm.tags().iter().cloned().collect();
+        tags.sort_unstable();
+        tags
+    }
+
+    /// Describe the VM along with its resource pool and tags for diagnostic messages.
+    pub fn describe(&self) -> String {
+        let mut owner = Vec::new();
+        if let Some(pool) = self.pool.as_ref() {
+            owner.push(format!("pool {pool}"));
+        }
+        let tags = self.sorted_tags();
+        if !tags.is_empty() {
+            owner.push(format!("tags {tags}", tags = tags.join(",")));
+        }
+        let vmid = self.vm.vmid();
+        if owner.is_empty() {
+            format!("VM {vmid}")
+        } else {
+            format!("VM {vmid} ({owner})", owner = owner.join(", "))
+        }
+    }
+}
+
+/// The VMs that match the selection criteria.
+#[derive(Debug)]
+pub struct Selection {
+    /// The selected VMs, sorted by ID.
+    pub vms: Vec<SelectedVm>,
+
+    /// The nodes that could hold selected VMs, but were not online.
+    pub offline: Vec<String>,
+}
+
+/// Find out which resource pool each VM belongs to.
+///
+/// Only examine the specified pools if any, otherwise all of them.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE API requests failed.
+pub async fn vm_pools(api: &Proxmoxy, only: Option<&[String]>) -> Result<HashMap<u32, String>> {
+    let poolids: Vec<String> = match only {
+        Some(poolids) => poolids.to_vec(),
+        None => api
+            .get(api.path().pools())
+            .await
+            .map_err(Error::Api)?
+            .iter()
+            .map(|pool| pool.poolid().to_owned())
+            .collect(),
+    };
+    let mut res = HashMap::new();
+    for poolid in poolids {
+        let info = api
+            .get(api.path().pools().id(&poolid))
+            .await
+            .map_err(Error::Api)?;
+        for vmid in info
+            .members()
+            .iter()
+            .filter(|member| member.is_qemu())
+            .filter_map(PoolMember::vmid)
+        {
+            res.insert(vmid, poolid.clone());
+        }
+    }
+    debug!("Got the resource pools of {count} VM(s)", count = res.len());
+    Ok(res)
+}
+
+/// Find the VMs on the online nodes that match the selection criteria.
+///
+/// The resource pools are only looked up if the selection depends on them;
+/// the `pool` field of the selected VMs is not set otherwise.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE API requests failed.
+pub async fn select(api: &Proxmoxy, guests: &GuestSelector) -> Result<Selection> {
+    let pools = if guests.pools.is_empty() {
+        HashMap::new()
+    } else {
+        vm_pools(api, Some(&guests.pools)).await?
+    };
+    select_in(api, guests, &pools).await
+}
+
+/// Find the VMs that match the selection criteria along with the resource pools they belong to.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE API requests failed.
+pub async fn select_with_pools(api: &Proxmoxy, guests: &GuestSelector) -> Result<Selection> {
+    let pools = vm_pools(api, None).await?;
+    select_in(api, guests, &pools).await
+}
+
+/// Find the VMs on the online nodes that match the selection criteria.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the Proxmox VE API requests failed.
+async fn select_in(
+    api: &Proxmoxy,
+    guests: &GuestSelector,
+    pools: &HashMap<u32, String>,
+) -> Result<Selection> {
+    let mut res = Vec::new();
+    let mut offline = Vec::new();
+    for node in api.get(api.path().nodes()).await.map_err(Error::Api)? {
+        let name = node.node();
+        if !guests.matches_node(name) {
+            continue;
+        }
+        if node.status() != NodeStatus::Online {
+            debug!("Skipping the {name} node, not online");
+            offline.push(name.to_owned());
+            continue;
+        }
+
+        let path_vms = api.path().nodes().id(name).qemu();
+        for vm in api.get(path_vms.clone()).await.map_err(Error::Api)? {
+            let vmid = vm.vmid();
+            let pool = pools.get(&vmid);
+            if !guests.matches(&vm)
+                || !(guests.pools.is_empty()
+                    || pool.map_or(false, |pool| guests.pools.contains(pool)))
+            {
+                continue;
+            }
+            if !guests.storage.is_empty() {
+                let vmcfg = api
+                    .get(path_vms.clone().id(vmid).config())
+                    .await
+                    .map_err(Error::Api)?;
+                if !vmcfg
+                    .disks()
+                    .iter()
+                    .any(|disk| guests.storage.iter().any(|store| store == disk.storage()))
+                {
+                    continue;
+                }
+            }
+            res.push(SelectedVm {
+                node: name.to_owned(),
+                pool: pool.cloned(),
+                vm,
+            });
+        }
+    }
+    res.sort_by_key(|sel| sel.vm.vmid());
+    debug!("Selected {count} VM(s)", count = res.len());
+    Ok(Selection { vms: res, offline })
+}
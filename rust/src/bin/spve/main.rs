@@ -2,180 +2,352 @@
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
 
-use std::collections::HashMap;
-
-use anyhow::{Context, Result as AnyResult};
+use anyhow::{anyhow, Context, Result as AnyResult};
 use std::process::{ExitCode, Termination};
-use tracing::{debug, warn};
-
-use proxmoxy::types::{NodeStatus, Storage, VmDisk, VmDiskType};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tracing::{error, info, info_span, warn, Instrument};
 
+mod check;
 mod cli;
+mod compat;
 mod config;
+mod configure;
 mod defs;
+mod guests;
+mod migrate;
+mod output;
+mod quorum;
+mod report;
+mod snapshot;
+mod status;
+mod storpool;
+mod vm;
+mod watch;
+
+#[cfg(test)]
+mod tests;
 
-use crate::cli::Mode;
-use crate::defs::{Error, Result};
+use crate::cli::{Clusters, Invocation, Mode};
+use proxmoxy::Proxmoxy;
 
-/// The exit status of a main program's subcommand.
+use crate::config::{CheckPolicy, Config, Target};
+use crate::defs::Error;
+
+/// The exit status of a main program's subcommand, from the best to the worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum MainExit {
     /// Everything went fine.
     Ok,
 
     /// A 'check' subcommand found problems.
     CheckFailed,
+
+    /// Some of the requested operations could not be performed.
+    Failed,
+}
+
+impl MainExit {
+    /// Report a failure if a 'check' subcommand found any problems.
+    const fn from_problems(problems: bool) -> Self {
+        if problems {
+            Self::CheckFailed
+        } else {
+            Self::Ok
+        }
+    }
+
+    /// Report a failure if any of the requested operations failed.
+    const fn from_failures(failures: bool) -> Self {
+        if failures {
+            Self::Failed
+        } else {
+            Self::Ok
+        }
+    }
+
+    /// Combine the results of running a command against several clusters.
+    fn worst(self, other: Self) -> Self {
+        self.max(other)
+    }
 }
 
 impl Termination for MainExit {
     fn report(self) -> ExitCode {
         match self {
             Self::Ok => ExitCode::SUCCESS,
-            Self::CheckFailed => ExitCode::FAILURE,
+            Self::CheckFailed | Self::Failed => ExitCode::FAILURE,
         }
     }
 }
 
-/// Check the configuration of a single disk for a VM.
-fn check_vm_disk(disk_id: &str, disk: &VmDisk) -> bool {
-    let mut problems: bool = false;
-
-    #[allow(clippy::wildcard_enum_match_arm)]
-    match disk.disk_type() {
-        VmDiskType::Virtio => (),
-        other => {
-            warn!(
-                "Disk type '{other}' instead of 'virtio' for {disk_id}",
-                other = other.as_ref()
-            );
-            problems = true;
-        }
-    };
-
-    match disk.options().get("cache") {
-        None => (),
-        Some(value) if value == "none" => (),
-        Some(other) => {
-            warn!("Expected 'cache=none' for {disk_id}, got '{other}'");
-            problems = true;
-        }
-    };
+/// Run a command against a single cluster, applying the configured check policy.
+///
+/// The configuration is parsed and the connection to the Proxmox VE API is set up once,
+/// then shared by the compatibility and quorum checks and the command itself.
+/// The commands are refused if the cluster runs a Proxmox VE version that is too old.
+/// The commands that modify the cluster are refused if it is not quorate unless
+/// `ignore_quorum` is set.
+async fn run(cfg_target: Target, mode: Mode, ignore_quorum: bool) -> AnyResult<MainExit> {
+    if !mode.per_cluster() {
+        return run_config(cfg_target, mode).await;
+    }
 
-    match disk.options().get("discard") {
-        None => {
-            warn!("No 'discard' defined for {disk_id}");
-            problems = true;
-        }
-        Some(value) if value == "on" => (),
-        Some(other) => {
-            warn!("Expected 'discard=on' for {disk_id}, got '{other}'");
-            problems = true;
+    let cfg = config::parse(&cfg_target)?;
+    let api = Arc::new(cfg.get_proxmox_api()?);
+    compat::check(&api, &cfg.cluster.name, &mode).await?;
+    if mode.writes() {
+        if ignore_quorum {
+            warn!("Not checking whether the cluster is quorate");
+        } else {
+            quorum::check(&api, &cfg.cluster.name)
+                .await
+                .context("Refusing to modify the cluster; use --ignore-quorum to override")?;
         }
-    };
-
-    match disk.options().get("iothread") {
-        None => {
-            warn!("No 'iothread' defined for {disk_id}");
-            problems = true;
+    }
+    let check = mode
+        .check_name()
+        .map(|name| (name, cfg.cluster.spve.checks.policy(name)));
+    match check {
+        Some((name, CheckPolicy::Skip)) => {
+            info!("Skipping the {name} check as configured");
+            Ok(MainExit::Ok)
         }
-        Some(value) if value == "1" => (),
-        Some(other) => {
-            warn!("Expected 'iothread=1' for {disk_id}, got '{other}'");
-            problems = true;
+        Some((name, CheckPolicy::Warn)) => {
+            let res = run_mode(&cfg, &api, mode).await?;
+            if res == MainExit::CheckFailed {
+                warn!("The {name} check found problems, but it is configured to only warn");
+                Ok(MainExit::Ok)
+            } else {
+                Ok(res)
+            }
         }
-    };
-
-    problems
+        Some((_, CheckPolicy::Fail)) | None => run_mode(&cfg, &api, mode).await,
+    }
 }
-/// Check the `StorPool`-backed VM disks.
-async fn cmd_check_vms(cluster: Option<String>) -> Result<MainExit> {
-    let cfg = config::parse(cluster.as_deref())?;
-    let api = cfg.get_proxmox_api()?;
 
-    let storage: HashMap<String, Storage> = api
-        .get(api.path().storage())
+/// Run a command that only examines or modifies the configuration files.
+async fn run_config(cfg_target: Target, mode: Mode) -> AnyResult<MainExit> {
+    match mode {
+        Mode::ConfigAddCluster {
+            name,
+            urls,
+            token_id,
+            token,
+            insecure,
+            check,
+        } => configure::cmd_config_add_cluster(
+            cfg_target, name, urls, token_id, token, insecure, check,
+        )
         .await
-        .map_err(Error::Api)?
-        .into_iter()
-        .map(|store| (store.storage().to_owned(), store))
-        .collect();
-    debug!(
-        "Got information about {count} storage(s)",
-        count = storage.len()
-    );
-    for (name, store) in &storage {
-        debug!(
-            "- {name}: {storage_type}",
-            storage_type = store.storage_type()
-        );
+        .context("Could not add the cluster to the configuration"),
+        Mode::ConfigInit { force } => configure::cmd_config_init(cfg_target, force)
+            .await
+            .context("Could not write the configuration file templates"),
+        Mode::ConfigMigrate { dry_run } => configure::cmd_config_migrate(cfg_target, dry_run)
+            .await
+            .context("Could not convert the configuration file"),
+        Mode::ConfigShow { format } => configure::cmd_config_show(cfg_target, format)
+            .await
+            .context("Could not display the configuration"),
+        Mode::ConfigValidate => configure::cmd_config_validate(cfg_target)
+            .await
+            .context("Could not validate the configuration"),
+        other => Err(Error::Internal(format!("Not a configuration command: {other:?}")).into()),
     }
+}
 
-    let nodes = api.get(api.path().nodes()).await.map_err(Error::Api)?;
-    debug!("Got information about {count} node(s)", count = nodes.len());
-
-    let mut problems = false;
-    for node in nodes {
-        let name = node.node();
-        if node.status() != NodeStatus::Online {
-            debug!("Skipping the {name} node, not online");
-            continue;
+/// Run a command against a single cluster.
+async fn run_mode(cfg: &Config, api: &Arc<Proxmoxy>, mode: Mode) -> AnyResult<MainExit> {
+    match mode {
+        Mode::CheckAttachments { guests } => {
+            check::attachments::cmd_check_attachments(cfg, api, guests)
+                .await
+                .context("Could not check the StorPool volume attachments")
         }
+        Mode::CheckEvacuate { node, guests } => {
+            check::evacuate::cmd_check_evacuate(api, node, guests)
+                .await
+                .context("Could not check the node evacuation")
+        }
+        Mode::CheckHa => check::ha::cmd_check_ha(cfg, api)
+            .await
+            .context("Could not check the HA resources"),
+        Mode::CheckSnapshots { guests } => check::snapshots::cmd_check_snapshots(cfg, api, guests)
+            .await
+            .context("Could not check the snapshots"),
+        Mode::CheckStorage => check::storage::cmd_check_storage(cfg, api)
+            .await
+            .context("Could not check the storage definitions"),
+        Mode::CheckVms { guests, state } => check::vms::cmd_check_vms(api, guests, state)
+            .await
+            .context("Could not check the VM configuration"),
+        Mode::Migrate {
+            source,
+            guests,
+            target,
+            parallel,
+            timeout,
+            dry_run,
+        } => migrate::cmd_migrate(
+            cfg,
+            Arc::clone(api),
+            source,
+            guests,
+            target,
+            parallel,
+            timeout,
+            dry_run,
+        )
+        .await
+        .context("Could not migrate the virtual machines"),
+        Mode::ReportUsage {
+            guests,
+            format,
+            table,
+        } => report::cmd_report_usage(cfg, api, guests, format, table)
+            .await
+            .context("Could not report the storage usage"),
+        Mode::SnapshotCreate {
+            guests,
+            name,
+            description,
+            vmstate,
+        } => snapshot::cmd_snapshot_create(api, guests, name, description, vmstate)
+            .await
+            .context("Could not create the snapshots"),
+        Mode::SnapshotDelete { guests, name } => snapshot::cmd_snapshot_delete(api, guests, name)
+            .await
+            .context("Could not delete the snapshots"),
+        Mode::SnapshotList { guests, format } => snapshot::cmd_snapshot_list(api, guests, format)
+            .await
+            .context("Could not list the snapshots"),
+        Mode::SnapshotPrune {
+            guests,
+            prefix,
+            pattern,
+            policy,
+            dry_run,
+        } => snapshot::cmd_snapshot_prune(cfg, api, guests, prefix, pattern, policy, dry_run)
+            .await
+            .context("Could not prune the snapshots"),
+        Mode::SnapshotRollback { guests, name } => {
+            snapshot::cmd_snapshot_rollback(api, guests, name)
+                .await
+                .context("Could not roll back to the snapshots")
+        }
+        Mode::Status { format } => status::cmd_status(cfg, api, format)
+            .await
+            .context("Could not display the cluster status"),
+        Mode::VmShow { vmid, format } => vm::cmd_vm_show(cfg, api, vmid, format)
+            .await
+            .context("Could not examine the VM"),
+        Mode::WatchConfigs { guests, state_dir } => {
+            watch::cmd_watch_configs(api, guests, state_dir)
+                .await
+                .context("Could not examine the VM configuration changes")
+        }
+        other => Err(Error::Internal(format!("Not a cluster command: {other:?}")).into()),
+    }
+}
 
-        debug!("Looking for virtual machines at {name}");
-        let path_vms = api.path().nodes().id(name).qemu();
-        let vms = api.get(path_vms.clone()).await.map_err(Error::Api)?;
-        debug!(
-            "Got information about {count} VM(s) on node {name}",
-            count = vms.len()
-        );
-
-        for vm in vms {
-            let vmid = vm.vmid();
-            if vmid < 100 {
-                continue;
+/// Run a command against several clusters at once, collecting the output of each one.
+async fn run_clusters(
+    cfg_target: &Target,
+    names: Vec<String>,
+    mode: Mode,
+    ignore_quorum: bool,
+) -> MainExit {
+    let mut tasks = JoinSet::new();
+    for name in names {
+        let cl_mode = match mode {
+            Mode::WatchConfigs {
+                ref guests,
+                ref state_dir,
+            } => Mode::WatchConfigs {
+                guests: guests.clone(),
+                state_dir: state_dir.join(&name),
+            },
+            ref other => other.clone(),
+        };
+        let cl_target = Target {
+            cluster: Some(name.clone()),
+            ..cfg_target.clone()
+        };
+        let span = info_span!("cluster", name = name.as_str());
+        tasks.spawn(
+            async move {
+                let (res, lines) = output::capture(run(cl_target, cl_mode, ignore_quorum)).await;
+                (name, res, lines)
             }
+            .instrument(span),
+        );
+    }
 
-            debug!("Looking for disks on VM {vmid}");
-            let vmcfg = api
-                .get(path_vms.clone().id(vmid).config())
-                .await
-                .map_err(Error::Api)?;
-            for disk in vmcfg.disks().iter() {
-                let disk_id = format!(
-                    "the {disk_type}{idx} disk for VM {vmid}",
-                    disk_type = disk.disk_type().as_ref(),
-                    idx = disk.idx(),
-                );
-                match storage.get(disk.storage()) {
-                    None => {
-                        warn!(
-                            "Invalid type {storage} for {disk_id}",
-                            storage = disk.storage(),
-                        );
-                        problems = true;
-                    }
-                    Some(store) if store.storage_type() != "storpool" => {
-                        continue;
-                    }
-                    Some(_) => {
-                        problems = check_vm_disk(&disk_id, disk) || problems;
-                    }
-                }
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(res) => results.push(res),
+            Err(err) => {
+                error!("A cluster task failed unexpectedly: {err}");
+                results.push((String::new(), Err(err.into()), Vec::new()));
             }
         }
     }
+    results.sort_by(|first, second| first.0.cmp(&second.0));
 
-    if problems {
-        Ok(MainExit::CheckFailed)
-    } else {
-        Ok(MainExit::Ok)
+    let mut exit = MainExit::Ok;
+    let mut outputs = Vec::new();
+    for (name, res, captured) in results {
+        exit = exit.worst(res.unwrap_or_else(|err| {
+            error!("Cluster {name}: {err:#}");
+            MainExit::Failed
+        }));
+        outputs.push((name, captured));
     }
+    if let Err(err) = output::print_clusters(outputs) {
+        error!("Could not display the output: {err:#}");
+        exit = exit.worst(MainExit::Failed);
+    }
+    exit
 }
 
 #[tokio::main]
 async fn main() -> AnyResult<MainExit> {
-    match cli::parse().context("Could not parse the command-line arguments")? {
-        Mode::CheckVms { cluster } => cmd_check_vms(cluster)
+    let Invocation {
+        clusters,
+        cfg_target,
+        ignore_quorum,
+        mode,
+    } = cli::parse().context("Could not parse the command-line arguments")?;
+    if !mode.per_cluster() {
+        return run(cfg_target, mode, ignore_quorum).await;
+    }
+    let names = match clusters {
+        Clusters::Default => return run(cfg_target, mode, ignore_quorum).await,
+        Clusters::Named(names) if names.len() == 1 => {
+            return run(
+                Target {
+                    cluster: names.into_iter().next(),
+                    ..cfg_target
+                },
+                mode,
+                ignore_quorum,
+            )
             .await
-            .context("Could not check the VM configuration"),
+        }
+        Clusters::Named(names) => names,
+        Clusters::All => {
+            config::cluster_names(&cfg_target).context("Could not get the list of clusters")?
+        }
+    };
+    let overrides = config::env_overrides();
+    if !overrides.is_empty() {
+        return Err(Error::Invoke(anyhow!(
+            "The {vars} environment variable(s) may only be used with a single cluster",
+            vars = overrides.join(", ")
+        ))
+        .into());
     }
+    Ok(run_clusters(&cfg_target, names, mode, ignore_quorum).await)
 }
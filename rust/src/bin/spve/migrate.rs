@@ -0,0 +1,389 @@
+//! Live-migrate the running VMs off a Proxmox VE node.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn, Instrument};
+
+use proxmoxy::types::{NodeStatus, NodeSummary, VmStatus};
+use proxmoxy::{Error as PmError, Proxmoxy};
+
+use crate::cli::{GuestSelector, MigrateTarget};
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests::{self, SelectedVm};
+use crate::output;
+use crate::storpool::{GlobalIdDecoder, Rights, StorPool};
+use crate::MainExit;
+
+/// A VM that needs to be placed on another node.
+#[derive(Debug)]
+pub struct Guest {
+    /// The ID of the VM.
+    pub vmid: u32,
+
+    /// The name of the VM, if any.
+    pub name: String,
+
+    /// The amount of memory that the VM needs on the target node.
+    pub mem: u64,
+}
+
+/// A single VM to be migrated.
+#[derive(Debug)]
+pub struct Migration {
+    /// The ID of the VM.
+    pub vmid: u32,
+
+    /// The name of the VM, if any.
+    pub name: String,
+
+    /// The node to migrate the VM to.
+    pub target: String,
+}
+
+/// The information needed to verify the `StorPool` attachments after a migration.
+#[derive(Debug)]
+struct Verifier {
+    /// The `StorPool` API client.
+    sp_api: StorPool,
+
+    /// Extract the `StorPool` global IDs from the volume IDs.
+    decoder: GlobalIdDecoder,
+
+    /// The `StorPool` client ID of each Proxmox VE node.
+    node_ids: HashMap<String, u32>,
+
+    /// The names of the `storpool` storages.
+    sp_storage: HashSet<String>,
+}
+
+impl Verifier {
+    /// Prepare to verify the attachments if the `StorPool` API is configured.
+    async fn from_config(cfg: &Config, api: &Proxmoxy) -> Result<Option<Self>> {
+        match cfg.get_storpool_api() {
+            Ok(sp_api) => {
+                let sp_storage = api
+                    .get(api.path().storage())
+                    .await
+                    .map_err(Error::Api)?
+                    .into_iter()
+                    .filter(|store| store.as_storpool().is_some())
+                    .map(|store| store.storage().to_owned())
+                    .collect();
+                Ok(Some(Self {
+                    sp_api,
+                    decoder: GlobalIdDecoder::new()?,
+                    node_ids: cfg
+                        .storpool_node_names()
+                        .into_iter()
+                        .map(|(sp_id, name)| (name, sp_id))
+                        .collect(),
+                    sp_storage,
+                }))
+            }
+            Err(Error::StorPoolNotConfigured(_)) => {
+                warn!("No StorPool API settings, the attachments will not be verified");
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Make sure that the VM's disks are attached to the target node and not to the source one.
+    async fn verify(&self, api: &Proxmoxy, source: &str, mig: &Migration) -> Result<bool> {
+        let (src_id, dst_id) = if let (Some(src_id), Some(dst_id)) =
+            (self.node_ids.get(source), self.node_ids.get(&mig.target))
+        {
+            (*src_id, *dst_id)
+        } else {
+            warn!(
+                "VM {vmid}: cannot verify the StorPool attachments, no client IDs for {source} and {target}",
+                vmid = mig.vmid,
+                target = mig.target
+            );
+            return Ok(true);
+        };
+
+        let vmcfg = api
+            .get(
+                api.path()
+                    .nodes()
+                    .id(&mig.target)
+                    .qemu()
+                    .id(mig.vmid)
+                    .config(),
+            )
+            .await
+            .map_err(Error::Api)?;
+        let attachments = self.sp_api.attachments().await?;
+
+        let mut good = true;
+        for disk in vmcfg
+            .disks()
+            .iter()
+            .filter(|disk| self.sp_storage.contains(disk.storage()))
+        {
+            let sp_name = match self.decoder.decode(disk.volid()) {
+                Some(global_id) => format!("~{global_id}"),
+                None => continue,
+            };
+            let disk_atts: Vec<_> = attachments
+                .iter()
+                .filter(|att| att.volume == sp_name)
+                .collect();
+            if disk_atts.iter().any(|att| att.client == src_id) {
+                warn!(
+                    "VM {vmid}: {sp_name} is still attached to {source}",
+                    vmid = mig.vmid
+                );
+                good = false;
+            }
+            if !disk_atts
+                .iter()
+                .any(|att| att.client == dst_id && att.rights == Rights::ReadWrite)
+            {
+                warn!(
+                    "VM {vmid}: {sp_name} is not attached read-write to {target}",
+                    vmid = mig.vmid,
+                    target = mig.target
+                );
+                good = false;
+            }
+        }
+        Ok(good)
+    }
+}
+
+/// Start a single migration, wait for it to complete, verify the result.
+///
+/// A migration that does not complete within `timeout` is considered to have failed.
+async fn run_migration(
+    api: Arc<Proxmoxy>,
+    verifier: Option<Arc<Verifier>>,
+    source: String,
+    mig: Migration,
+    timeout: Duration,
+) -> Result<bool> {
+    let vmid = mig.vmid;
+    let upid = api
+        .post(
+            api.path().nodes().id(&source).qemu().id(vmid).migrate(),
+            &[("target", &mig.target), ("online", "1")],
+        )
+        .await
+        .map_err(Error::Api)?;
+    info!(
+        "VM {vmid}: migrating to {target}, task {upid}",
+        target = mig.target,
+        upid = upid.as_str()
+    );
+
+    let status = match api.wait_task(&upid, timeout).await {
+        Ok(status) => status,
+        Err(PmError::TimedOut(_)) => {
+            warn!(
+                "VM {vmid}: the migration to {target} did not complete within {secs} seconds, task {upid}",
+                target = mig.target,
+                secs = timeout.as_secs(),
+                upid = upid.as_str()
+            );
+            return Ok(false);
+        }
+        Err(err) => return Err(Error::Api(err)),
+    };
+    if !status.succeeded() {
+        warn!(
+            "VM {vmid}: the migration to {target} failed: {exitstatus}",
+            target = mig.target,
+            exitstatus = status.exitstatus().unwrap_or("(no exit status)")
+        );
+        return Ok(false);
+    }
+    info!("VM {vmid}: migrated to {target}", target = mig.target);
+
+    match verifier {
+        Some(ver) => ver.verify(&api, &source, &mig).await,
+        None => Ok(true),
+    }
+}
+
+/// Compute the free memory on the online nodes other than the source one.
+#[must_use]
+pub fn headroom(nodes: &[NodeSummary], source: &str) -> HashMap<String, u64> {
+    nodes
+        .iter()
+        .filter(|node| node.node() != source && node.status() == NodeStatus::Online)
+        .map(|node| {
+            (
+                node.node().to_owned(),
+                node.maxmem()
+                    .unwrap_or(0)
+                    .saturating_sub(node.mem().unwrap_or(0)),
+            )
+        })
+        .collect()
+}
+
+/// Plan the migrations: pick a target node for each VM.
+///
+/// The VMs are placed in order, so they should be sorted by decreasing memory size.
+/// The `allowed` function may reject a target node for a VM for reasons other than memory.
+/// Return the planned migrations and the IDs of the VMs that could not be placed.
+#[must_use]
+pub fn plan<F>(
+    guests: Vec<Guest>,
+    target: &MigrateTarget,
+    mut headroom: HashMap<String, u64>,
+    allowed: F,
+) -> (Vec<Migration>, Vec<u32>)
+where
+    F: Fn(u32, &str) -> bool,
+{
+    let mut res = Vec::new();
+    let mut unplaced = Vec::new();
+    for guest in guests {
+        let vmid = guest.vmid;
+        let chosen = match *target {
+            MigrateTarget::Node(ref node) => Some(node.clone()).filter(|name| allowed(vmid, name)),
+            MigrateTarget::Auto => headroom
+                .iter()
+                .filter(|&(node, free)| *free >= guest.mem && allowed(vmid, node))
+                .max_by(|first, second| first.1.cmp(second.1).then(second.0.cmp(first.0)))
+                .map(|(node, _)| node.clone()),
+        };
+        match chosen {
+            Some(node) => {
+                if let Some(free) = headroom.get_mut(&node) {
+                    *free = free.saturating_sub(guest.mem);
+                }
+                res.push(Migration {
+                    vmid,
+                    name: guest.name,
+                    target: node,
+                });
+            }
+            None => unplaced.push(vmid),
+        }
+    }
+    (res, unplaced)
+}
+
+/// Live-migrate the running VMs off a node.
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_migrate(
+    cfg: &Config,
+    api: Arc<Proxmoxy>,
+    source: String,
+    guests: GuestSelector,
+    target: MigrateTarget,
+    parallel: usize,
+    timeout: Duration,
+    dry_run: bool,
+) -> Result<MainExit> {
+    let nodes = api.get(api.path().nodes()).await.map_err(Error::Api)?;
+    if !nodes
+        .iter()
+        .any(|node| node.node() == source && node.status() == NodeStatus::Online)
+    {
+        return Err(Error::Invoke(anyhow!("The {source} node is not online")));
+    }
+    let headroom = headroom(&nodes, &source);
+    if let MigrateTarget::Node(ref name) = target {
+        if !headroom.contains_key(name) {
+            return Err(Error::Invoke(anyhow!(
+                "The {name} node is not an online node other than {source}"
+            )));
+        }
+    }
+
+    let mut vms = Vec::new();
+    let mut problems = false;
+    for SelectedVm { vm, .. } in guests::select(&api, &guests).await?.vms {
+        let vmid = vm.vmid();
+        if vm.status() != VmStatus::Running {
+            debug!("Skipping VM {vmid}, not running");
+            continue;
+        }
+        if let Some(lock) = vm.lock() {
+            warn!("VM {vmid}: locked ({lock}), not migrating it");
+            problems = true;
+            continue;
+        }
+        vms.push(Guest {
+            vmid,
+            name: vm.name().unwrap_or_default().to_owned(),
+            mem: vm.maxmem().unwrap_or(0),
+        });
+    }
+    vms.sort_by(|first, second| {
+        second
+            .mem
+            .cmp(&first.mem)
+            .then(first.vmid.cmp(&second.vmid))
+    });
+
+    let (migrations, unplaced) = plan(vms, &target, headroom, |_, _| true);
+    for vmid in &unplaced {
+        warn!("VM {vmid}: no node has enough free memory for it");
+        problems = true;
+    }
+    for mig in &migrations {
+        output::emit(&format!(
+            "VM {vmid} ({name}): {source} -> {target}",
+            vmid = mig.vmid,
+            name = mig.name,
+            target = mig.target
+        ));
+    }
+    if dry_run {
+        return Ok(MainExit::from_failures(problems));
+    }
+
+    let verifier = Verifier::from_config(cfg, &api).await?.map(Arc::new);
+
+    let mut running = JoinSet::new();
+    let mut pending = migrations.into_iter();
+    loop {
+        while running.len() < parallel {
+            match pending.next() {
+                Some(mig) => {
+                    running.spawn(
+                        run_migration(
+                            Arc::clone(&api),
+                            verifier.clone(),
+                            source.clone(),
+                            mig,
+                            timeout,
+                        )
+                        .in_current_span(),
+                    );
+                }
+                None => break,
+            }
+        }
+        match running.join_next().await {
+            Some(res) => {
+                let good = res.map_err(|err| {
+                    Error::Internal(format!("A migration task could not be completed: {err}"))
+                })?;
+                match good {
+                    Ok(true) => (),
+                    Ok(false) => problems = true,
+                    Err(err) => {
+                        warn!("{err:?}");
+                        problems = true;
+                    }
+                }
+            }
+            None => break,
+        }
+    }
+
+    Ok(MainExit::from_failures(problems))
+}
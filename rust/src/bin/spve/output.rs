@@ -0,0 +1,367 @@
+//! Display the results of the spve commands as a table, JSON, or CSV.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use proxmoxy::JsonValue;
+
+use crate::defs::{Error, Result};
+
+tokio::task_local! {
+    /// The output collected while running a command against one of many clusters.
+    static CAPTURED: RefCell<Vec<Captured>>;
+}
+
+/// A piece of output collected while running a command against one of many clusters.
+#[derive(Debug, PartialEq)]
+pub enum Captured {
+    /// A line of human-readable text.
+    Line(String),
+
+    /// A JSON document.
+    Json(JsonValue),
+
+    /// A table to be displayed as comma-separated values.
+    Csv(Table),
+}
+
+/// Is the output being collected while running against one of many clusters?
+fn capturing() -> bool {
+    CAPTURED.try_with(|_| ()).is_ok()
+}
+
+/// Collect a piece of output if running against one of many clusters.
+///
+/// Return false if the output should be displayed right away instead.
+fn collect<F: FnOnce() -> Captured>(item: F) -> bool {
+    CAPTURED
+        .try_with(|captured| captured.borrow_mut().push(item()))
+        .is_ok()
+}
+
+/// Display a line of output, or collect it if running against one of many clusters.
+///
+/// This is the only place where the spve tool writes to the standard output stream.
+#[allow(clippy::print_stdout)]
+pub fn emit(line: &str) {
+    if !collect(|| Captured::Line(line.to_owned())) {
+        println!("{line}");
+    }
+}
+
+/// Run a command, collecting its output instead of displaying it right away.
+pub async fn capture<F: Future>(fut: F) -> (F::Output, Vec<Captured>) {
+    CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            let res = fut.await;
+            (res, CAPTURED.with(RefCell::take))
+        })
+        .await
+}
+
+/// The output format for reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A human-readable table.
+    Table,
+
+    /// A JSON object.
+    Json,
+
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+/// A list of rows with named columns to be displayed as a table or as CSV.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// The column headers.
+    headers: Vec<&'static str>,
+
+    /// The table rows, each with as many values as there are headers.
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Start building a table with the specified column headers.
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row to the table.
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Display the table with its columns aligned.
+    fn print_aligned(&self) {
+        let widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(idx, header)| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(idx))
+                    .map(|value| value.chars().count())
+                    .fold(header.len(), usize::max)
+            })
+            .collect();
+        let format_row = |row: &[String]| {
+            row.iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{value:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        };
+
+        let headers: Vec<String> = self
+            .headers
+            .iter()
+            .map(|&header| header.to_owned())
+            .collect();
+        emit(&format_row(&headers));
+        for row in &self.rows {
+            emit(&format_row(row));
+        }
+    }
+
+    /// Display the table as comma-separated values.
+    fn print_csv(&self) {
+        let quote = |value: &str| {
+            if value.contains([',', '"', '\n']) {
+                format!("\"{value}\"", value = value.replace('"', "\"\""))
+            } else {
+                value.to_owned()
+            }
+        };
+        emit(
+            &self
+                .headers
+                .iter()
+                .map(|header| quote(header))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        for row in &self.rows {
+            emit(
+                &row.iter()
+                    .map(|value| quote(value))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+    }
+
+    /// Display the table in the specified format; JSON is not handled here.
+    pub fn print(&self, format: Format) {
+        if format == Format::Csv {
+            if !collect(|| Captured::Csv(self.clone())) {
+                self.print_csv();
+            }
+        } else {
+            self.print_aligned();
+        }
+    }
+}
+
+/// Display a serializable object as pretty-printed JSON.
+///
+/// # Errors
+///
+/// [`Error::Internal`] if the object could not be serialized.
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    if capturing() {
+        let doc = serde_json::to_value(value)
+            .map_err(|err| Error::Internal(format!("Could not serialize the output: {err}")))?;
+        collect(|| Captured::Json(doc));
+        return Ok(());
+    }
+
+    let text = serde_json::to_string_pretty(value)
+        .map_err(|err| Error::Internal(format!("Could not serialize the output: {err}")))?;
+    emit(&text);
+    Ok(())
+}
+
+/// Display the output collected while running a command against several clusters.
+///
+/// The lines of text are shown in a separate section for each cluster.
+/// The JSON documents are combined into a single object keyed by the cluster name.
+/// The CSV tables are combined into a single one with a leading "cluster" column.
+///
+/// # Errors
+///
+/// [`Error::Internal`] if the JSON output could not be serialized or
+/// the CSV tables of the clusters do not have the same columns.
+pub fn print_clusters(outputs: Vec<(String, Vec<Captured>)>) -> Result<()> {
+    let mut docs: BTreeMap<String, JsonValue> = BTreeMap::new();
+    let mut merged: Option<Table> = None;
+    for (name, items) in outputs {
+        let mut header = false;
+        let mut cl_docs = Vec::new();
+        for item in items {
+            match item {
+                Captured::Line(line) => {
+                    if !header {
+                        emit(&format!("==> {name} <=="));
+                        header = true;
+                    }
+                    emit(&line);
+                }
+                Captured::Json(doc) => cl_docs.push(doc),
+                Captured::Csv(table) => {
+                    let combined = merged.get_or_insert_with(|| {
+                        let mut headers = vec!["cluster"];
+                        headers.extend_from_slice(&table.headers);
+                        Table {
+                            headers,
+                            rows: Vec::new(),
+                        }
+                    });
+                    if combined.headers.get(1..) != Some(table.headers.as_slice()) {
+                        return Err(Error::Internal(format!(
+                            "Cluster {name}: unexpected CSV columns {headers:?}",
+                            headers = table.headers
+                        )));
+                    }
+                    for row in table.rows {
+                        let mut cl_row = vec![name.clone()];
+                        cl_row.extend(row);
+                        combined.push(cl_row);
+                    }
+                }
+            }
+        }
+        match cl_docs.len() {
+            0 => (),
+            1 => {
+                docs.insert(name, cl_docs.remove(0));
+            }
+            _ => {
+                docs.insert(name, JsonValue::Array(cl_docs));
+            }
+        }
+    }
+
+    if !docs.is_empty() {
+        print_json(&docs)?;
+    }
+    if let Some(table) = merged {
+        table.print_csv();
+    }
+    Ok(())
+}
+
+/// Divide two numbers, treating a zero divisor as yielding zero.
+fn div(num: u64, den: u64) -> u64 {
+    num.checked_div(den).unwrap_or_default()
+}
+
+/// Get the remainder of dividing two numbers, treating a zero divisor as yielding zero.
+fn rem(num: u64, den: u64) -> u64 {
+    num.checked_rem(den).unwrap_or_default()
+}
+
+/// Format a size in bytes for display in a table, e.g. "12.5G".
+pub fn format_size(bytes: u64, format: Format) -> String {
+    if format != Format::Table {
+        return bytes.to_string();
+    }
+
+    let units = ["K", "M", "G", "T", "P"];
+    let mut value = bytes;
+    let mut frac = 0;
+    let mut unit = "";
+    for next in units {
+        if value < 1024 {
+            break;
+        }
+        frac = div(rem(value, 1024).saturating_mul(10), 1024);
+        value = div(value, 1024);
+        unit = next;
+    }
+    if unit.is_empty() {
+        value.to_string()
+    } else {
+        format!("{value}.{frac}{unit}")
+    }
+}
+
+/// Format an optional size in bytes for display, leaving a placeholder if there is no value.
+pub fn format_opt_size(bytes: Option<u64>, format: Format) -> String {
+    bytes.map_or_else(
+        || {
+            if format == Format::Table {
+                "-".to_owned()
+            } else {
+                String::new()
+            }
+        },
+        |value| format_size(value, format),
+    )
+}
+
+/// Format a duration in seconds as days, hours, and minutes, e.g. "12d 03:45".
+pub fn format_duration(secs: u64) -> String {
+    format!(
+        "{days}d {hours:02}:{mins:02}",
+        days = div(secs, 86400),
+        hours = div(rem(secs, 86400), 3600),
+        mins = div(rem(secs, 3600), 60)
+    )
+}
+
+/// Format a Unix timestamp for display in a table as a UTC date and time.
+pub fn format_time(secs: u64, format: Format) -> String {
+    if format != Format::Table {
+        return secs.to_string();
+    }
+
+    // Howard Hinnant's days-to-civil algorithm, restricted to dates after 1970.
+    let days = div(secs, 86400);
+    let time = rem(secs, 86400);
+    let shifted = days.saturating_add(719_468);
+    let era = div(shifted, 146_097);
+    let doe = rem(shifted, 146_097);
+    let yoe = div(
+        doe.saturating_sub(div(doe, 1460))
+            .saturating_add(div(doe, 36524))
+            .saturating_sub(div(doe, 146_096)),
+        365,
+    );
+    let doy = doe.saturating_sub(
+        yoe.saturating_mul(365)
+            .saturating_add(div(yoe, 4))
+            .saturating_sub(div(yoe, 100)),
+    );
+    let mp = div(doy.saturating_mul(5).saturating_add(2), 153);
+    let day = doy
+        .saturating_sub(div(mp.saturating_mul(153).saturating_add(2), 5))
+        .saturating_add(1);
+    let month = if mp < 10 {
+        mp.saturating_add(3)
+    } else {
+        mp.saturating_sub(9)
+    };
+    let year = yoe
+        .saturating_add(era.saturating_mul(400))
+        .saturating_add(u64::from(month <= 2));
+    format!(
+        "{year:04}-{month:02}-{day:02} {hour:02}:{min:02}:{sec:02}",
+        hour = div(time, 3600),
+        min = div(rem(time, 3600), 60),
+        sec = rem(time, 60)
+    )
+}
@@ -0,0 +1,56 @@
+//! Make sure that a Proxmox VE cluster is quorate before modifying anything.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use tracing::debug;
+
+use proxmoxy::types::ClusterStatusItem;
+use proxmoxy::Proxmoxy;
+
+use crate::defs::{Error, Result};
+
+/// Make sure that the cluster has quorum.
+///
+/// A standalone node that is not part of a cluster is always considered quorate.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the cluster status could not be obtained.
+/// [`Error::NoQuorum`] if the cluster is not quorate.
+pub async fn check(api: &Proxmoxy, cl_name: &str) -> Result<()> {
+    let status = api
+        .get(api.path().cluster().status())
+        .await
+        .map_err(Error::Api)?;
+    match status.iter().find(|item| item.is_cluster()) {
+        Some(cluster) if cluster.quorate() => {
+            debug!(
+                "The {name} Proxmox VE cluster is quorate, {online} of {count} node(s) online",
+                name = cluster.name(),
+                online = status
+                    .iter()
+                    .filter(|item| item.is_node() && item.online())
+                    .count(),
+                count = cluster.nodes().unwrap_or(0)
+            );
+            Ok(())
+        }
+        Some(cluster) if cluster.name() == cl_name => Err(Error::NoQuorum(cl_name.to_owned())),
+        Some(cluster) => Err(Error::NoQuorum(format!(
+            "{cl_name} ({name})",
+            name = cluster.name()
+        ))),
+        None => {
+            debug!(
+                "Not part of a Proxmox VE cluster, standalone node {nodes}",
+                nodes = status
+                    .iter()
+                    .filter(|item| item.is_node())
+                    .map(ClusterStatusItem::name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Report the storage usage as seen by Proxmox VE and by `StorPool`.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use proxmoxy::types::{NodeStatus, StorageConfig};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::{GuestSelector, UsageTable};
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::output::{self, Format, Table};
+use crate::storpool::{GlobalIdDecoder, TemplateStatus, Volume};
+use crate::MainExit;
+
+/// The usage of a single `storpool` storage.
+#[derive(Debug, Serialize)]
+struct StorageUsage {
+    /// The name of the Proxmox VE storage.
+    storage: String,
+
+    /// The name of the `StorPool` template.
+    template: String,
+
+    /// The total size as reported by Proxmox VE.
+    pve_total: Option<u64>,
+
+    /// The used space as reported by Proxmox VE.
+    pve_used: Option<u64>,
+
+    /// The capacity available to the `StorPool` template.
+    sp_capacity: Option<u64>,
+
+    /// The free space available to the `StorPool` template.
+    sp_free: Option<u64>,
+
+    /// The space taken up by the template's volumes and snapshots.
+    sp_stored: Option<u64>,
+
+    /// The total provisioned size of the template's volumes.
+    sp_provisioned: Option<u64>,
+}
+
+/// The usage of the `storpool` storage by a single VM.
+#[derive(Debug, Serialize)]
+struct VmUsage {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// The node that the VM runs on.
+    node: String,
+
+    /// The resource pool that the VM belongs to, if any.
+    pool: Option<String>,
+
+    /// The tags assigned to the VM.
+    tags: Vec<String>,
+
+    /// The number of the VM's disks on `storpool` storage.
+    disks: usize,
+
+    /// The total size of the disks as specified in the VM configuration.
+    configured: u64,
+
+    /// The total size of the `StorPool` volumes backing the disks.
+    provisioned: u64,
+
+    /// The number of `StorPool` snapshots of the VM's disks.
+    snapshots: usize,
+
+    /// The total size of the `StorPool` snapshots of the VM's disks.
+    snapshots_size: u64,
+}
+
+/// The full storage usage report.
+#[derive(Debug, Serialize)]
+struct UsageReport {
+    /// The usage of each `storpool` storage.
+    storage: Vec<StorageUsage>,
+
+    /// The usage of the `storpool` storage by each VM.
+    vms: Vec<VmUsage>,
+}
+
+impl UsageReport {
+    /// Display the report in the specified format, either all of it or a single table.
+    ///
+    /// The JSON output always holds the whole report.
+    fn print(&self, format: Format, table: Option<UsageTable>) -> Result<()> {
+        if format == Format::Json {
+            return output::print_json(self);
+        }
+
+        if table != Some(UsageTable::Vms) {
+            self.print_storage(format);
+        }
+        if table.is_none() {
+            output::emit("");
+        }
+        if table != Some(UsageTable::Storage) {
+            self.print_vms(format);
+        }
+        Ok(())
+    }
+
+    /// Display the usage of each `storpool` storage.
+    fn print_storage(&self, format: Format) {
+        let mut storage = Table::new(&[
+            "storage",
+            "template",
+            "pve_total",
+            "pve_used",
+            "sp_capacity",
+            "sp_free",
+            "sp_stored",
+            "sp_provisioned",
+        ]);
+        for item in &self.storage {
+            storage.push(vec![
+                item.storage.clone(),
+                item.template.clone(),
+                output::format_opt_size(item.pve_total, format),
+                output::format_opt_size(item.pve_used, format),
+                output::format_opt_size(item.sp_capacity, format),
+                output::format_opt_size(item.sp_free, format),
+                output::format_opt_size(item.sp_stored, format),
+                output::format_opt_size(item.sp_provisioned, format),
+            ]);
+        }
+        storage.print(format);
+    }
+
+    /// Display the usage of the `storpool` storage by each VM.
+    fn print_vms(&self, format: Format) {
+        let mut vms = Table::new(&[
+            "vmid",
+            "node",
+            "pool",
+            "tags",
+            "disks",
+            "configured",
+            "provisioned",
+            "snapshots",
+            "snapshots_size",
+        ]);
+        for item in &self.vms {
+            vms.push(vec![
+                item.vmid.to_string(),
+                item.node.clone(),
+                item.pool.clone().unwrap_or_default(),
+                item.tags.join(";"),
+                item.disks.to_string(),
+                output::format_size(item.configured, format),
+                output::format_size(item.provisioned, format),
+                item.snapshots.to_string(),
+                output::format_size(item.snapshots_size, format),
+            ]);
+        }
+        vms.print(format);
+    }
+}
+
+/// Compare the Proxmox VE view of the `StorPool`-backed storage with the templates' usage.
+async fn storage_usage(
+    api: &Proxmoxy,
+    sp_storage: &[StorageConfig],
+    templates: &HashMap<String, TemplateStatus>,
+) -> Result<Vec<StorageUsage>> {
+    let online: Vec<String> = api
+        .get(api.path().nodes())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .filter(|node| node.status() == NodeStatus::Online)
+        .map(|node| node.node().to_owned())
+        .sorted()
+        .collect();
+
+    let mut storage = Vec::new();
+    for store in sp_storage {
+        let name = store.storage();
+        let (pve_total, pve_used) =
+            if let Some(node) = online.iter().find(|node| store.common().enabled_on(node)) {
+                let status = api
+                    .get(api.path().nodes().id(node).storage().id(name).status())
+                    .await
+                    .map_err(Error::Api)?;
+                (status.total(), status.used())
+            } else {
+                warn!("Storage {name}: not enabled on any online node");
+                (None, None)
+            };
+        let template = store
+            .as_storpool()
+            .map_or(name, |sp_store| sp_store.template_name());
+        let tmpl = templates.get(template);
+        if tmpl.is_none() {
+            warn!("Storage {name}: no '{template}' StorPool template");
+        }
+        storage.push(StorageUsage {
+            storage: name.to_owned(),
+            template: template.to_owned(),
+            pve_total,
+            pve_used,
+            sp_capacity: tmpl.and_then(|tmpl| tmpl.stored.as_ref().map(|stored| stored.capacity)),
+            sp_free: tmpl.and_then(|tmpl| tmpl.stored.as_ref().map(|stored| stored.free)),
+            sp_stored: tmpl.map(|tmpl| tmpl.stored_size),
+            sp_provisioned: tmpl.map(|tmpl| tmpl.size),
+        });
+    }
+    Ok(storage)
+}
+
+/// Report the usage of the `storpool` storage, per storage and per VM.
+pub async fn cmd_report_usage(
+    cfg: &Config,
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    format: Format,
+    table: Option<UsageTable>,
+) -> Result<MainExit> {
+    let sp_api = cfg.get_storpool_api()?;
+
+    let sp_storage: Vec<StorageConfig> = api
+        .get(api.path().storage())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .filter(|store| store.as_storpool().is_some())
+        .sorted_by(|first, second| first.storage().cmp(second.storage()))
+        .collect();
+    let sp_names: HashSet<&str> = sp_storage.iter().map(StorageConfig::storage).collect();
+
+    let templates: HashMap<String, TemplateStatus> = sp_api
+        .templates_status()
+        .await?
+        .into_iter()
+        .map(|tmpl| (tmpl.name.clone(), tmpl))
+        .collect();
+    let volumes: HashMap<String, Volume> = sp_api
+        .volumes()
+        .await?
+        .into_iter()
+        .map(|vol| (vol.name.clone(), vol))
+        .collect();
+    let snapshots = sp_api.snapshots().await?;
+    debug!(
+        "Got information about {vcount} StorPool volume(s) and {scount} snapshot(s)",
+        vcount = volumes.len(),
+        scount = snapshots.len()
+    );
+
+    let storage = storage_usage(api, &sp_storage, &templates).await?;
+
+    let mut vm_snapshots: HashMap<String, (usize, u64)> = HashMap::new();
+    for snap in &snapshots {
+        if let (Some(vmid), Some(store)) = (snap.tags.get("pve-vm"), snap.tags.get("pve")) {
+            if sp_names.contains(store.as_str()) {
+                let entry = vm_snapshots.entry(vmid.clone()).or_default();
+                entry.0 = entry.0.saturating_add(1);
+                entry.1 = entry.1.saturating_add(snap.size);
+            }
+        }
+    }
+
+    let decoder = GlobalIdDecoder::new()?;
+    let mut vms = Vec::new();
+    for sel in guests::select_with_pools(api, &guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let vmcfg = api
+            .get(api.path().nodes().id(&sel.node).qemu().id(vmid).config())
+            .await
+            .map_err(Error::Api)?;
+        let mut usage = VmUsage {
+            vmid,
+            node: sel.node.clone(),
+            pool: sel.pool.clone(),
+            tags: sel.sorted_tags(),
+            disks: 0,
+            configured: 0,
+            provisioned: 0,
+            snapshots: 0,
+            snapshots_size: 0,
+        };
+        for disk in vmcfg
+            .disks()
+            .iter()
+            .filter(|disk| sp_names.contains(disk.storage()))
+        {
+            usage.disks = usage.disks.saturating_add(1);
+            usage.configured = usage
+                .configured
+                .saturating_add(disk.size().map_err(Error::Api)?.unwrap_or(0));
+            if let Some(global_id) = decoder.decode(disk.volid()) {
+                if let Some(vol) = volumes.get(&format!("~{global_id}")) {
+                    usage.provisioned = usage.provisioned.saturating_add(vol.size);
+                } else {
+                    warn!(
+                        "VM {vmid}: no StorPool volume for {volid}",
+                        volid = disk.volid()
+                    );
+                }
+            } else {
+                debug!(
+                    "VM {vmid}: no StorPool global ID in {volid}",
+                    volid = disk.volid()
+                );
+            }
+        }
+        if let Some(&(count, size)) = vm_snapshots.get(&vmid.to_string()) {
+            usage.snapshots = count;
+            usage.snapshots_size = size;
+        }
+        if usage.disks > 0 || usage.snapshots > 0 {
+            vms.push(usage);
+        }
+    }
+    vms.sort_by_key(|usage| usage.vmid);
+
+    UsageReport { storage, vms }.print(format, table)?;
+    Ok(MainExit::Ok)
+}
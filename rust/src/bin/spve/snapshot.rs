@@ -0,0 +1,555 @@
+//! Manage the snapshots of many VMs at once.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, Error as AnyError};
+use regex::{Captures, Regex};
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use proxmoxy::types::{StorageConfig, Upid, VmSnapshot};
+use proxmoxy::{Error as PmError, Proxmoxy, Result as PmResult};
+
+use crate::check;
+use crate::check::snapshots::{snapshot_owner, sp_disk_count, VMSTATE_DISK};
+use crate::cli::{GuestSelector, RetentionPolicy};
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests::{self, SelectedVm};
+use crate::output::{self, Format, Table};
+use crate::MainExit;
+
+/// How long to wait for a single snapshot task before considering it failed.
+const TASK_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// A single snapshot of a single VM.
+#[derive(Debug, Serialize)]
+struct SnapshotInfo {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// The node that the VM is on.
+    node: String,
+
+    /// The resource pool that the VM belongs to, if any.
+    pool: Option<String>,
+
+    /// The tags assigned to the VM.
+    tags: Vec<String>,
+
+    /// The name of the snapshot.
+    name: String,
+
+    /// The time the snapshot was taken at, in seconds since the Unix epoch.
+    snaptime: Option<u64>,
+
+    /// Does the snapshot include the VM's memory state?
+    vmstate: bool,
+
+    /// The name of the snapshot that this one was taken after.
+    parent: Option<String>,
+
+    /// The description of the snapshot.
+    description: String,
+}
+
+/// The names of the snapshots created by a scheduled job: a fixed prefix followed by a timestamp.
+#[derive(Debug)]
+pub struct NamePattern {
+    /// The regular expression that matches the snapshot names.
+    re: Regex,
+
+    /// The timestamp fields captured by the regular expression, in order.
+    fields: Vec<char>,
+}
+
+impl NamePattern {
+    /// Build a name pattern from a prefix and a strftime-like timestamp pattern.
+    ///
+    /// Only the `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, and `%%` conversions are supported.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Invoke`] if the timestamp pattern is invalid.
+    pub fn new(prefix: &str, pattern: &str) -> Result<Self> {
+        let mut re_src = format!("^{prefix}", prefix = regex::escape(prefix));
+        let mut fields = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(chr) = chars.next() {
+            if chr != '%' {
+                re_src.push_str(&regex::escape(&chr.to_string()));
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => {
+                    re_src.push_str("([0-9]{4})");
+                    fields.push('Y');
+                }
+                Some(field @ ('m' | 'd' | 'H' | 'M' | 'S')) => {
+                    re_src.push_str("([0-9]{2})");
+                    fields.push(field);
+                }
+                Some('%') => re_src.push('%'),
+                other => {
+                    return Err(Error::Invoke(anyhow!(
+                        "Unsupported conversion %{other} in the {pattern:?} timestamp pattern",
+                        other = other.map_or_else(String::new, String::from)
+                    )))
+                }
+            }
+        }
+        re_src.push('$');
+        if !['Y', 'm', 'd'].iter().all(|field| fields.contains(field)) {
+            return Err(Error::Invoke(anyhow!(
+                "The {pattern:?} timestamp pattern must contain at least %Y, %m, and %d"
+            )));
+        }
+        let re = Regex::new(&re_src).map_err(|err| {
+            Error::Internal(format!(
+                "Could not build the snapshot name regular expression: {err}"
+            ))
+        })?;
+        Ok(Self { re, fields })
+    }
+
+    /// Extract the timestamp (UTC, seconds since the Unix epoch) from a snapshot name.
+    #[must_use]
+    pub fn timestamp(&self, name: &str) -> Option<u64> {
+        let caps: Captures<'_> = self.re.captures(name)?;
+        let (mut year, mut month, mut day, mut hour, mut min, mut sec) = (0, 1, 1, 0, 0, 0);
+        for (idx, field) in self.fields.iter().enumerate() {
+            let value: u64 = caps.get(idx + 1)?.as_str().parse().ok()?;
+            match *field {
+                'Y' => year = value,
+                'm' => month = value,
+                'd' => day = value,
+                'H' => hour = value,
+                'M' => min = value,
+                _ => sec = value,
+            }
+        }
+        if year < 1970 || !(1..=12).contains(&month) {
+            return None;
+        }
+        if !(1..=days_in_month(year, month)).contains(&day) {
+            return None;
+        }
+        if hour > 23 || min > 59 || sec > 59 {
+            return None;
+        }
+        Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + min * 60 + sec)
+    }
+}
+
+/// Get the number of days in a month of the Gregorian calendar.
+#[must_use]
+pub const fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Compute the number of days since the Unix epoch for a date after 1970.
+///
+/// This is Howard Hinnant's days-from-civil algorithm.
+#[must_use]
+pub const fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let adj_year = if month <= 2 { year - 1 } else { year };
+    let era = adj_year / 400;
+    let yoe = adj_year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Map a timestamp to the retention period (hour, day, week) that it falls within.
+type PeriodFn = fn(u64) -> u64;
+
+/// Decide which snapshots to keep according to a retention policy.
+///
+/// The snapshots must be sorted by timestamp, newest first.
+/// For each period, keep the newest snapshot in each of the most recent periods.
+#[must_use]
+pub fn retained<'snap>(
+    snapshots: &[(&'snap str, u64)],
+    policy: &RetentionPolicy,
+) -> HashSet<&'snap str> {
+    let periods: [(usize, PeriodFn); 3] = [
+        (policy.hourly, |secs| secs / 3600),
+        (policy.daily, |secs| secs / 86400),
+        // The Unix epoch was a Thursday; make the weeks start on Mondays.
+        (policy.weekly, |secs| (secs / 86400 + 3) / 7),
+    ];
+
+    let mut keep = HashSet::new();
+    for (count, period) in periods {
+        let mut last = None;
+        let mut kept = 0;
+        for &(name, secs) in snapshots {
+            if kept >= count {
+                break;
+            }
+            let current = period(secs);
+            if last != Some(current) {
+                keep.insert(name);
+                last = Some(current);
+                kept += 1;
+            }
+        }
+    }
+    keep
+}
+
+/// Wait for a snapshot task to complete, report the result.
+///
+/// A failure to start the task or to obtain its status is only reported, so that
+/// the rest of the VMs may still be processed.
+async fn wait_for(api: &Proxmoxy, vmid: u32, action: &str, started: PmResult<Upid>) -> bool {
+    let upid = match started {
+        Ok(upid) => upid,
+        Err(err) => {
+            warn!(
+                "VM {vmid}: {action}: could not start the task: {err:#}",
+                err = AnyError::from(Error::Api(err))
+            );
+            return false;
+        }
+    };
+    match api.wait_task(&upid, TASK_TIMEOUT).await {
+        Ok(status) if status.succeeded() => {
+            info!("VM {vmid}: {action}: done");
+            true
+        }
+        Ok(status) => {
+            warn!(
+                "VM {vmid}: {action}: failed: {exitstatus}",
+                exitstatus = status.exitstatus().unwrap_or("(no exit status)")
+            );
+            false
+        }
+        Err(PmError::TimedOut(_)) => {
+            warn!(
+                "VM {vmid}: {action}: did not complete within {secs} seconds, task {upid}",
+                secs = TASK_TIMEOUT.as_secs(),
+                upid = upid.as_str()
+            );
+            false
+        }
+        Err(err) => {
+            warn!(
+                "VM {vmid}: {action}: could not obtain the task status: {err:#}",
+                err = AnyError::from(Error::Api(err))
+            );
+            false
+        }
+    }
+}
+
+/// Find the selected VMs that have a snapshot with the specified name.
+///
+/// The VMs that the snapshots could not be listed for are reported and skipped;
+/// the returned flag indicates whether there were any.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the VMs could not be selected.
+async fn select_with_snapshot(
+    api: &Proxmoxy,
+    guests: &GuestSelector,
+    name: &str,
+) -> Result<(Vec<SelectedVm>, bool)> {
+    let mut res = Vec::new();
+    let mut problems = false;
+    for sel in guests::select(api, guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let snapshots = match api
+            .get(api.path().nodes().id(&sel.node).qemu().id(vmid).snapshot())
+            .await
+        {
+            Ok(snapshots) => snapshots,
+            Err(err) => {
+                warn!(
+                    "VM {vmid}: could not list the snapshots: {err:#}",
+                    err = AnyError::from(Error::Api(err))
+                );
+                problems = true;
+                continue;
+            }
+        };
+        if snapshots.iter().any(|snap| snap.name() == name) {
+            res.push(sel);
+        } else {
+            info!("VM {vmid}: no '{name}' snapshot");
+        }
+    }
+    Ok((res, problems))
+}
+
+/// List the snapshots of the selected VMs.
+pub async fn cmd_snapshot_list(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    format: Format,
+) -> Result<MainExit> {
+    let mut snapshots = Vec::new();
+    for sel in guests::select_with_pools(api, &guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let mut vm_snapshots: Vec<VmSnapshot> = api
+            .get(api.path().nodes().id(&sel.node).qemu().id(vmid).snapshot())
+            .await
+            .map_err(Error::Api)?
+            .into_iter()
+            .filter(|snap| !snap.is_current())
+            .collect();
+        vm_snapshots.sort_by(|first, second| {
+            first
+                .snaptime()
+                .cmp(&second.snaptime())
+                .then(first.name().cmp(second.name()))
+        });
+        snapshots.extend(vm_snapshots.into_iter().map(|snap| SnapshotInfo {
+            vmid,
+            node: sel.node.clone(),
+            pool: sel.pool.clone(),
+            tags: sel.sorted_tags(),
+            name: snap.name().to_owned(),
+            snaptime: snap.snaptime(),
+            vmstate: snap.vmstate(),
+            parent: snap.parent().map(ToOwned::to_owned),
+            description: snap.description().trim().to_owned(),
+        }));
+    }
+
+    if format == Format::Json {
+        output::print_json(&snapshots)?;
+    } else {
+        let mut table = Table::new(&[
+            "vmid",
+            "node",
+            "pool",
+            "tags",
+            "name",
+            "snaptime",
+            "vmstate",
+            "parent",
+            "description",
+        ]);
+        for snap in snapshots {
+            table.push(vec![
+                snap.vmid.to_string(),
+                snap.node,
+                snap.pool.unwrap_or_default(),
+                snap.tags.join(";"),
+                snap.name,
+                snap.snaptime
+                    .map_or_else(String::new, |secs| output::format_time(secs, format)),
+                if snap.vmstate { "yes" } else { "no" }.to_owned(),
+                snap.parent.unwrap_or_default(),
+                snap.description,
+            ]);
+        }
+        table.print(format);
+    }
+    Ok(MainExit::Ok)
+}
+
+/// Take a snapshot of the selected VMs.
+pub async fn cmd_snapshot_create(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    name: String,
+    description: Option<String>,
+    vmstate: bool,
+) -> Result<MainExit> {
+    let mut params = vec![("snapname", name.as_str())];
+    if let Some(ref desc) = description {
+        params.push(("description", desc.as_str()));
+    }
+    if vmstate {
+        params.push(("vmstate", "1"));
+    }
+
+    let mut problems = false;
+    for sel in guests::select(api, &guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let action = format!("creating the '{name}' snapshot");
+        let started = api
+            .post(
+                api.path().nodes().id(&sel.node).qemu().id(vmid).snapshot(),
+                &params,
+            )
+            .await;
+        problems = !wait_for(api, vmid, &action, started).await || problems;
+    }
+    Ok(MainExit::from_failures(problems))
+}
+
+/// Delete a snapshot of the selected VMs.
+pub async fn cmd_snapshot_delete(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    name: String,
+) -> Result<MainExit> {
+    let (selected, mut problems) = select_with_snapshot(api, &guests, &name).await?;
+    for sel in selected {
+        let vmid = sel.vm.vmid();
+        let action = format!("deleting the '{name}' snapshot");
+        let started = api
+            .delete(
+                api.path()
+                    .nodes()
+                    .id(&sel.node)
+                    .qemu()
+                    .id(vmid)
+                    .snapshot()
+                    .id(&name),
+                &[],
+            )
+            .await;
+        problems = !wait_for(api, vmid, &action, started).await || problems;
+    }
+    Ok(MainExit::from_failures(problems))
+}
+
+/// Roll the selected VMs back to a snapshot.
+pub async fn cmd_snapshot_rollback(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    name: String,
+) -> Result<MainExit> {
+    let (selected, mut problems) = select_with_snapshot(api, &guests, &name).await?;
+    for sel in selected {
+        let vmid = sel.vm.vmid();
+        let action = format!("rolling back to the '{name}' snapshot");
+        let started = api
+            .post(
+                api.path()
+                    .nodes()
+                    .id(&sel.node)
+                    .qemu()
+                    .id(vmid)
+                    .snapshot()
+                    .id(&name)
+                    .rollback(),
+                &[],
+            )
+            .await;
+        problems = !wait_for(api, vmid, &action, started).await || problems;
+    }
+    Ok(MainExit::from_failures(problems))
+}
+
+/// Delete the old scheduled snapshots of the selected VMs according to a retention policy.
+///
+/// Only the snapshots with names that match the pattern and that are known to `StorPool`
+/// (the snapshots of all their `StorPool` disks are tagged with `pve-snap`) are considered.
+pub async fn cmd_snapshot_prune(
+    cfg: &Config,
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    prefix: String,
+    pattern: String,
+    policy: RetentionPolicy,
+    dry_run: bool,
+) -> Result<MainExit> {
+    let name_pattern = NamePattern::new(&prefix, &pattern)?;
+    let sp_api = cfg.get_storpool_api()?;
+
+    let storage = check::get_storage(api).await?;
+    let sp_names: HashSet<&str> = storage
+        .values()
+        .filter(|store| store.as_storpool().is_some())
+        .map(StorageConfig::storage)
+        .collect();
+    let mut sp_counts: HashMap<(u32, String), usize> = HashMap::new();
+    for snap in sp_api.snapshots().await? {
+        let ours = snap
+            .tags
+            .get("pve")
+            .map_or(false, |store| sp_names.contains(store.as_str()));
+        let vmstate = snap.tags.get("pve-disk").map(String::as_str) == Some(VMSTATE_DISK);
+        if let (true, false, Some(owner)) = (ours, vmstate, snapshot_owner(&snap)) {
+            let count = sp_counts.entry(owner).or_default();
+            *count = count.saturating_add(1);
+        }
+    }
+    debug!(
+        "Got {count} tagged StorPool VM snapshot(s)",
+        count = sp_counts.len()
+    );
+
+    let mut problems = false;
+    for sel in guests::select(api, &guests).await?.vms {
+        let vmid = sel.vm.vmid();
+        let path_snapshots = api.path().nodes().id(&sel.node).qemu().id(vmid).snapshot();
+        let vm_snapshots = match api.get(path_snapshots.clone()).await {
+            Ok(vm_snapshots) => vm_snapshots,
+            Err(err) => {
+                warn!(
+                    "VM {vmid}: could not list the snapshots: {err:#}",
+                    err = AnyError::from(Error::Api(err))
+                );
+                problems = true;
+                continue;
+            }
+        };
+        let mut ours: Vec<(&str, u64)> = Vec::new();
+        for snap in &vm_snapshots {
+            let name = snap.name();
+            let secs = match name_pattern.timestamp(name) {
+                Some(secs) => secs,
+                None => continue,
+            };
+            let snap_cfg = match api.get(path_snapshots.clone().id(name).config()).await {
+                Ok(snap_cfg) => snap_cfg,
+                Err(err) => {
+                    warn!(
+                        "VM {vmid}: could not get the configuration of the '{name}' snapshot: {err:#}",
+                        err = AnyError::from(Error::Api(err))
+                    );
+                    problems = true;
+                    continue;
+                }
+            };
+            let expected = sp_disk_count(&snap_cfg, &sp_names);
+            let found = sp_counts
+                .get(&(vmid, name.to_owned()))
+                .copied()
+                .unwrap_or_default();
+            if expected == 0 || found < expected {
+                debug!(
+                    "VM {vmid}: {found} StorPool snapshot(s) tagged with pve-snap={name} for {expected} StorPool disk(s), ignoring it"
+                );
+                continue;
+            }
+            ours.push((name, secs));
+        }
+        ours.sort_by(|first, second| second.1.cmp(&first.1).then(second.0.cmp(first.0)));
+
+        let keep = retained(&ours, &policy);
+        for &(name, _) in &ours {
+            if keep.contains(name) {
+                if dry_run {
+                    output::emit(&format!("VM {vmid}: keep {name}"));
+                }
+                continue;
+            }
+            if dry_run {
+                output::emit(&format!("VM {vmid}: delete {name}"));
+                continue;
+            }
+
+            let action = format!("deleting the '{name}' snapshot");
+            let started = api.delete(path_snapshots.clone().id(name), &[]).await;
+            problems = !wait_for(api, vmid, &action, started).await || problems;
+        }
+    }
+    Ok(MainExit::from_failures(problems))
+}
@@ -0,0 +1,400 @@
+//! Display an overview of the state of a Proxmox VE cluster and its `StorPool` storage.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use anyhow::Error as AnyError;
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use proxmoxy::types::{NodeStatus, NodeStorageStatus, NodeSummary, VmStatus};
+use proxmoxy::Proxmoxy;
+
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::output::{self, Format, Table};
+use crate::storpool::StorPool;
+use crate::MainExit;
+
+/// The state of a single Proxmox VE node.
+#[derive(Debug, Serialize)]
+struct NodeInfo {
+    /// The name of the node.
+    node: String,
+
+    /// The status of the node in the cluster.
+    status: String,
+
+    /// The CPU utilization, from 0 to 1.
+    cpu: Option<f64>,
+
+    /// The number of available CPUs.
+    maxcpu: Option<u32>,
+
+    /// The used memory in bytes.
+    mem: Option<u64>,
+
+    /// The available memory in bytes.
+    maxmem: Option<u64>,
+
+    /// The node uptime in seconds.
+    uptime: Option<u64>,
+}
+
+/// The usage of a single `storpool` storage as reported by Proxmox VE.
+#[derive(Debug, Serialize)]
+struct StorageInfo {
+    /// The name of the Proxmox VE storage.
+    storage: String,
+
+    /// The name of the `StorPool` template.
+    template: String,
+
+    /// The total size of the storage.
+    total: Option<u64>,
+
+    /// The used space.
+    used: Option<u64>,
+
+    /// The available space.
+    avail: Option<u64>,
+}
+
+/// The number of VMs in each state.
+#[derive(Debug, Default, Serialize)]
+struct VmCounts {
+    /// The number of running VMs.
+    running: usize,
+
+    /// The number of stopped VMs.
+    stopped: usize,
+}
+
+/// A VM with a configuration lock.
+#[derive(Debug, Serialize)]
+struct LockedVm {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// The node that the VM is on.
+    node: String,
+
+    /// The type of the lock, e.g. "backup" or "migrate".
+    lock: String,
+}
+
+/// The status of a single `StorPool` service.
+#[derive(Debug, Serialize)]
+struct ServiceInfo {
+    /// The type of the service: "mgmt", "server", or "client".
+    service: &'static str,
+
+    /// The `StorPool` ID of the node that the service runs on.
+    id: u32,
+
+    /// The current status of the service, e.g. "running".
+    status: String,
+}
+
+/// The state of a single `StorPool` disk.
+#[derive(Debug, Serialize)]
+struct DiskInfo {
+    /// The `StorPool` ID of the disk.
+    id: u32,
+
+    /// The `StorPool` ID of the server that the disk belongs to.
+    server_id: u32,
+
+    /// Is the disk currently part of the cluster?
+    up: bool,
+
+    /// Is the disk being ejected?
+    soft_eject: Option<String>,
+}
+
+/// The health of the `StorPool` cluster.
+#[derive(Debug, Serialize)]
+struct StorPoolHealth {
+    /// The `StorPool` services.
+    services: Vec<ServiceInfo>,
+
+    /// The `StorPool` disks.
+    disks: Vec<DiskInfo>,
+}
+
+/// The full cluster status report.
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    /// The Proxmox VE nodes.
+    nodes: Vec<NodeInfo>,
+
+    /// The `storpool` storages.
+    storage: Vec<StorageInfo>,
+
+    /// The number of VMs in each state on the online nodes.
+    vms: VmCounts,
+
+    /// The VMs with a configuration lock.
+    locked: Vec<LockedVm>,
+
+    /// The health of the `StorPool` cluster, if its API could be queried.
+    storpool: Option<StorPoolHealth>,
+}
+
+/// Format an uptime in seconds for display in a table, e.g. "12d 03:45".
+fn format_uptime(secs: Option<u64>, format: Format) -> String {
+    match secs {
+        Some(secs) if format == Format::Table => output::format_duration(secs),
+        Some(secs) => secs.to_string(),
+        None if format == Format::Table => "-".to_owned(),
+        None => String::new(),
+    }
+}
+
+/// Format the CPU utilization for display in a table, e.g. "12.5%".
+#[allow(clippy::float_arithmetic)]
+fn format_cpu(cpu: Option<f64>, format: Format) -> String {
+    match cpu {
+        Some(cpu) if format == Format::Table => format!("{pct:.1}%", pct = cpu * 100.0),
+        Some(cpu) => cpu.to_string(),
+        None if format == Format::Table => "-".to_owned(),
+        None => String::new(),
+    }
+}
+
+impl StatusReport {
+    /// Display the report in the specified format.
+    fn print(&self, format: Format) -> Result<()> {
+        if format == Format::Json {
+            return output::print_json(self);
+        }
+
+        let mut nodes = Table::new(&["node", "status", "cpu", "maxcpu", "mem", "maxmem", "uptime"]);
+        for item in &self.nodes {
+            nodes.push(vec![
+                item.node.clone(),
+                item.status.clone(),
+                format_cpu(item.cpu, format),
+                item.maxcpu.map(|cpus| cpus.to_string()).unwrap_or_default(),
+                output::format_opt_size(item.mem, format),
+                output::format_opt_size(item.maxmem, format),
+                format_uptime(item.uptime, format),
+            ]);
+        }
+        nodes.print(format);
+        output::emit("");
+
+        let mut storage = Table::new(&["storage", "template", "total", "used", "avail"]);
+        for item in &self.storage {
+            storage.push(vec![
+                item.storage.clone(),
+                item.template.clone(),
+                output::format_opt_size(item.total, format),
+                output::format_opt_size(item.used, format),
+                output::format_opt_size(item.avail, format),
+            ]);
+        }
+        storage.print(format);
+        output::emit("");
+
+        let mut vms = Table::new(&["running", "stopped", "locked"]);
+        vms.push(vec![
+            self.vms.running.to_string(),
+            self.vms.stopped.to_string(),
+            self.locked.len().to_string(),
+        ]);
+        vms.print(format);
+
+        if !self.locked.is_empty() {
+            output::emit("");
+            let mut locked = Table::new(&["vmid", "node", "lock"]);
+            for item in &self.locked {
+                locked.push(vec![
+                    item.vmid.to_string(),
+                    item.node.clone(),
+                    item.lock.clone(),
+                ]);
+            }
+            locked.print(format);
+        }
+
+        if let Some(ref sp_health) = self.storpool {
+            output::emit("");
+            let mut services = Table::new(&["service", "id", "status"]);
+            for item in &sp_health.services {
+                services.push(vec![
+                    item.service.to_owned(),
+                    item.id.to_string(),
+                    item.status.clone(),
+                ]);
+            }
+            services.print(format);
+            output::emit("");
+
+            let mut disks = Table::new(&["disk", "server", "up", "soft_eject"]);
+            for item in &sp_health.disks {
+                disks.push(vec![
+                    item.id.to_string(),
+                    item.server_id.to_string(),
+                    item.up.to_string(),
+                    item.soft_eject.clone().unwrap_or_default(),
+                ]);
+            }
+            disks.print(format);
+        }
+        Ok(())
+    }
+}
+
+/// Query the `StorPool` API for the state of the services and disks.
+async fn storpool_health(sp_api: &StorPool) -> Result<StorPoolHealth> {
+    let services_list = sp_api.services().await?;
+    let services = [
+        ("mgmt", &services_list.mgmt),
+        ("server", &services_list.servers),
+        ("client", &services_list.clients),
+    ]
+    .into_iter()
+    .flat_map(|(service, items)| {
+        items
+            .values()
+            .sorted_by_key(|item| item.id)
+            .map(move |item| ServiceInfo {
+                service,
+                id: item.id,
+                status: item.status.clone(),
+            })
+    })
+    .collect();
+    let disks = sp_api
+        .disks()
+        .await?
+        .into_values()
+        .sorted_by_key(|disk| disk.id)
+        .map(|disk| DiskInfo {
+            id: disk.id,
+            server_id: disk.server_id,
+            up: disk.up,
+            soft_eject: disk.soft_eject,
+        })
+        .collect();
+    Ok(StorPoolHealth { services, disks })
+}
+
+/// Query the capacity of the `StorPool`-backed storage on one of the online nodes.
+async fn storage_info(api: &Proxmoxy, online: &[&str]) -> Result<Vec<StorageInfo>> {
+    let mut storage = Vec::new();
+    for store in api
+        .get(api.path().storage())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .filter(|store| store.as_storpool().is_some())
+        .sorted_by(|first, second| first.storage().cmp(second.storage()))
+    {
+        let name = store.storage();
+        let status = if let Some(node) = online.iter().find(|node| store.common().enabled_on(node))
+        {
+            Some(
+                api.get(api.path().nodes().id(node).storage().id(name).status())
+                    .await
+                    .map_err(Error::Api)?,
+            )
+        } else {
+            warn!("Storage {name}: not enabled on any online node");
+            None
+        };
+        storage.push(StorageInfo {
+            storage: name.to_owned(),
+            template: store
+                .as_storpool()
+                .map_or(name, |sp_store| sp_store.template_name())
+                .to_owned(),
+            total: status.as_ref().and_then(NodeStorageStatus::total),
+            used: status.as_ref().and_then(NodeStorageStatus::used),
+            avail: status.as_ref().and_then(NodeStorageStatus::avail),
+        });
+    }
+    Ok(storage)
+}
+
+/// Display the state of the cluster's nodes, storage, VMs, and `StorPool` services.
+pub async fn cmd_status(cfg: &Config, api: &Proxmoxy, format: Format) -> Result<MainExit> {
+    let all_nodes: Vec<_> = api
+        .get(api.path().nodes())
+        .await
+        .map_err(Error::Api)?
+        .into_iter()
+        .sorted_by(|first, second| first.node().cmp(second.node()))
+        .collect();
+    let online: Vec<&str> = all_nodes
+        .iter()
+        .filter(|node| node.status() == NodeStatus::Online)
+        .map(NodeSummary::node)
+        .collect();
+    let nodes = all_nodes
+        .iter()
+        .map(|node| NodeInfo {
+            node: node.node().to_owned(),
+            status: node.status().as_ref().to_owned(),
+            cpu: node.cpu(),
+            maxcpu: node.maxcpu(),
+            mem: node.mem(),
+            maxmem: node.maxmem(),
+            uptime: node.uptime(),
+        })
+        .collect();
+
+    let storage = storage_info(api, &online).await?;
+
+    let mut vms = VmCounts::default();
+    let mut locked = Vec::new();
+    for node in &online {
+        for vm in api
+            .get(api.path().nodes().id(node).qemu())
+            .await
+            .map_err(Error::Api)?
+        {
+            match vm.status() {
+                VmStatus::Running => vms.running = vms.running.saturating_add(1),
+                VmStatus::Stopped => vms.stopped = vms.stopped.saturating_add(1),
+            }
+            if let Some(lock) = vm.lock() {
+                locked.push(LockedVm {
+                    vmid: vm.vmid(),
+                    node: (*node).to_owned(),
+                    lock: lock.to_owned(),
+                });
+            }
+        }
+    }
+    locked.sort_by_key(|item| item.vmid);
+
+    let storpool = match cfg.get_storpool_api() {
+        Ok(sp_api) => match storpool_health(&sp_api).await {
+            Ok(sp_health) => Some(sp_health),
+            Err(err) => {
+                warn!(
+                    "Could not query the StorPool API: {err:#}",
+                    err = AnyError::from(err)
+                );
+                None
+            }
+        },
+        Err(Error::StorPoolNotConfigured(_)) => {
+            debug!("No StorPool API settings, not checking the StorPool health");
+            None
+        }
+        Err(err) => return Err(err),
+    };
+
+    StatusReport {
+        nodes,
+        storage,
+        vms,
+        locked,
+        storpool,
+    }
+    .print(format)?;
+    Ok(MainExit::Ok)
+}
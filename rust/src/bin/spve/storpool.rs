@@ -0,0 +1,388 @@
+//! A minimal client for the `StorPool` JSON-over-HTTP API.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Client, ClientBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use proxmoxy::JsonValue;
+
+use crate::config::TimeoutsSnippet;
+use crate::defs::{Error, Result};
+
+/// How long to wait for a connection to the `StorPool` API unless configured.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a `StorPool` API request to complete unless configured.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Recognize the `StorPool` global ID at the end of a Proxmox VE volume ID.
+const RE_VOLID_GLOBAL_ID: &str = r"(?x)
+    -sp-
+    (?P<global_id> [a-z0-9]+ \. [a-z0-9]+ \. [a-z0-9]+ )
+    \. (?: raw | iso )
+    $
+";
+
+/// The `StorPool` volume tags set by the Proxmox VE plugin itself.
+pub const RESERVED_TAGS: [&str; 10] = [
+    "virt",
+    "pve",
+    "pve-base",
+    "pve-comment",
+    "pve-disk",
+    "pve-loc",
+    "pve-snap",
+    "pve-snap-v",
+    "pve-type",
+    "pve-vm",
+];
+
+/// The access rights for a `StorPool` volume or snapshot attached to a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Rights {
+    /// Read-only access.
+    #[serde(rename = "ro")]
+    ReadOnly,
+
+    /// Read-write access.
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+impl AsRef<str> for Rights {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        match *self {
+            Self::ReadOnly => "ro",
+            Self::ReadWrite => "rw",
+        }
+    }
+}
+
+/// A single `StorPool` volume or snapshot attached to a `StorPool` client.
+#[derive(Debug, Deserialize)]
+pub struct Attachment {
+    /// The `StorPool` client ID.
+    pub client: u32,
+
+    /// The name of the attached volume or snapshot.
+    pub volume: String,
+
+    /// Is this a snapshot rather than a volume?
+    #[serde(default)]
+    pub snapshot: bool,
+
+    /// The access rights of the client.
+    pub rights: Rights,
+}
+
+/// The space used by the volumes and snapshots created from a `StorPool` template.
+#[derive(Debug, Deserialize)]
+pub struct TemplateStored {
+    /// The total space available for this template's data, in bytes.
+    pub capacity: u64,
+
+    /// The free space for this template's data, in bytes.
+    pub free: u64,
+}
+
+/// The status of a `StorPool` volume template.
+#[derive(Debug, Deserialize)]
+pub struct TemplateStatus {
+    /// The name of the template.
+    pub name: String,
+
+    /// The total provisioned size of the template's volumes, in bytes.
+    #[serde(default)]
+    pub size: u64,
+
+    /// The space actually taken up by the template's volumes and snapshots, in bytes.
+    #[serde(default, rename = "storedSize")]
+    pub stored_size: u64,
+
+    /// The capacity available to the template.
+    pub stored: Option<TemplateStored>,
+}
+
+/// A `StorPool` volume or snapshot.
+#[derive(Debug, Deserialize)]
+pub struct Volume {
+    /// The name of the volume, `~` followed by the global ID for unnamed ones.
+    pub name: String,
+
+    /// The provisioned size of the volume, in bytes.
+    pub size: u64,
+
+    /// The tags set for this volume.
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+
+    /// The global ID of the volume.
+    #[serde(default, rename = "globalId")]
+    pub global_id: Option<String>,
+
+    /// The name of the template that the volume was created from.
+    #[serde(default, rename = "templateName")]
+    pub template_name: Option<String>,
+
+    /// The placement group for all the replicas, unless overridden below.
+    #[serde(default, rename = "placeAll")]
+    pub place_all: Option<String>,
+
+    /// The placement group for the last replica.
+    #[serde(default, rename = "placeTail")]
+    pub place_tail: Option<String>,
+
+    /// The placement group for the first replica.
+    #[serde(default, rename = "placeHead")]
+    pub place_head: Option<String>,
+
+    /// For a snapshot, the name of the volume that it was taken of.
+    #[serde(default, rename = "onVolume")]
+    pub snapshot_of: Option<String>,
+}
+
+/// The status of a single `StorPool` service.
+#[derive(Debug, Deserialize)]
+pub struct ServiceStatus {
+    /// The `StorPool` ID of the node that the service runs on.
+    pub id: u32,
+
+    /// The current status of the service, e.g. "running".
+    pub status: String,
+}
+
+/// The `StorPool` services running in the cluster.
+#[derive(Debug, Deserialize)]
+pub struct ServicesList {
+    /// The `StorPool` block device clients, keyed by `StorPool` ID.
+    pub clients: HashMap<String, ServiceStatus>,
+
+    /// The `StorPool` servers, keyed by `StorPool` ID.
+    #[serde(default)]
+    pub servers: HashMap<String, ServiceStatus>,
+
+    /// The `StorPool` management services, keyed by `StorPool` ID.
+    #[serde(default)]
+    pub mgmt: HashMap<String, ServiceStatus>,
+}
+
+/// A single `StorPool` disk.
+#[derive(Debug, Deserialize)]
+pub struct Disk {
+    /// The `StorPool` ID of the disk.
+    pub id: u32,
+
+    /// The `StorPool` ID of the server that the disk belongs to.
+    #[serde(rename = "serverId")]
+    pub server_id: u32,
+
+    /// Is the disk currently part of the cluster?
+    pub up: bool,
+
+    /// Is the disk being ejected: "on", "off", or "paused".
+    #[serde(default, rename = "softEject")]
+    pub soft_eject: Option<String>,
+}
+
+/// The raw response returned by the `StorPool` API.
+#[derive(Debug, Deserialize)]
+struct RawResponse<T> {
+    /// The requested data, if the query succeeded.
+    data: Option<T>,
+
+    /// The error returned by the API, if any.
+    error: Option<JsonValue>,
+}
+
+/// Send queries to the `StorPool` API.
+#[derive(Debug)]
+pub struct StorPool {
+    /// The HTTP client used to send the requests.
+    client: Client,
+
+    /// The base API URL, e.g. `http://10.1.2.3:81/ctrl/1.0`.
+    url: String,
+}
+
+impl StorPool {
+    /// Prepare to send requests to the `StorPool` API.
+    ///
+    /// The cluster's timeouts apply to the `StorPool` API, too.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::StorPool`] if the HTTP client could not be initialized.
+    pub fn new(url: &str, token: &str, timeouts: &TimeoutsSnippet) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        let mut auth_hdr = HeaderValue::try_from(format!("Storpool v1:{token}"))
+            .context("Could not build the StorPool Authorization header")
+            .map_err(Error::StorPool)?;
+        auth_hdr.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, auth_hdr);
+        let client = ClientBuilder::new()
+            .default_headers(headers)
+            .connect_timeout(
+                timeouts
+                    .connect
+                    .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs),
+            )
+            .timeout(
+                timeouts
+                    .request
+                    .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_secs),
+            )
+            .build()
+            .context("Could not build the StorPool HTTP client")
+            .map_err(Error::StorPool)?;
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Send a GET request to the `StorPool` API, deserialize the returned data.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::StorPool`] if the request fails or the API returns an error.
+    pub async fn get<T: DeserializeOwned>(&self, query: &str) -> Result<T> {
+        let url = format!("{base}/{query}", base = self.url);
+        let resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("The StorPool GET request for {url} failed"))
+            .map_err(Error::StorPool)?;
+        let status = resp.status();
+        let raw_bytes = resp
+            .bytes()
+            .await
+            .with_context(|| format!("Could not receive the StorPool response for {url}"))
+            .map_err(Error::StorPool)?;
+        if !status.is_success() {
+            return Err(Error::StorPool(
+                match serde_json::from_slice::<RawResponse<JsonValue>>(&raw_bytes) {
+                    Ok(RawResponse {
+                        error: Some(err), ..
+                    }) => anyhow!("The StorPool API returned {status} for {query}: {err}"),
+                    _ => anyhow!("The StorPool API returned {status} for {query}"),
+                },
+            ));
+        }
+        let resp: RawResponse<T> = serde_json::from_slice(&raw_bytes)
+            .with_context(|| format!("Could not decode the StorPool response for {url}"))
+            .map_err(Error::StorPool)?;
+        match resp {
+            RawResponse {
+                error: Some(err), ..
+            } => Err(Error::StorPool(anyhow!(
+                "The StorPool API returned an error for {query}: {err}"
+            ))),
+            RawResponse {
+                data: Some(data), ..
+            } => Ok(data),
+            RawResponse { .. } => Err(Error::StorPool(anyhow!(
+                "The StorPool API returned neither data nor an error for {query}"
+            ))),
+        }
+    }
+
+    /// List the volumes and snapshots attached to `StorPool` clients.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn attachments(&self) -> Result<Vec<Attachment>> {
+        self.get("AttachmentsList").await
+    }
+
+    /// List the `StorPool` snapshots.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn snapshots(&self) -> Result<Vec<Volume>> {
+        self.get("SnapshotsList").await
+    }
+
+    /// List the `StorPool` volumes.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn volumes(&self) -> Result<Vec<Volume>> {
+        self.get("VolumesList").await
+    }
+
+    /// List the `StorPool` services: clients, servers, etc.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn services(&self) -> Result<ServicesList> {
+        self.get("ServicesList").await
+    }
+
+    /// List the `StorPool` disks, keyed by `StorPool` disk ID.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn disks(&self) -> Result<HashMap<String, Disk>> {
+        self.get("DisksList").await
+    }
+
+    /// Get the status of the `StorPool` volume templates.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from [`StorPool::get`].
+    pub async fn templates_status(&self) -> Result<Vec<TemplateStatus>> {
+        self.get("VolumeTemplatesStatus").await
+    }
+}
+
+/// Extract the `StorPool` global IDs from Proxmox VE volume IDs.
+#[derive(Debug)]
+pub struct GlobalIdDecoder {
+    /// The compiled [`RE_VOLID_GLOBAL_ID`] regular expression.
+    re_volid: Regex,
+}
+
+impl GlobalIdDecoder {
+    /// Compile the regular expression once for all the volume IDs to be examined.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Internal`] if the regular expression could not be compiled.
+    pub fn new() -> Result<Self> {
+        let re_volid = Regex::new(RE_VOLID_GLOBAL_ID).map_err(|err| {
+            Error::Internal(format!(
+                "Could not build the volume ID regular expression: {err}"
+            ))
+        })?;
+        Ok(Self { re_volid })
+    }
+
+    /// Extract the global ID from a volume ID (e.g. `vm-616-disk-0-sp-4.1.a.raw`).
+    ///
+    /// Returns `None` for volumes that do not carry a global ID in their name
+    /// (e.g. `vm-616-cloudinit.raw`).
+    #[must_use]
+    pub fn decode(&self, volid: &str) -> Option<String> {
+        self.re_volid
+            .captures(volid)
+            .and_then(|caps| caps.name("global_id"))
+            .map(|global_id| global_id.as_str().to_owned())
+    }
+}
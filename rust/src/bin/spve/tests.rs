@@ -0,0 +1,608 @@
+//! Unit-style tests for the spve command-line tool.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process;
+
+use anyhow::Result;
+use regex::Regex;
+use serde_json::json;
+
+use proxmoxy::types::NodeSummary;
+
+use crate::check::snapshots::snapshot_owner;
+use crate::check::storage::{self, RE_TAG_PATTERN};
+use crate::cli::{MigrateTarget, RetentionPolicy};
+use crate::config::{self, v0_1, AuthCluster, CheckPolicy, SecretSources};
+use crate::guests::SelectedVm;
+use crate::migrate::{self, Guest};
+use crate::output::{self, Captured, Format, Table};
+use crate::snapshot::{self, NamePattern};
+use crate::storpool::Volume;
+
+/// Build a timestamp from a UTC date and time.
+const fn at(date: (u64, u64, u64), hour: u64, min: u64) -> u64 {
+    snapshot::days_from_civil(date.0, date.1, date.2) * 86400 + hour * 3600 + min * 60
+}
+
+#[test]
+fn test_days_from_civil() {
+    for &(date, expected) in &[
+        ((1970, 1, 1), 0),
+        ((1970, 12, 31), 364),
+        ((1971, 1, 1), 365),
+        ((2000, 2, 29), 11_016),
+        ((2000, 3, 1), 11_017),
+        ((2023, 12, 31), 19_722),
+        ((2024, 1, 1), 19_723),
+        ((2024, 2, 29), 19_782),
+        ((2024, 3, 1), 19_783),
+        ((2100, 2, 28), 47_540),
+        ((2100, 3, 1), 47_541),
+    ] {
+        assert_eq!(
+            snapshot::days_from_civil(date.0, date.1, date.2),
+            expected,
+            "{date:?}"
+        );
+    }
+}
+
+#[test]
+fn test_name_pattern() -> Result<()> {
+    let pattern = NamePattern::new("auto.", "%Y%m%d-%H%M")?;
+    for &(name, expected) in &[
+        ("auto.20240229-1230", Some(at((2024, 2, 29), 12, 30))),
+        ("auto.20240301-0000", Some(at((2024, 3, 1), 0, 0))),
+        ("auto.19700101-0001", Some(60)),
+        ("autox20240229-1230", None),
+        ("manual.20240229-1230", None),
+        ("auto.20240229-1230-1", None),
+        ("auto.2024029-1230", None),
+        ("auto.20241301-0000", None),
+        ("auto.20240100-0000", None),
+        ("auto.20240132-0000", None),
+        ("auto.20240230-0000", None),
+        ("auto.20230229-0000", None),
+        ("auto.21000229-0000", None),
+        ("auto.20000229-0000", Some(at((2000, 2, 29), 0, 0))),
+        ("auto.20240430-0000", Some(at((2024, 4, 30), 0, 0))),
+        ("auto.20240431-0000", None),
+        ("auto.20241231-0000", Some(at((2024, 12, 31), 0, 0))),
+        ("auto.20240101-2400", None),
+        ("auto.20240101-0060", None),
+        ("auto.19691231-2359", None),
+    ] {
+        assert_eq!(pattern.timestamp(name), expected, "{name}");
+    }
+
+    let pattern = NamePattern::new("daily-", "%Y-%m-%d_100%%")?;
+    assert_eq!(
+        pattern.timestamp("daily-2024-02-29_100%"),
+        Some(at((2024, 2, 29), 0, 0))
+    );
+    assert_eq!(pattern.timestamp("daily-2024-02-29_100"), None);
+
+    for bad in ["%H%M", "%Y%m", "%Y%m%d%q", "%Y%m%d%"] {
+        assert!(NamePattern::new("auto-", bad).is_err(), "{bad}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_retained() {
+    let day = (2024, 1, 10);
+    let snapshots = [
+        ("s6", at(day, 10, 50)),
+        ("s5", at(day, 10, 10)),
+        ("s4", at(day, 9, 59)),
+        ("s3", at(day, 9, 0)),
+        ("s2", at((2024, 1, 9), 23, 59)),
+        ("s1", at((2024, 1, 8), 0, 0)),
+        ("s0", at((2024, 1, 7), 23, 59)),
+    ];
+    let policy = |hourly, daily, weekly| RetentionPolicy {
+        hourly,
+        daily,
+        weekly,
+    };
+    let names = |names: &[&'static str]| names.iter().copied().collect::<HashSet<_>>();
+
+    for (hourly, daily, weekly, expected) in [
+        (0, 0, 0, names(&[])),
+        (1, 0, 0, names(&["s6"])),
+        (2, 0, 0, names(&["s6", "s4"])),
+        (3, 0, 0, names(&["s6", "s4", "s2"])),
+        (10, 0, 0, names(&["s6", "s4", "s2", "s1", "s0"])),
+        (0, 1, 0, names(&["s6"])),
+        (0, 2, 0, names(&["s6", "s2"])),
+        (0, 4, 0, names(&["s6", "s2", "s1", "s0"])),
+        // 2024-01-08 was a Monday, so s0 on the Sunday before is in the previous week.
+        (0, 0, 1, names(&["s6"])),
+        (0, 0, 2, names(&["s6", "s0"])),
+        (2, 2, 2, names(&["s6", "s4", "s2", "s0"])),
+    ] {
+        assert_eq!(
+            snapshot::retained(&snapshots, &policy(hourly, daily, weekly)),
+            expected,
+            "hourly {hourly} daily {daily} weekly {weekly}"
+        );
+    }
+}
+
+#[test]
+fn test_snapshot_owner() -> Result<()> {
+    let volume = |tags| -> Result<Volume> {
+        Ok(serde_json::from_value(json!({
+            "name": "~4.1.b",
+            "size": 1_073_741_824_u64,
+            "tags": tags,
+        }))?)
+    };
+
+    let tagged = volume(json!({"pve": "sp", "pve-vm": "616", "pve-snap": "before-upgrade"}))?;
+    assert_eq!(
+        snapshot_owner(&tagged),
+        Some((616, "before-upgrade".to_owned()))
+    );
+
+    let both = volume(json!({
+        "pve-vm": "616",
+        "pve-snap": "before-upgrade",
+        "pve-snap-v": "4.1.a",
+    }))?;
+    assert_eq!(
+        snapshot_owner(&both),
+        Some((616, "before-upgrade".to_owned()))
+    );
+
+    let parent_only = volume(json!({"pve": "sp", "pve-vm": "616", "pve-snap-v": "4.1.a"}))?;
+    assert_eq!(snapshot_owner(&parent_only), None);
+
+    let no_vm = volume(json!({"pve": "sp", "pve-snap": "before-upgrade"}))?;
+    assert_eq!(snapshot_owner(&no_vm), None);
+
+    let bad_vm = volume(json!({"pve-vm": "vm-616", "pve-snap": "before-upgrade"}))?;
+    assert_eq!(snapshot_owner(&bad_vm), None);
+    Ok(())
+}
+
+#[test]
+fn test_selected_vm_describe() -> Result<()> {
+    let selected = |pool: Option<&str>, tags: &str| -> Result<SelectedVm> {
+        Ok(SelectedVm {
+            node: "pve1".to_owned(),
+            pool: pool.map(str::to_owned),
+            vm: serde_json::from_value(json!({
+                "vmid": 616,
+                "status": "running",
+                "tags": tags,
+            }))?,
+        })
+    };
+
+    assert_eq!(selected(None, "")?.describe(), "VM 616");
+    assert_eq!(
+        selected(Some("team-a"), "")?.describe(),
+        "VM 616 (pool team-a)"
+    );
+    assert_eq!(
+        selected(None, "web;prod")?.describe(),
+        "VM 616 (tags prod,web)"
+    );
+    assert_eq!(
+        selected(Some("team-a"), "web;prod")?.describe(),
+        "VM 616 (pool team-a, tags prod,web)"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_config_upgrade() -> Result<()> {
+    let path = Path::new("spve.toml");
+    let old = r#"# Managed by hand.
+[format.version]
+major = 0
+minor = 0
+
+[spve.defaults]
+cluster = "pve"
+
+[spve.clusters.pve]
+api_mode = "https"
+# The main API endpoint.
+url = "https://pve1:8006"  # via the proxy
+
+[spve.clusters.pve.storpool]
+url = "http://sp:81/ctrl/1.0"
+nodes = { pve1 = 1 }
+"#;
+    assert_eq!(config::get_format_version(path, old)?, (0, 0));
+    let parsed = config::parse_global(path, old)?;
+
+    let new = v0_1::upgrade(old)?;
+    assert_eq!(
+        config::get_format_version(path, &new)?,
+        (0, config::FORMAT_MINOR_LATEST)
+    );
+    for line in [
+        "# Managed by hand.",
+        "# The main API endpoint.",
+        r#"endpoints = ["https://pve1:8006"]  # via the proxy"#,
+    ] {
+        assert!(new.lines().any(|new_line| new_line == line), "{line}");
+    }
+
+    let upgraded = config::parse_global(path, &new)?;
+    let (old_pve, new_pve) = (&parsed.spve.clusters["pve"], &upgraded.spve.clusters["pve"]);
+    assert_eq!(new_pve.endpoints, old_pve.endpoints);
+    assert!(!new_pve.tls.verify);
+    assert_eq!(
+        new_pve.storpool.as_ref().map(|sp_cfg| &sp_cfg.url),
+        old_pve.storpool.as_ref().map(|sp_cfg| &sp_cfg.url)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_config_parse() -> Result<()> {
+    let path = Path::new("spve.toml");
+    let global = r#"[format.version]
+major = 0
+minor = 2
+
+[spve.defaults]
+cluster = "pve"
+
+[spve.clusters.pve]
+api_mode = "https"
+endpoints = ["https://pve1:8006", "https://pve2:8006"]
+checks = { ha = "warn", snapshots = "skip" }
+
+[spve.clusters.pve.tls]
+ca_file = "/etc/spve/ca.pem"
+
+[spve.clusters.pve.storpool]
+url = "http://sp:81/ctrl/1.0"
+nodes = { pve1 = 1, pve2 = 2 }
+"#;
+    assert_eq!(config::get_format_version(path, global)?, (0, 2));
+    let parsed = config::parse_global(path, global)?;
+    assert_eq!(parsed.spve.defaults.cluster, "pve");
+    let pve = &parsed.spve.clusters["pve"];
+    assert_eq!(pve.endpoints, ["https://pve1:8006", "https://pve2:8006"]);
+    assert!(pve.tls.verify);
+    assert_eq!(
+        pve.tls.ca_file.as_deref(),
+        Some(Path::new("/etc/spve/ca.pem"))
+    );
+    assert_eq!(pve.timeouts.connect, None);
+    for &(check, expected) in &[
+        ("attachments", CheckPolicy::Fail),
+        ("ha", CheckPolicy::Warn),
+        ("snapshots", CheckPolicy::Skip),
+    ] {
+        assert_eq!(pve.checks.policy(check), expected, "{check}");
+    }
+    assert_eq!(
+        pve.storpool.as_ref().map(|sp_cfg| sp_cfg.nodes.get("pve2")),
+        Some(Some(&2))
+    );
+
+    for bad in [
+        global.replace("minor = 2", "minor = 99"),
+        global.replace("major = 0", "major = 1"),
+        global.replace("endpoints", "url"),
+        global.replace("\"skip\"", "\"ignore\""),
+        global.replace("ha = ", "high_availability = "),
+    ] {
+        assert!(config::parse_global(path, &bad).is_err(), "{bad}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_config_auth() -> Result<()> {
+    let path = Path::new("auth.toml");
+    let auth = r#"[format.version]
+major = 0
+minor = 2
+
+[clusters.pve]
+auth_type = "token"
+id = "root@pam!spve"
+value_env = "PVE_TOKEN"
+
+[clusters.lab]
+auth_type = "token"
+id = "root@pam!spve"
+value = "secret"
+value_file = "/etc/spve/lab.token"
+
+[storpool.pve]
+token_command = ["pass", "show", "storpool"]
+"#;
+    let parsed = config::parse_auth(path, auth)?;
+    let AuthCluster::Token(ref pve) = parsed.clusters["pve"];
+    assert_eq!(pve.id, "root@pam!spve");
+    let sources = pve.sources();
+    assert_eq!(sources.names(), ["value_env"]);
+    assert_eq!(sources.describe(), "environment variable PVE_TOKEN");
+
+    let AuthCluster::Token(ref lab) = parsed.clusters["lab"];
+    assert_eq!(lab.sources().names(), ["value", "value_file"]);
+    assert!(lab.sources().read("the lab token").is_err());
+
+    let sp_sources = parsed.storpool["pve"].sources();
+    assert_eq!(sp_sources.names(), ["token_command"]);
+    assert_eq!(sp_sources.describe(), "command pass show storpool");
+
+    assert!(parsed.storpool["pve"].token.is_none());
+
+    assert!(config::parse_auth(path, &auth.replace("\"token\"", "\"password\"")).is_err());
+    assert!(config::parse_auth(path, &auth.replace("minor = 2", "minor = 99")).is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_secret_sources() -> Result<()> {
+    fn sources<'data>(
+        file: Option<&'data Path>,
+        command: Option<&'data [String]>,
+    ) -> SecretSources<'data> {
+        SecretSources {
+            key: "value",
+            value: None,
+            file,
+            env: None,
+            command,
+        }
+    }
+
+    let path = env::temp_dir().join(format!("spve-test-{pid}.token", pid = process::id()));
+    fs::write(&path, "secret\n")?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+    let insecure = sources(Some(&path), None).read("the test token");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    let secure = sources(Some(&path), None).read("the test token");
+    fs::remove_file(&path)?;
+    if env::var_os("SPVE_ALLOW_INSECURE_AUTH").is_none() {
+        assert!(insecure.is_err());
+    }
+    assert_eq!(secure?, "secret");
+    assert!(sources(Some(&path), None).read("the test token").is_err());
+
+    let command = ["sh", "-c", "echo secret; echo 'a diagnostic message' 1>&2"].map(str::to_owned);
+    assert_eq!(
+        sources(None, Some(&command)).read("the test token")?,
+        "secret"
+    );
+    let failing = ["sh", "-c", "echo secret; exit 1"].map(str::to_owned);
+    assert!(sources(None, Some(&failing))
+        .read("the test token")
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_extra_tags() -> Result<()> {
+    let re_tag = Regex::new(RE_TAG_PATTERN)?;
+    for &(spec, problems) in &[
+        ("", false),
+        ("owner=team-a", false),
+        ("owner=team-a  tier=ssd.v2 zone=eu:1", false),
+        ("owner", true),
+        ("owner=team-a tier", true),
+        ("owner=team/a", true),
+        ("=ssd", true),
+        ("tier=", true),
+        ("pve=other", true),
+        ("virt=pve", true),
+    ] {
+        assert_eq!(
+            storage::check_extra_tags("sp", spec, &re_tag),
+            problems,
+            "{spec:?}"
+        );
+    }
+    Ok(())
+}
+
+/// Build the Proxmox VE node summaries from (name, online, used and total memory in GiB).
+fn nodes(nodes: &[(&str, bool, u64, u64)]) -> Result<Vec<NodeSummary>> {
+    Ok(serde_json::from_value(
+        nodes
+            .iter()
+            .map(|&(name, online, used, total)| {
+                json!({
+                    "type": "node",
+                    "node": name,
+                    "id": format!("node/{name}"),
+                    "status": if online { "online" } else { "offline" },
+                    "mem": used << 30,
+                    "maxmem": total << 30,
+                })
+            })
+            .collect(),
+    )?)
+}
+
+#[test]
+fn test_headroom() -> Result<()> {
+    let summaries = nodes(&[
+        ("pve1", true, 4, 16),
+        ("pve2", true, 2, 16),
+        ("pve3", false, 0, 64),
+        ("pve4", true, 20, 16),
+    ])?;
+    let expected: HashMap<String, u64> = [("pve2", 14_u64 << 30), ("pve4", 0)]
+        .into_iter()
+        .map(|(name, free)| (name.to_owned(), free))
+        .collect();
+    assert_eq!(migrate::headroom(&summaries, "pve1"), expected);
+    assert_eq!(migrate::headroom(&summaries, "pve5").len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_plan() {
+    let guests = |mems: &[(u32, u64)]| {
+        mems.iter()
+            .map(|&(vmid, mem)| Guest {
+                vmid,
+                name: format!("vm{vmid}"),
+                mem,
+            })
+            .collect::<Vec<_>>()
+    };
+    let headroom = |free: &[(&str, u64)]| {
+        free.iter()
+            .map(|&(name, mem)| (name.to_owned(), mem))
+            .collect::<HashMap<_, _>>()
+    };
+    let placed = |migrations: &[migrate::Migration]| {
+        migrations
+            .iter()
+            .map(|mig| (mig.vmid, mig.target.clone()))
+            .collect::<Vec<_>>()
+    };
+    let pair = |vmid: u32, node: &str| (vmid, node.to_owned());
+
+    // The largest VM goes to the node with the most free memory; ties go to the first name.
+    let (migrations, unplaced) = migrate::plan(
+        guests(&[(100, 8), (101, 6), (102, 4), (103, 4)]),
+        &MigrateTarget::Auto,
+        headroom(&[("pve2", 10), ("pve3", 10), ("pve4", 3)]),
+        |_, _| true,
+    );
+    assert_eq!(
+        placed(&migrations),
+        [pair(100, "pve2"), pair(101, "pve3"), pair(102, "pve3")]
+    );
+    assert_eq!(unplaced, [103]);
+
+    // The allowed function may reject a node that has enough memory.
+    let (migrations, unplaced) = migrate::plan(
+        guests(&[(100, 8), (101, 2)]),
+        &MigrateTarget::Auto,
+        headroom(&[("pve2", 10), ("pve3", 9)]),
+        |vmid, node| vmid != 100 || node != "pve2",
+    );
+    assert_eq!(placed(&migrations), [pair(100, "pve3"), pair(101, "pve2")]);
+    assert!(unplaced.is_empty());
+
+    // An explicit target node does not depend on the free memory.
+    let (migrations, unplaced) = migrate::plan(
+        guests(&[(100, 8), (101, 2)]),
+        &MigrateTarget::Node("pve4".to_owned()),
+        headroom(&[("pve4", 1)]),
+        |vmid, _| vmid != 101,
+    );
+    assert_eq!(placed(&migrations), [pair(100, "pve4")]);
+    assert_eq!(unplaced, [101]);
+}
+
+#[tokio::test]
+async fn test_print_clusters() -> Result<()> {
+    let table = |headers: &[&'static str], rows: &[[&str; 2]]| {
+        let mut table = Table::new(headers);
+        for row in rows {
+            table.push(row.iter().map(|&value| value.to_owned()).collect());
+        }
+        Captured::Csv(table)
+    };
+    let line = |text: &str| Captured::Line(text.to_owned());
+
+    let (res, captured) = output::capture(async {
+        output::print_clusters(vec![
+            (
+                "pve1".to_owned(),
+                vec![table(&["vmid", "pool"], &[["100", "team-a"], ["101", ""]])],
+            ),
+            ("pve2".to_owned(), Vec::new()),
+            (
+                "pve3".to_owned(),
+                vec![table(&["vmid", "pool"], &[["300", "team,b"]])],
+            ),
+        ])
+    })
+    .await;
+    res?;
+    assert_eq!(
+        captured,
+        [
+            line("cluster,vmid,pool"),
+            line("pve1,100,team-a"),
+            line("pve1,101,"),
+            line("pve3,300,\"team,b\""),
+        ]
+    );
+
+    let (res, captured) = output::capture(async {
+        output::print_clusters(vec![
+            (
+                "pve1".to_owned(),
+                vec![Captured::Json(json!({"vms": [100]}))],
+            ),
+            ("pve2".to_owned(), vec![line("Nothing to do")]),
+            ("pve3".to_owned(), vec![Captured::Json(json!({"vms": []}))]),
+        ])
+    })
+    .await;
+    res?;
+    assert_eq!(
+        captured,
+        [
+            line("==> pve2 <=="),
+            line("Nothing to do"),
+            Captured::Json(json!({"pve1": {"vms": [100]}, "pve3": {"vms": []}})),
+        ]
+    );
+
+    let (res, _) = output::capture(async {
+        output::print_clusters(vec![
+            ("pve1".to_owned(), vec![table(&["vmid", "pool"], &[])]),
+            ("pve2".to_owned(), vec![table(&["vmid", "node"], &[])]),
+        ])
+    })
+    .await;
+    assert!(res.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_format() {
+    for &(bytes, expected) in &[
+        (0, "0"),
+        (1023, "1023"),
+        (1024, "1.0K"),
+        (1536, "1.5K"),
+        (34_359_738_368, "32.0G"),
+        (u64::MAX, "16383.9P"),
+    ] {
+        assert_eq!(output::format_size(bytes, Format::Table), expected);
+        assert_eq!(output::format_size(bytes, Format::Csv), bytes.to_string());
+    }
+
+    for &(secs, expected) in &[
+        (0, "1970-01-01 00:00:00"),
+        (951_782_400, "2000-02-29 00:00:00"),
+        (1_709_209_845, "2024-02-29 12:30:45"),
+        (1_735_689_599, "2024-12-31 23:59:59"),
+        (u64::MAX, "584554051223-11-09 07:00:15"),
+    ] {
+        assert_eq!(output::format_time(secs, Format::Table), expected);
+        assert_eq!(output::format_time(secs, Format::Json), secs.to_string());
+    }
+
+    assert_eq!(output::format_duration(0), "0d 00:00");
+    assert_eq!(output::format_duration(200_000), "2d 07:33");
+    assert_eq!(output::format_duration(u64::MAX), "213503982334601d 07:00");
+}
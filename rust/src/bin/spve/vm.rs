@@ -0,0 +1,369 @@
+//! Examine a single VM's disks along with the `StorPool` volumes backing them.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::anyhow;
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use proxmoxy::types::{StorageConfig, VmDisk};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::config::Config;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::output::{self, Format, Table};
+use crate::storpool::{Attachment, GlobalIdDecoder, Volume};
+use crate::MainExit;
+
+/// A `StorPool` volume or snapshot attached to a Proxmox VE node.
+#[derive(Debug, Serialize)]
+struct DiskAttachment {
+    /// The name of the node, or the `StorPool` client ID if it is not known.
+    client: String,
+
+    /// The access rights of the client: "ro" or "rw".
+    rights: String,
+}
+
+/// The `StorPool` volume backing a VM disk.
+#[derive(Debug, Serialize)]
+struct SpVolumeInfo {
+    /// The name of the `StorPool` volume.
+    name: String,
+
+    /// The global ID of the `StorPool` volume.
+    global_id: String,
+
+    /// The provisioned size of the volume, in bytes.
+    size: u64,
+
+    /// The name of the template that the volume was created from.
+    template: Option<String>,
+
+    /// The placement groups: "all", "head", and "tail".
+    placement: BTreeMap<&'static str, String>,
+
+    /// The clients that the volume is attached to.
+    attachments: Vec<DiskAttachment>,
+
+    /// The names of the snapshots of the volume.
+    snapshots: Vec<String>,
+
+    /// The tags set for the volume.
+    tags: BTreeMap<String, String>,
+}
+
+/// A single disk of the VM.
+#[derive(Debug, Serialize)]
+struct DiskInfo {
+    /// The disk's key in the VM configuration, e.g. "scsi0".
+    disk: String,
+
+    /// The bus that the disk is attached to, e.g. "scsi".
+    bus: String,
+
+    /// The per-bus index of the disk.
+    index: u32,
+
+    /// The Proxmox VE storage that the disk is on.
+    storage: String,
+
+    /// The storage-specific ID of the disk's volume.
+    volid: String,
+
+    /// The disk options, e.g. `size` or `discard`.
+    options: BTreeMap<String, String>,
+
+    /// The `StorPool` volume backing the disk, if any.
+    storpool: Option<SpVolumeInfo>,
+}
+
+/// The full VM disk report.
+#[derive(Debug, Serialize)]
+struct VmReport {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// The name of the VM, if any.
+    name: Option<String>,
+
+    /// The node that the VM is on.
+    node: String,
+
+    /// The VM's disks.
+    disks: Vec<DiskInfo>,
+}
+
+impl VmReport {
+    /// Display the report in the specified format.
+    fn print(&self, format: Format) -> Result<()> {
+        if format == Format::Json {
+            return output::print_json(self);
+        }
+
+        let pairs = |items: &BTreeMap<String, String>| {
+            items
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .join(";")
+        };
+        let mut disks = Table::new(&[
+            "disk",
+            "bus",
+            "index",
+            "storage",
+            "volid",
+            "options",
+            "sp_name",
+            "global_id",
+            "size",
+            "template",
+            "placement",
+            "attachments",
+            "snapshots",
+            "tags",
+        ]);
+        for item in &self.disks {
+            let mut row = vec![
+                item.disk.clone(),
+                item.bus.clone(),
+                item.index.to_string(),
+                item.storage.clone(),
+                item.volid.clone(),
+                pairs(&item.options),
+            ];
+            match item.storpool {
+                Some(ref sp_vol) => row.extend([
+                    sp_vol.name.clone(),
+                    sp_vol.global_id.clone(),
+                    output::format_size(sp_vol.size, format),
+                    sp_vol.template.clone().unwrap_or_default(),
+                    sp_vol
+                        .placement
+                        .iter()
+                        .map(|(kind, group)| format!("{kind}={group}"))
+                        .join(";"),
+                    sp_vol
+                        .attachments
+                        .iter()
+                        .map(|att| {
+                            format!(
+                                "{client}:{rights}",
+                                client = att.client,
+                                rights = att.rights
+                            )
+                        })
+                        .join(";"),
+                    sp_vol.snapshots.join(";"),
+                    pairs(&sp_vol.tags),
+                ]),
+                None => row.extend((0..8).map(|_| String::new())),
+            }
+            disks.push(row);
+        }
+        if format == Format::Table {
+            output::emit(&format!(
+                "VM {vmid} ({name}) on {node}",
+                vmid = self.vmid,
+                name = self.name.as_deref().unwrap_or("no name"),
+                node = self.node
+            ));
+        }
+        disks.print(format);
+        Ok(())
+    }
+}
+
+/// Does a `StorPool` snapshot belong to the specified volume?
+fn is_snapshot_of(snap: &Volume, vol: &Volume) -> bool {
+    if snap.snapshot_of.as_ref() == Some(&vol.name) {
+        return true;
+    }
+    match (
+        vol.tags.get("pve-vm"),
+        vol.tags.get("pve-disk"),
+        snap.tags.get("pve-vm"),
+        snap.tags.get("pve-disk"),
+    ) {
+        (Some(vol_vm), Some(vol_disk), Some(snap_vm), Some(snap_disk)) => {
+            vol_vm == snap_vm && vol_disk == snap_disk
+        }
+        _ => false,
+    }
+}
+
+/// Gather the `StorPool` information about a volume.
+fn describe_volume(
+    vol: &Volume,
+    attachments: &[Attachment],
+    snapshots: &[Volume],
+    node_names: &HashMap<u32, String>,
+) -> SpVolumeInfo {
+    SpVolumeInfo {
+        name: vol.name.clone(),
+        global_id: vol
+            .global_id
+            .clone()
+            .unwrap_or_else(|| vol.name.trim_start_matches('~').to_owned()),
+        size: vol.size,
+        template: vol.template_name.clone(),
+        placement: [
+            ("all", &vol.place_all),
+            ("head", &vol.place_head),
+            ("tail", &vol.place_tail),
+        ]
+        .into_iter()
+        .filter_map(|(kind, group)| group.as_ref().map(|group| (kind, group.clone())))
+        .collect(),
+        attachments: attachments
+            .iter()
+            .filter(|att| att.volume == vol.name)
+            .sorted_by_key(|att| att.client)
+            .map(|att| DiskAttachment {
+                client: node_names
+                    .get(&att.client)
+                    .cloned()
+                    .unwrap_or_else(|| att.client.to_string()),
+                rights: att.rights.as_ref().to_owned(),
+            })
+            .collect(),
+        snapshots: snapshots
+            .iter()
+            .filter(|snap| is_snapshot_of(snap, vol))
+            .map(|snap| snap.name.clone())
+            .sorted()
+            .collect(),
+        tags: vol
+            .tags
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+    }
+}
+
+/// Find the `StorPool` volume backing a VM disk.
+fn find_volume<'vol>(
+    vmid: u32,
+    disk: &VmDisk,
+    volumes: &'vol [Volume],
+    decoder: &GlobalIdDecoder,
+) -> Option<&'vol Volume> {
+    if let Some(global_id) = decoder.decode(disk.volid()) {
+        let sp_name = format!("~{global_id}");
+        let found = volumes.iter().find(|vol| vol.name == sp_name);
+        if found.is_none() {
+            warn!(
+                "VM {vmid}: no StorPool volume for {volid}",
+                volid = disk.volid()
+            );
+        }
+        found
+    } else {
+        debug!(
+            "VM {vmid}: no StorPool global ID in {volid}",
+            volid = disk.volid()
+        );
+        None
+    }
+}
+
+/// Display a VM's disks along with the `StorPool` volumes backing them.
+pub async fn cmd_vm_show(
+    cfg: &Config,
+    api: &Proxmoxy,
+    vmid: u32,
+    format: Format,
+) -> Result<MainExit> {
+    let selection = guests::select(
+        api,
+        &GuestSelector {
+            vmids: vec![vmid..=vmid],
+            ..GuestSelector::default()
+        },
+    )
+    .await?;
+    let sel = match selection.vms.into_iter().next() {
+        Some(sel) => sel,
+        None if selection.offline.is_empty() => {
+            return Err(Error::Invoke(anyhow!("No VM with ID {vmid}")));
+        }
+        None => {
+            return Err(Error::Invoke(anyhow!(
+                "No VM with ID {vmid} on the online nodes; offline: {offline}",
+                offline = selection.offline.join(", ")
+            )));
+        }
+    };
+    let vmcfg = api
+        .get(api.path().nodes().id(&sel.node).qemu().id(vmid).config())
+        .await
+        .map_err(Error::Api)?;
+    let disks: Vec<&VmDisk> = vmcfg
+        .disks()
+        .iter()
+        .sorted_by_key(|disk| (disk.disk_type().as_ref().to_owned(), disk.idx()))
+        .collect();
+
+    let sp_names: HashSet<String> = api
+        .get(api.path().storage())
+        .await
+        .map_err(Error::Api)?
+        .iter()
+        .filter(|store| store.as_storpool().is_some())
+        .map(StorageConfig::storage)
+        .map(ToOwned::to_owned)
+        .collect();
+    let sp_data = if disks.iter().any(|disk| sp_names.contains(disk.storage())) {
+        let sp_api = cfg.get_storpool_api()?;
+        Some((
+            sp_api.volumes().await?,
+            sp_api.attachments().await?,
+            sp_api.snapshots().await?,
+        ))
+    } else {
+        debug!("VM {vmid}: no disks on StorPool storage");
+        None
+    };
+    let node_names = cfg.storpool_node_names();
+    let decoder = GlobalIdDecoder::new()?;
+
+    let mut res = Vec::new();
+    for disk in disks {
+        let sp_vol = match sp_data {
+            Some((ref volumes, ref attachments, ref snapshots))
+                if sp_names.contains(disk.storage()) =>
+            {
+                find_volume(vmid, disk, volumes, &decoder)
+                    .map(|vol| describe_volume(vol, attachments, snapshots, &node_names))
+            }
+            _ => None,
+        };
+        res.push(DiskInfo {
+            disk: disk.key(),
+            bus: disk.disk_type().as_ref().to_owned(),
+            index: disk.idx(),
+            storage: disk.storage().to_owned(),
+            volid: disk.volid().to_owned(),
+            options: disk
+                .options()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            storpool: sp_vol,
+        });
+    }
+
+    VmReport {
+        vmid,
+        name: sel.vm.name().map(ToOwned::to_owned),
+        node: sel.node,
+        disks: res,
+    }
+    .print(format)?;
+    Ok(MainExit::Ok)
+}
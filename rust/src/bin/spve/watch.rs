@@ -0,0 +1,335 @@
+//! Keep track of the changes in the VM configuration between runs.
+// SPDX-FileCopyrightText: StorPool <support@storpool.com>
+// SPDX-License-Identifier: BSD-2-Clause
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use proxmoxy::types::{VmConfig, VmDisk};
+use proxmoxy::Proxmoxy;
+
+use crate::cli::GuestSelector;
+use crate::defs::{Error, Result};
+use crate::guests;
+use crate::output;
+use crate::MainExit;
+
+/// The name of the file holding the digest of the last seen configuration of a VM.
+const CURRENT_FILE: &str = "current";
+
+/// The recorded configuration of a single VM disk.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredDisk {
+    /// The Proxmox VE storage that the disk is on.
+    storage: String,
+
+    /// The storage-specific ID of the volume.
+    volid: String,
+
+    /// The additional disk options.
+    options: BTreeMap<String, String>,
+}
+
+impl From<&VmDisk> for StoredDisk {
+    fn from(disk: &VmDisk) -> Self {
+        Self {
+            storage: disk.storage().to_owned(),
+            volid: disk.volid().to_owned(),
+            options: disk
+                .options()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The recorded configuration of a VM.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// The node that the VM was on.
+    node: String,
+
+    /// The checksum of the VM configuration.
+    digest: String,
+
+    /// The simulated SCSI controller type.
+    scsihw: Option<String>,
+
+    /// The VM's disks, keyed by name (e.g. "scsi0").
+    disks: BTreeMap<String, StoredDisk>,
+}
+
+impl StoredConfig {
+    /// Record the interesting parts of a VM's configuration.
+    fn new(vmid: u32, node: &str, vmcfg: &VmConfig) -> Self {
+        Self {
+            vmid,
+            node: node.to_owned(),
+            digest: vmcfg.digest().to_owned(),
+            scsihw: vmcfg.scsihw().map(ToOwned::to_owned),
+            disks: vmcfg
+                .disks()
+                .iter()
+                .map(|disk| {
+                    (
+                        format!(
+                            "{disk_type}{idx}",
+                            disk_type = disk.disk_type().as_ref(),
+                            idx = disk.idx()
+                        ),
+                        StoredDisk::from(disk),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Describe the changes between two versions of a VM's configuration.
+fn diff_configs(old: &StoredConfig, new: &StoredConfig) -> Vec<String> {
+    let mut res = Vec::new();
+    if old.node != new.node {
+        res.push(format!(
+            "~ moved from node {old} to node {new}",
+            old = old.node,
+            new = new.node
+        ));
+    }
+    if old.scsihw != new.scsihw {
+        res.push(format!(
+            "~ scsihw: {old} -> {new}",
+            old = old.scsihw.as_deref().unwrap_or("(none)"),
+            new = new.scsihw.as_deref().unwrap_or("(none)")
+        ));
+    }
+
+    for (name, disk) in &old.disks {
+        if !new.disks.contains_key(name) {
+            res.push(format!(
+                "- {name}: removed ({storage}:{volid})",
+                storage = disk.storage,
+                volid = disk.volid
+            ));
+        }
+    }
+    for (name, disk) in &new.disks {
+        let old_disk = if let Some(old_disk) = old.disks.get(name) {
+            old_disk
+        } else {
+            res.push(format!(
+                "+ {name}: added ({storage}:{volid})",
+                storage = disk.storage,
+                volid = disk.volid
+            ));
+            continue;
+        };
+        if old_disk == disk {
+            continue;
+        }
+
+        if old_disk.storage != disk.storage {
+            res.push(format!(
+                "~ {name}: storage moved from {old} to {new}",
+                old = old_disk.storage,
+                new = disk.storage
+            ));
+        }
+        if old_disk.volid != disk.volid {
+            res.push(format!(
+                "~ {name}: volume changed from {old} to {new}",
+                old = old_disk.volid,
+                new = disk.volid
+            ));
+        }
+        let opt_names: BTreeSet<&String> =
+            old_disk.options.keys().chain(disk.options.keys()).collect();
+        for opt in opt_names {
+            match (old_disk.options.get(opt), disk.options.get(opt)) {
+                (Some(old_value), Some(new_value)) if old_value != new_value => res.push(format!(
+                    "~ {name}: {opt} changed from {old_value} to {new_value}"
+                )),
+                (Some(old_value), None) => {
+                    res.push(format!("~ {name}: {opt}={old_value} removed"));
+                }
+                (None, Some(new_value)) => {
+                    res.push(format!("~ {name}: {opt}={new_value} added"));
+                }
+                _ => (),
+            }
+        }
+    }
+    res
+}
+
+/// Read the last recorded configuration of a VM, if any.
+///
+/// # Errors
+///
+/// [`Error::StateDir`] if the files could not be read or parsed.
+fn read_stored(vm_dir: &Path) -> Result<Option<StoredConfig>> {
+    let current_file = vm_dir.join(CURRENT_FILE);
+    let digest = match fs::read_to_string(&current_file) {
+        Ok(contents) => contents.trim().to_owned(),
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| {
+                    format!(
+                        "Could not read {current_file}",
+                        current_file = current_file.display()
+                    )
+                })
+                .map_err(Error::StateDir)
+        }
+    };
+    let cfg_file = vm_dir.join(format!("{digest}.json"));
+    let contents = fs::read_to_string(&cfg_file)
+        .with_context(|| format!("Could not read {cfg_file}", cfg_file = cfg_file.display()))
+        .map_err(Error::StateDir)?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Could not parse {cfg_file}", cfg_file = cfg_file.display()))
+        .map(Some)
+        .map_err(Error::StateDir)
+}
+
+/// Record a VM's configuration and mark it as the last seen one.
+///
+/// # Errors
+///
+/// [`Error::StateDir`] if the files could not be written.
+fn write_stored(vm_dir: &Path, stored: &StoredConfig) -> Result<()> {
+    fs::create_dir_all(vm_dir)
+        .with_context(|| format!("Could not create {vm_dir}", vm_dir = vm_dir.display()))
+        .map_err(Error::StateDir)?;
+    let cfg_file = vm_dir.join(format!("{digest}.json", digest = stored.digest));
+    let mut contents = serde_json::to_string_pretty(stored)
+        .context("Could not serialize the VM configuration")
+        .map_err(Error::StateDir)?;
+    contents.push('\n');
+    fs::write(&cfg_file, contents)
+        .with_context(|| format!("Could not write {cfg_file}", cfg_file = cfg_file.display()))
+        .map_err(Error::StateDir)?;
+
+    let current_file = vm_dir.join(CURRENT_FILE);
+    let tmp_file = vm_dir.join(format!("{CURRENT_FILE}.tmp"));
+    fs::write(&tmp_file, format!("{digest}\n", digest = stored.digest))
+        .with_context(|| format!("Could not write {tmp_file}", tmp_file = tmp_file.display()))
+        .map_err(Error::StateDir)?;
+    fs::rename(&tmp_file, &current_file)
+        .with_context(|| {
+            format!(
+                "Could not rename {tmp_file} to {current_file}",
+                tmp_file = tmp_file.display(),
+                current_file = current_file.display()
+            )
+        })
+        .map_err(Error::StateDir)
+}
+
+/// Compare the VM configuration to the one recorded during the previous run.
+pub async fn cmd_watch_configs(
+    api: &Proxmoxy,
+    guests: GuestSelector,
+    state_dir: PathBuf,
+) -> Result<MainExit> {
+    let selection = guests::select(api, &guests).await?;
+    for name in &selection.offline {
+        warn!("The {name} node is not online, the configuration of its VMs will not be examined");
+    }
+    let mut seen = HashSet::new();
+    for sel in selection.vms {
+        let vmid = sel.vm.vmid();
+        seen.insert(vmid.to_string());
+        let vmcfg = api
+            .get(api.path().nodes().id(&sel.node).qemu().id(vmid).config())
+            .await
+            .map_err(Error::Api)?;
+        let stored = StoredConfig::new(vmid, &sel.node, &vmcfg);
+        let vm_dir = state_dir.join(vmid.to_string());
+
+        match read_stored(&vm_dir)? {
+            None => output::emit(&format!(
+                "VM {vmid}: first seen on node {name}, digest {digest}",
+                name = sel.node,
+                digest = stored.digest
+            )),
+            Some(old) if old.digest == stored.digest && old.node == stored.node => {
+                debug!("VM {vmid}: no changes");
+                continue;
+            }
+            Some(old) => {
+                output::emit(&format!(
+                    "VM {vmid}: digest {old_digest} -> {new_digest}",
+                    old_digest = old.digest,
+                    new_digest = stored.digest
+                ));
+                for line in diff_configs(&old, &stored) {
+                    output::emit(&format!("  {line}"));
+                }
+            }
+        }
+        write_stored(&vm_dir, &stored)?;
+    }
+
+    // Only look for the VMs that are gone if all of them were examined.
+    if selection.offline.is_empty() && guests.selects_all() {
+        let entries = match fs::read_dir(&state_dir) {
+            Ok(entries) => entries
+                .collect::<StdResult<Vec<_>, _>>()
+                .with_context(|| {
+                    format!(
+                        "Could not list the contents of {state_dir}",
+                        state_dir = state_dir.display()
+                    )
+                })
+                .map_err(Error::StateDir)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| {
+                        format!(
+                            "Could not list the contents of {state_dir}",
+                            state_dir = state_dir.display()
+                        )
+                    })
+                    .map_err(Error::StateDir)
+            }
+        };
+        let mut gone: Vec<u32> = entries
+            .iter()
+            .filter_map(|entry| entry.file_name().to_str().map(ToOwned::to_owned))
+            .filter(|name| !seen.contains(name))
+            .filter_map(|name| name.parse().ok())
+            .collect();
+        gone.sort_unstable();
+        for vmid in gone {
+            let vm_dir = state_dir.join(vmid.to_string());
+            if read_stored(&vm_dir)?.is_some() {
+                output::emit(&format!("VM {vmid}: no longer present"));
+                // Keep the recorded configurations, only forget the last seen one.
+                let current_file = vm_dir.join(CURRENT_FILE);
+                fs::remove_file(&current_file)
+                    .with_context(|| {
+                        format!(
+                            "Could not remove {current_file}",
+                            current_file = current_file.display()
+                        )
+                    })
+                    .map_err(Error::StateDir)?;
+            }
+        }
+    }
+
+    Ok(MainExit::Ok)
+}
@@ -5,6 +5,7 @@
 #![allow(clippy::pub_use)]
 
 use core::fmt::Debug;
+use core::time::Duration;
 use std::result::Result as StdResult;
 
 use anyhow::Error as AnyError;
@@ -20,10 +21,22 @@ pub enum Error {
     #[error("The Proxmox VE API returned an error")]
     Api(#[source] AnyError),
 
+    /// The configuration was modified by someone else while we were updating it.
+    #[error("Conflicting modification: {0}")]
+    Conflict(String),
+
     /// Something went really, really wrong...
     #[error("proxmoxy internal error: {0}")]
     Internal(String),
 
+    /// A Proxmox VE task did not complete in time.
+    #[error("Timed out: {0}")]
+    TimedOut(String),
+
+    /// The Proxmox VE version is too old for the requested operation.
+    #[error("Unsupported Proxmox VE version: {0}")]
+    TooOld(String),
+
     /// Could not send an HTTPS request.
     #[error("Could not send an HTTPS request to the Proxmox VE API")]
     Reqwest(#[source] AnyError),
@@ -52,3 +65,26 @@ pub struct BackendConfig {
     /// The URL specifying where to send API requests.
     pub url: String,
 }
+
+/// Additional settings for the JSON-over-HTTPS backend.
+///
+/// The default values preserve the behavior of [`crate::Proxmoxy::get_https_api`]:
+/// the server's certificate is not verified and there are no timeouts.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct HttpsOptions {
+    /// Verify the server's TLS certificate.
+    pub verify_tls: bool,
+
+    /// An additional CA certificate in PEM format to trust when verifying the server's one.
+    pub ca_cert_pem: Option<Vec<u8>>,
+
+    /// How long to wait for a connection to be established.
+    pub connect_timeout: Option<Duration>,
+
+    /// How long to wait for a whole request to complete.
+    pub timeout: Option<Duration>,
+
+    /// More URLs to try, in order, if the main one cannot be reached.
+    pub fallback_urls: Vec<String>,
+}
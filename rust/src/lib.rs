@@ -23,7 +23,10 @@
 #![allow(clippy::pub_use)]
 
 use core::fmt::Debug;
+use core::time::Duration;
 
+use tokio::sync::OnceCell;
+use tokio::time::{sleep, Instant};
 use tracing::{debug, trace};
 
 mod backend;
@@ -32,14 +35,20 @@ mod backend;
 mod tests;
 
 use crate::backend::https::BackendData as HttpsBackendData;
-use crate::path::{PathStop, PathStopPure, PathTop};
+use crate::path::{
+    PathNNVVConfig, PathStop, PathStopDelete, PathStopPost, PathStopPure, PathStopPut, PathTop,
+};
+use crate::types::{PveVersion, TaskState, TaskStatus, Upid, VmConfig};
 
 pub mod defs;
 pub mod parse;
 pub mod path;
 pub mod types;
 
-pub use defs::{Auth, BackendConfig, Error, JsonValue, Result};
+pub use defs::{Auth, BackendConfig, Error, HttpsOptions, JsonValue, Result};
+
+/// How often to check whether a Proxmox VE task has completed.
+const TASK_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// Backend-specific data (e.g. an HTTP client or SSH connection or something).
 #[derive(Debug)]
@@ -56,6 +65,9 @@ enum BackendData {
 pub struct Proxmoxy {
     /// The selected backend for accessing the API.
     pm_backend: BackendData,
+
+    /// The Proxmox VE version, fetched on first use.
+    pm_version: OnceCell<PveVersion>,
 }
 
 impl Proxmoxy {
@@ -67,8 +79,21 @@ impl Proxmoxy {
     /// the HTTPS backend's initialization function.
     #[inline]
     pub fn get_https_api(cfg: BackendConfig) -> Result<Self> {
+        Self::get_https_api_with(cfg, HttpsOptions::default())
+    }
+
+    /// Prepare to access the Proxmox VE API using the JSON-over-HTTPS interface
+    /// with the specified TLS, timeout, and failover settings.
+    ///
+    /// # Errors
+    ///
+    /// Propagates [`Error::Reqwest`] and [`Error::Api`] errors from
+    /// the HTTPS backend's initialization function.
+    #[inline]
+    pub fn get_https_api_with(cfg: BackendConfig, opts: HttpsOptions) -> Result<Self> {
         Ok(Self {
-            pm_backend: BackendData::Https(HttpsBackendData::new(cfg)?),
+            pm_backend: BackendData::Https(HttpsBackendData::new(cfg, opts)?),
+            pm_version: OnceCell::new(),
         })
     }
 
@@ -79,6 +104,48 @@ impl Proxmoxy {
         PathTop::from_parts(Vec::new())
     }
 
+    /// Get the Proxmox VE version, querying the API the first time it is needed.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from the `get()` method.
+    /// Propagates errors from [`PveVersion::from_info`].
+    #[inline]
+    pub async fn version(&self) -> Result<&PveVersion> {
+        self.pm_version
+            .get_or_try_init(|| async {
+                let info = self.get(self.path().version()).await?;
+                let version = PveVersion::from_info(&info)?;
+                debug!(
+                    "Proxmox VE version {version}, release {release}, repoid {repoid}",
+                    release = version.release(),
+                    repoid = version.repoid()
+                );
+                Ok(version)
+            })
+            .await
+    }
+
+    /// Make sure that the Proxmox VE version is at least the specified one.
+    ///
+    /// The `what` string describes the operation that needs the newer version.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::TooOld`] if the Proxmox VE version is older than `major.minor`.
+    /// Propagates errors from the `version()` method.
+    #[inline]
+    pub async fn require_version(&self, major: u32, minor: u32, what: &str) -> Result<()> {
+        let version = self.version().await?;
+        if version.at_least(major, minor) {
+            Ok(())
+        } else {
+            Err(Error::TooOld(format!(
+                "{what} needs Proxmox VE {major}.{minor} or later, found {version}"
+            )))
+        }
+    }
+
     /// Query the Proxmox VE API for a value.
     ///
     /// # Errors
@@ -95,4 +162,174 @@ impl Proxmoxy {
         trace!("{raw:?}");
         PS::from_json(raw)
     }
+
+    /// Send a POST request to the Proxmox VE API, e.g. to start an operation.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from the backend's `post()` method.
+    /// Propagates errors from the query path's `post_from_json()` method.
+    #[inline]
+    pub async fn post<PS: PathStopPost + Send + Sync>(
+        &self,
+        path: PS,
+        params: &[(&str, &str)],
+    ) -> Result<PS::PostResultType> {
+        let query = path.parts().join("/");
+        debug!(query);
+        let raw = match self.pm_backend {
+            BackendData::Https(ref data) => data.post(&query, params).await?,
+        };
+        trace!("{raw:?}");
+        PS::post_from_json(raw)
+    }
+
+    /// Send a PUT request to the Proxmox VE API, e.g. to modify a configuration.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from the backend's `put()` method.
+    /// Propagates errors from the query path's `put_from_json()` method.
+    #[inline]
+    pub async fn put<PS: PathStopPut + Send + Sync>(
+        &self,
+        path: PS,
+        params: &[(&str, &str)],
+    ) -> Result<PS::PutResultType> {
+        let query = path.parts().join("/");
+        debug!(query);
+        let raw = match self.pm_backend {
+            BackendData::Https(ref data) => data.put(&query, params).await?,
+        };
+        trace!("{raw:?}");
+        PS::put_from_json(raw)
+    }
+
+    /// Send a DELETE request to the Proxmox VE API, e.g. to remove an object.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors from the backend's `delete()` method.
+    /// Propagates errors from the query path's `delete_from_json()` method.
+    #[inline]
+    pub async fn delete<PS: PathStopDelete + Send + Sync>(
+        &self,
+        path: PS,
+        params: &[(&str, &str)],
+    ) -> Result<PS::DeleteResultType> {
+        let query = path.parts().join("/");
+        debug!(query);
+        let raw = match self.pm_backend {
+            BackendData::Https(ref data) => data.delete(&query, params).await?,
+        };
+        trace!("{raw:?}");
+        PS::delete_from_json(raw)
+    }
+
+    /// Modify a VM's configuration without overwriting any concurrent changes.
+    ///
+    /// Read the configuration, let the `update` function modify it, then send
+    /// only the changed keys along with the digest of the configuration that
+    /// was read. If someone else modified the configuration in the meantime,
+    /// start over, at most `retries` more times.
+    ///
+    /// Return `false` if the `update` function did not change anything.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Conflict`] if the configuration was still being modified concurrently
+    /// after all the retries.
+    /// Propagates errors from the `get()` and `put()` methods.
+    #[inline]
+    pub async fn update_vm_config<F>(
+        &self,
+        path: PathNNVVConfig,
+        retries: u32,
+        mut update: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&mut VmConfig) + Send,
+    {
+        let mut attempt: u32 = 0;
+        loop {
+            let current = self.get(path.clone()).await?;
+            let mut updated = current.clone();
+            update(&mut updated);
+            let (changed, removed) = current.changes(&updated);
+            if changed.is_empty() && removed.is_empty() {
+                debug!(
+                    "Nothing to change in {query}",
+                    query = path.parts().join("/")
+                );
+                return Ok(false);
+            }
+
+            let removed_keys = removed.join(",");
+            let mut params: Vec<(&str, &str)> = changed
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            if !removed_keys.is_empty() {
+                params.push(("delete", &removed_keys));
+            }
+            params.push(("digest", current.digest()));
+
+            let err = match self.put(path.clone(), &params).await {
+                Ok(()) => return Ok(true),
+                Err(err) => err,
+            };
+            let latest = self.get(path.clone()).await?;
+            if latest.digest() == current.digest() {
+                return Err(err);
+            }
+            attempt = attempt.saturating_add(1);
+            if attempt > retries {
+                return Err(Error::Conflict(format!(
+                    "{query} was modified concurrently {attempt} time(s), giving up",
+                    query = path.parts().join("/")
+                )));
+            }
+            debug!(
+                "{query} was modified concurrently, retrying: {err}",
+                query = path.parts().join("/")
+            );
+        }
+    }
+
+    /// Wait for a Proxmox VE task to complete, return its final status.
+    ///
+    /// The task itself is left running if it does not complete within `timeout`.
+    /// A timeout too large to represent as a point in time means waiting forever.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the task identifier is invalid.
+    /// [`Error::TimedOut`] if the task is still running after `timeout`.
+    /// Propagates errors from the `get()` method.
+    #[inline]
+    pub async fn wait_task(&self, upid: &Upid, timeout: Duration) -> Result<TaskStatus> {
+        let deadline = Instant::now().checked_add(timeout);
+        let path = self
+            .path()
+            .nodes()
+            .id(upid.node()?)
+            .tasks()
+            .id(upid)
+            .status();
+        loop {
+            let status = self.get(path.clone()).await?;
+            if status.status() == TaskState::Stopped {
+                return Ok(status);
+            }
+            if deadline.map_or(false, |limit| Instant::now() >= limit) {
+                return Err(Error::TimedOut(format!(
+                    "the {upid} task did not complete within {secs} seconds",
+                    upid = upid.as_str(),
+                    secs = timeout.as_secs()
+                )));
+            }
+            trace!("Waiting for {upid}", upid = upid.as_str());
+            sleep(TASK_POLL_INTERVAL).await;
+        }
+    }
 }
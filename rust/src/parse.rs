@@ -2,10 +2,10 @@
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::iter;
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use itertools;
 use nom::{
     character::complete::{char, none_of, one_of},
@@ -24,10 +24,7 @@ fn p_proxmox_id(input: &str) -> IResult<&str, String> {
         one_of("abcdefghijklmnopqrstuvwxyz0123456789"),
         many0(one_of("abcdefghijklmnopqrstuvwxyz0123456789_-")),
     ))(input)?;
-    Ok((
-        r_input,
-        itertools::chain(iter::once(first), rest.into_iter()).collect(),
-    ))
+    Ok((r_input, itertools::chain(iter::once(first), rest).collect()))
 }
 
 /// Parse a Proxmox VE storage-specific volume ID string.
@@ -73,3 +70,99 @@ pub fn disk(input: &str) -> Result<(String, String, HashMap<String, String>)> {
         .map_err(Error::Api)?;
     Ok((storage, volid, options))
 }
+
+/// Parse a comma-separated list of Proxmox VE identifiers (nodes, content types, etc.) into a set.
+#[inline]
+#[must_use]
+pub fn comma_set(input: &str) -> HashSet<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Parse a list of Proxmox VE tags separated by semicolons, commas, or whitespace into a set.
+#[inline]
+#[must_use]
+pub fn tag_set(input: &str) -> HashSet<String> {
+    input
+        .split(|chr: char| chr == ';' || chr == ',' || chr.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Parse a Proxmox VE disk size string (e.g. "32G" or "512M") into a number of bytes.
+///
+/// # Errors
+///
+/// [`Error::Api`] on parse failure.
+#[inline]
+pub fn disk_size(input: &str) -> Result<u64> {
+    let (digits, multiplier) = [
+        ('K', 1_u64 << 10_u32),
+        ('M', 1_u64 << 20_u32),
+        ('G', 1_u64 << 30_u32),
+        ('T', 1_u64 << 40_u32),
+    ]
+    .into_iter()
+    .find_map(|(suffix, multiplier)| {
+        input
+            .strip_suffix(suffix)
+            .map(|digits| (digits, multiplier))
+    })
+    .unwrap_or((input, 1));
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .ok_or_else(|| Error::Api(anyhow!("Could not parse the {input:?} disk size")))
+}
+
+/// Parse the node list of a Proxmox VE HA group (e.g. "pve1:2,pve2") into node priorities.
+///
+/// # Errors
+///
+/// [`Error::Api`] if a node priority is not a valid number.
+#[inline]
+pub fn node_priorities(input: &str) -> Result<BTreeMap<String, Option<u32>>> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.split_once(':') {
+            Some((node, prio)) => prio
+                .parse::<u32>()
+                .map(|prio| (node.to_owned(), Some(prio)))
+                .map_err(|err| {
+                    Error::Api(anyhow!("Could not parse the {item:?} HA group node: {err}"))
+                }),
+            None => Ok((item.to_owned(), None)),
+        })
+        .collect()
+}
+
+/// Parse a Proxmox VE version string (e.g. "8.2.4" or "7.4-3") into its numeric components.
+///
+/// A missing patch level is treated as zero.
+///
+/// # Errors
+///
+/// [`Error::Api`] on parse failure.
+#[inline]
+pub fn pve_version(input: &str) -> Result<(u32, u32, u32)> {
+    let parse_err = || Error::Api(anyhow!("Could not parse the {input:?} Proxmox VE version"));
+    let mut parts = input.split(['.', '-']);
+    let mut next_num = || -> Result<Option<u32>> {
+        parts
+            .next()
+            .map(|part| part.parse::<u32>().map_err(|_| parse_err()))
+            .transpose()
+    };
+    let major = next_num()?.ok_or_else(parse_err)?;
+    let minor = next_num()?.ok_or_else(parse_err)?;
+    let patch = next_num()?.unwrap_or(0);
+    Ok((major, minor, patch))
+}
@@ -8,7 +8,12 @@ use anyhow::Context;
 use serde::Deserialize;
 
 use crate::defs::{Error, JsonValue, Result};
-use crate::types::{NameSubdir, NodeSummary, Storage, Subdir, VmConfig, VmSummary};
+use crate::types::{
+    ClusterStatusItem, HaGroup, HaResource, HaStatusItem, NameSubdir, NodeDetails, NodeStorage,
+    NodeStorageStatus, NodeSummary, PoolInfo, PoolSummary, StorageConfig, StorageContent, Subdir,
+    TaskStatus, TaskSummary, Upid, VersionInfo, VmConfig, VmMigrateInfo, VmPendingConfig,
+    VmSnapshot, VmSummary,
+};
 
 /// An API request's query path built incrementally.
 #[allow(clippy::module_name_repetitions)]
@@ -17,9 +22,11 @@ pub trait PathStop: Debug {
     type ResultType;
 
     /// A short human-readable name for the API endpoint.
+    #[must_use]
     fn desc() -> &'static str;
 
     /// Get the strings to be joined by slash characters to build the query path.
+    #[must_use]
     fn parts(&self) -> &[String];
 
     /// Parse the raw JSON data returned by Proxmox VE into a Rust object.
@@ -30,10 +37,53 @@ pub trait PathStop: Debug {
     fn from_json(raw: JsonValue) -> Result<Self::ResultType>;
 }
 
+/// An API endpoint that accepts POST requests, e.g. to start an operation.
+#[allow(clippy::module_name_repetitions)]
+pub trait PathStopPost: PathStop {
+    /// The type returned by the API for a POST request.
+    type PostResultType;
+
+    /// Parse the raw JSON data returned by Proxmox VE for a POST request into a Rust object.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] on parse failure.
+    fn post_from_json(raw: JsonValue) -> Result<Self::PostResultType>;
+}
+
+/// An API endpoint that accepts PUT requests, e.g. to modify a configuration.
+#[allow(clippy::module_name_repetitions)]
+pub trait PathStopPut: PathStop {
+    /// The type returned by the API for a PUT request.
+    type PutResultType;
+
+    /// Parse the raw JSON data returned by Proxmox VE for a PUT request into a Rust object.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] on parse failure.
+    fn put_from_json(raw: JsonValue) -> Result<Self::PutResultType>;
+}
+
+/// An API endpoint that accepts DELETE requests, e.g. to remove an object.
+#[allow(clippy::module_name_repetitions)]
+pub trait PathStopDelete: PathStop {
+    /// The type returned by the API for a DELETE request.
+    type DeleteResultType;
+
+    /// Parse the raw JSON data returned by Proxmox VE for a DELETE request into a Rust object.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] on parse failure.
+    fn delete_from_json(raw: JsonValue) -> Result<Self::DeleteResultType>;
+}
+
 /// A query path builder that does not have its own identifier.
 #[allow(clippy::module_name_repetitions)]
 pub trait PathStopPure: PathStop {
     /// Build our query path based on the one of the parent.
+    #[must_use]
     fn from_parts(parts: Vec<String>) -> Self;
 }
 
@@ -41,6 +91,7 @@ pub trait PathStopPure: PathStop {
 #[allow(clippy::module_name_repetitions)]
 pub trait PathStopId<T>: PathStop {
     /// Build our query path based on the one of the parent and a value for the identifier.
+    #[must_use]
     fn from_parts_with_id(parts: Vec<String>, id: T) -> Self;
 }
 
@@ -59,6 +110,66 @@ where
         .map_err(Error::Api)
 }
 
+/// A generic JSON-to-Rust-object deserializer for objects returned by POST requests.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the JSON data cannot be deserialized.
+fn gen_post_from_json<PS>(raw: JsonValue) -> Result<PS::PostResultType>
+where
+    PS: PathStopPost,
+    for<'de> <PS as PathStopPost>::PostResultType: Deserialize<'de>,
+{
+    serde_json::from_value(raw)
+        .with_context(|| {
+            format!(
+                "Could not deserialize the response to the {desc} POST request",
+                desc = PS::desc()
+            )
+        })
+        .map_err(Error::Api)
+}
+
+/// A generic JSON-to-Rust-object deserializer for objects returned by PUT requests.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the JSON data cannot be deserialized.
+fn gen_put_from_json<PS>(raw: JsonValue) -> Result<PS::PutResultType>
+where
+    PS: PathStopPut,
+    for<'de> <PS as PathStopPut>::PutResultType: Deserialize<'de>,
+{
+    serde_json::from_value(raw)
+        .with_context(|| {
+            format!(
+                "Could not deserialize the response to the {desc} PUT request",
+                desc = PS::desc()
+            )
+        })
+        .map_err(Error::Api)
+}
+
+/// A generic JSON-to-Rust-object deserializer for objects returned by DELETE requests.
+///
+/// # Errors
+///
+/// [`Error::Api`] if the JSON data cannot be deserialized.
+fn gen_delete_from_json<PS>(raw: JsonValue) -> Result<PS::DeleteResultType>
+where
+    PS: PathStopDelete,
+    for<'de> <PS as PathStopDelete>::DeleteResultType: Deserialize<'de>,
+{
+    serde_json::from_value(raw)
+        .with_context(|| {
+            format!(
+                "Could not deserialize the response to the {desc} DELETE request",
+                desc = PS::desc()
+            )
+        })
+        .map_err(Error::Api)
+}
+
 /// Generate the base [`PathStop`] implementation for a class.
 macro_rules! path_stop_base {
     ( $class:ident, $result_type:ty, $desc:literal ) => {
@@ -66,13 +177,11 @@ macro_rules! path_stop_base {
             type ResultType = $result_type;
 
             #[inline]
-            #[must_use]
             fn desc() -> &'static str {
                 $desc
             }
 
             #[inline]
-            #[must_use]
             fn parts(&self) -> &[String] {
                 &self.parts
             }
@@ -90,6 +199,66 @@ macro_rules! path_stop_base {
     };
 }
 
+/// Generate the [`PathStopPost`] implementation for a class.
+macro_rules! path_stop_post {
+    ( $class:ident, $post_result_type:ty ) => {
+        impl PathStopPost for $class {
+            type PostResultType = $post_result_type;
+
+            /// Deserialize a JSON raw value returned by a POST request into a Rust object.
+            ///
+            /// # Errors
+            ///
+            /// Propagates an [`Error::Api`] result from `gen_post_from_json()` on
+            /// deserialization failure.
+            #[inline]
+            fn post_from_json(raw: JsonValue) -> Result<Self::PostResultType> {
+                gen_post_from_json::<Self>(raw)
+            }
+        }
+    };
+}
+
+/// Generate the [`PathStopPut`] implementation for a class.
+macro_rules! path_stop_put {
+    ( $class:ident, $put_result_type:ty ) => {
+        impl PathStopPut for $class {
+            type PutResultType = $put_result_type;
+
+            /// Deserialize a JSON raw value returned by a PUT request into a Rust object.
+            ///
+            /// # Errors
+            ///
+            /// Propagates an [`Error::Api`] result from `gen_put_from_json()` on
+            /// deserialization failure.
+            #[inline]
+            fn put_from_json(raw: JsonValue) -> Result<Self::PutResultType> {
+                gen_put_from_json::<Self>(raw)
+            }
+        }
+    };
+}
+
+/// Generate the [`PathStopDelete`] implementation for a class.
+macro_rules! path_stop_delete {
+    ( $class:ident, $delete_result_type:ty ) => {
+        impl PathStopDelete for $class {
+            type DeleteResultType = $delete_result_type;
+
+            /// Deserialize a JSON raw value returned by a DELETE request into a Rust object.
+            ///
+            /// # Errors
+            ///
+            /// Propagates an [`Error::Api`] result from `gen_delete_from_json()` on
+            /// deserialization failure.
+            #[inline]
+            fn delete_from_json(raw: JsonValue) -> Result<Self::DeleteResultType> {
+                gen_delete_from_json::<Self>(raw)
+            }
+        }
+    };
+}
+
 /// Generate the [`PathStopPure`] implementation for a class.
 macro_rules! path_stop {
     ( $class:ident, $result_type:ty, $desc:literal, $part:literal ) => {
@@ -97,7 +266,6 @@ macro_rules! path_stop {
 
         impl PathStopPure for $class {
             #[inline]
-            #[must_use]
             fn from_parts(mut parts: Vec<String>) -> Self {
                 parts.push($part.to_owned());
                 Self { parts }
@@ -110,7 +278,6 @@ macro_rules! path_stop {
 
         impl PathStopPure for $class {
             #[inline]
-            #[must_use]
             fn from_parts(parts: Vec<String>) -> Self {
                 Self { parts }
             }
@@ -146,7 +313,6 @@ macro_rules! path_stop_id {
 
         impl PathStopId<$id_type> for $class {
             #[inline]
-            #[must_use]
             fn from_parts_with_id(mut parts: Vec<String>, id: $id_type) -> Self {
                 parts.push(id.to_string());
                 Self {
@@ -162,7 +328,6 @@ macro_rules! path_stop_id {
 
         impl PathStopId<&str> for $class {
             #[inline]
-            #[must_use]
             fn from_parts_with_id(mut parts: Vec<String>, id: &str) -> Self {
                 parts.push(id.to_owned());
                 Self {
@@ -213,7 +378,108 @@ macro_rules! path_stop_id_impl {
     };
 }
 
-path_stop_impl!(PathStorage, Vec<Storage>, "storage definitions", "storage");
+path_stop_impl!(
+    PathCHGroups,
+    Vec<HaGroup>,
+    "high availability groups",
+    "groups"
+);
+
+path_stop_impl!(
+    PathCHResources,
+    Vec<HaResource>,
+    "high availability resources",
+    "resources"
+);
+
+path_stop_impl!(
+    PathCHSCurrent,
+    Vec<HaStatusItem>,
+    "current high availability status",
+    "current"
+);
+
+path_stop_impl!(
+    PathCHStatus,
+    Vec<Subdir>,
+    "high availability status",
+    "status"
+);
+
+impl PathCHStatus {
+    #[inline]
+    #[must_use]
+    pub fn current(self) -> PathCHSCurrent {
+        PathCHSCurrent::from_parts(self.parts)
+    }
+}
+
+path_stop_impl!(PathCHa, Vec<Subdir>, "high availability", "ha");
+
+impl PathCHa {
+    #[inline]
+    #[must_use]
+    pub fn groups(self) -> PathCHGroups {
+        PathCHGroups::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn resources(self) -> PathCHResources {
+        PathCHResources::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(self) -> PathCHStatus {
+        PathCHStatus::from_parts(self.parts)
+    }
+}
+
+path_stop_impl!(
+    PathCStatus,
+    Vec<ClusterStatusItem>,
+    "cluster status",
+    "status"
+);
+
+path_stop_impl!(
+    PathCluster,
+    Vec<Subdir>,
+    "cluster-wide information",
+    "cluster"
+);
+
+impl PathCluster {
+    #[inline]
+    #[must_use]
+    pub fn ha(self) -> PathCHa {
+        PathCHa::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(self) -> PathCStatus {
+        PathCStatus::from_parts(self.parts)
+    }
+}
+
+path_stop_id_impl!(PathSStorage, StorageConfig, "single storage definition", id);
+
+path_stop_impl!(
+    PathStorage,
+    Vec<StorageConfig>,
+    "storage definitions",
+    "storage"
+);
+
+impl PathStorage {
+    #[inline]
+    #[must_use]
+    pub fn id(self, name: &str) -> PathSStorage {
+        PathSStorage::from_parts_with_id(self.parts, name)
+    }
+}
 
 path_stop_impl!(
     PathNNVVConfig,
@@ -221,6 +487,75 @@ path_stop_impl!(
     "configuration of a virtual machine",
     "config"
 );
+path_stop_put!(PathNNVVConfig, ());
+
+path_stop_impl!(
+    PathNNVVMigrate,
+    VmMigrateInfo,
+    "migration preconditions of a virtual machine",
+    "migrate"
+);
+path_stop_post!(PathNNVVMigrate, Upid);
+
+path_stop_impl!(
+    PathNNVVPending,
+    VmPendingConfig,
+    "pending configuration changes of a virtual machine",
+    "pending"
+);
+
+path_stop_impl!(
+    PathNNVVSSConfig,
+    VmConfig,
+    "configuration of a virtual machine snapshot",
+    "config"
+);
+
+path_stop_impl!(
+    PathNNVVSSRollback,
+    JsonValue,
+    "rollback of a virtual machine snapshot",
+    "rollback"
+);
+path_stop_post!(PathNNVVSSRollback, Upid);
+
+path_stop_id_impl!(
+    PathNNVVSSnapshot,
+    Vec<Subdir>,
+    "single snapshot of a virtual machine",
+    id
+);
+path_stop_delete!(PathNNVVSSnapshot, Upid);
+
+impl PathNNVVSSnapshot {
+    #[inline]
+    #[must_use]
+    pub fn config(self) -> PathNNVVSSConfig {
+        PathNNVVSSConfig::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn rollback(self) -> PathNNVVSSRollback {
+        PathNNVVSSRollback::from_parts(self.parts)
+    }
+}
+
+path_stop_impl!(
+    PathNNVVSnapshots,
+    Vec<VmSnapshot>,
+    "snapshots of a virtual machine",
+    "snapshot"
+);
+path_stop_post!(PathNNVVSnapshots, Upid);
+
+impl PathNNVVSnapshots {
+    #[inline]
+    #[must_use]
+    pub fn id(self, name: &str) -> PathNNVVSSnapshot {
+        PathNNVVSSnapshot::from_parts_with_id(self.parts, name)
+    }
+}
 
 path_stop_id_impl!(PathNNVVm, Vec<Subdir>, "virtual machine", id, u32);
 
@@ -230,6 +565,24 @@ impl PathNNVVm {
     pub fn config(self) -> PathNNVVConfig {
         PathNNVVConfig::from_parts(self.parts)
     }
+
+    #[inline]
+    #[must_use]
+    pub fn migrate(self) -> PathNNVVMigrate {
+        PathNNVVMigrate::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn pending(self) -> PathNNVVPending {
+        PathNNVVPending::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn snapshot(self) -> PathNNVVSnapshots {
+        PathNNVVSnapshots::from_parts(self.parts)
+    }
 }
 
 path_stop_impl!(
@@ -247,6 +600,85 @@ impl PathNNVms {
     }
 }
 
+path_stop_impl!(
+    PathNNSSContent,
+    Vec<StorageContent>,
+    "volumes on a storage",
+    "content"
+);
+
+path_stop_impl!(
+    PathNNSSStatus,
+    NodeStorageStatus,
+    "status of a storage on a node",
+    "status"
+);
+
+path_stop_id_impl!(PathNNSStorage, Vec<Subdir>, "storage on a node", id);
+
+impl PathNNSStorage {
+    #[inline]
+    #[must_use]
+    pub fn content(self) -> PathNNSSContent {
+        PathNNSSContent::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(self) -> PathNNSSStatus {
+        PathNNSSStatus::from_parts(self.parts)
+    }
+}
+
+path_stop_impl!(
+    PathNNStorages,
+    Vec<NodeStorage>,
+    "storages on a node",
+    "storage"
+);
+
+impl PathNNStorages {
+    #[inline]
+    #[must_use]
+    pub fn id(self, name: &str) -> PathNNSStorage {
+        PathNNSStorage::from_parts_with_id(self.parts, name)
+    }
+}
+
+path_stop_impl!(
+    PathNNTTStatus,
+    TaskStatus,
+    "status of a task on a node",
+    "status"
+);
+
+path_stop_id_impl!(PathNNTTask, Vec<Subdir>, "task on a node", id);
+
+impl PathNNTTask {
+    #[inline]
+    #[must_use]
+    pub fn status(self) -> PathNNTTStatus {
+        PathNNTTStatus::from_parts(self.parts)
+    }
+}
+
+path_stop_impl!(PathNNTasks, Vec<TaskSummary>, "tasks on a node", "tasks");
+
+impl PathNNTasks {
+    #[inline]
+    #[must_use]
+    pub fn id(self, upid: &Upid) -> PathNNTTask {
+        PathNNTTask::from_parts_with_id(self.parts, upid.as_str())
+    }
+}
+
+path_stop_impl!(
+    PathNNStatus,
+    NodeDetails,
+    "detailed status of a node",
+    "status"
+);
+
 path_stop_id_impl!(PathNNode, Vec<NameSubdir>, "single cluster node", id);
 
 impl PathNNode {
@@ -255,6 +687,24 @@ impl PathNNode {
     pub fn qemu(self) -> PathNNVms {
         PathNNVms::from_parts(self.parts)
     }
+
+    #[inline]
+    #[must_use]
+    pub fn status(self) -> PathNNStatus {
+        PathNNStatus::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn storage(self) -> PathNNStorages {
+        PathNNStorages::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn tasks(self) -> PathNNTasks {
+        PathNNTasks::from_parts(self.parts)
+    }
 }
 
 path_stop_impl!(PathNodes, Vec<NodeSummary>, "cluster nodes", "nodes");
@@ -267,9 +717,27 @@ impl PathNodes {
     }
 }
 
+path_stop_id_impl!(PathPPool, PoolInfo, "single resource pool", id);
+
+path_stop_impl!(PathPools, Vec<PoolSummary>, "resource pools", "pools");
+
+impl PathPools {
+    #[inline]
+    #[must_use]
+    pub fn id(self, poolid: &str) -> PathPPool {
+        PathPPool::from_parts_with_id(self.parts, poolid)
+    }
+}
+
 path_stop_impl!(PathTop, Vec<Subdir>, "top-level API data");
 
 impl PathTop {
+    #[inline]
+    #[must_use]
+    pub fn cluster(self) -> PathCluster {
+        PathCluster::from_parts(self.parts)
+    }
+
     #[inline]
     #[must_use]
     pub fn storage(self) -> PathStorage {
@@ -281,4 +749,18 @@ impl PathTop {
     pub fn nodes(self) -> PathNodes {
         PathNodes::from_parts(self.parts)
     }
+
+    #[inline]
+    #[must_use]
+    pub fn pools(self) -> PathPools {
+        PathPools::from_parts(self.parts)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn version(self) -> PathVersion {
+        PathVersion::from_parts(self.parts)
+    }
 }
+
+path_stop_impl!(PathVersion, VersionInfo, "Proxmox VE version", "version");
@@ -2,18 +2,29 @@
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env::{self, VarError as EnvError};
 use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, Context, Error as AnyError, Result};
 use serde::Deserialize;
+use serde_json::json;
 use tracing::info;
 use tracing_test::traced_test;
 
-use crate::defs::{Auth, BackendConfig};
-use crate::path::PathStop;
-use crate::Proxmoxy;
+use crate::defs::{Auth, BackendConfig, Error, HttpsOptions};
+use crate::parse;
+use crate::path::{
+    PathCHGroups, PathCHResources, PathCHSCurrent, PathCStatus, PathNNSSContent, PathNNSSStatus,
+    PathNNStatus, PathNNTTStatus, PathNNVVConfig, PathNNVVPending, PathNNVVSnapshots, PathPPool,
+    PathPools, PathStop, PathStorage, PathVersion,
+};
+use crate::types::{PoolSummary, PveVersion, StorageConfig, TaskState, VmDisk};
+use crate::{JsonValue, Proxmoxy};
 
 #[derive(Debug, Deserialize)]
 struct AuthFileToken {
@@ -41,7 +52,10 @@ struct CfgFileDefaults {
 #[derive(Debug, Deserialize)]
 struct CfgFileCluster {
     api_mode: String,
-    url: String,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    endpoints: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -97,11 +111,729 @@ fn test_create_paths() -> Result<()> {
             .join("/")
     );
 
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .storage()
+            .id("sp-ssd")
+            .content()
+            .parts()
+            .join("/")
+    );
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .storage()
+            .id("sp-ssd")
+            .status()
+            .parts()
+            .join("/")
+    );
+
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .qemu()
+            .id(616)
+            .migrate()
+            .parts()
+            .join("/")
+    );
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .qemu()
+            .id(616)
+            .snapshot()
+            .id("before-upgrade")
+            .rollback()
+            .parts()
+            .join("/")
+    );
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .qemu()
+            .id(616)
+            .pending()
+            .parts()
+            .join("/")
+    );
+    let task = PathNNTTStatus::from_json(json!({
+        "upid": "UPID:local:0001:0002:0003:qmigrate:616:root@pam:",
+        "node": "local",
+        "type": "qmigrate",
+        "id": "616",
+        "status": "running",
+    }))?;
+    info!(
+        "{}",
+        api.path()
+            .nodes()
+            .id("local")
+            .tasks()
+            .id(task.upid())
+            .status()
+            .parts()
+            .join("/")
+    );
+
+    info!("{}", api.path().pools().parts().join("/"));
+    info!("{}", api.path().pools().id("team-a").parts().join("/"));
     info!("{}", api.path().storage().parts().join("/"));
+    info!("{}", api.path().storage().id("sp-ssd").parts().join("/"));
+    info!("{}", api.path().cluster().status().parts().join("/"));
+    info!("{}", api.path().version().parts().join("/"));
+    info!(
+        "{}",
+        api.path().cluster().ha().resources().parts().join("/")
+    );
+    info!("{}", api.path().cluster().ha().groups().parts().join("/"));
+    info!(
+        "{}",
+        api.path()
+            .cluster()
+            .ha()
+            .status()
+            .current()
+            .parts()
+            .join("/")
+    );
+    info!(
+        "{}",
+        api.path().nodes().id("local").status().parts().join("/")
+    );
 
     Ok(())
 }
 
+#[test]
+fn test_parse_storage() -> Result<()> {
+    let raw = json!([
+        {
+            "storage": "sp-ssd",
+            "type": "storpool",
+            "content": "images,rootdir",
+            "digest": "0123abcd",
+            "extra-tags": "owner=team-a",
+            "nodes": "pve1,pve2",
+            "shared": 1,
+        },
+        {
+            "storage": "local",
+            "type": "dir",
+            "content": "iso,vztmpl,backup",
+            "path": "/var/lib/vz",
+        },
+        {
+            "storage": "cephfs",
+            "type": "cephfs",
+            "disable": 1,
+        },
+    ]);
+    let storage = PathStorage::from_json(raw)?;
+    assert_eq!(storage.len(), 3);
+
+    let sp_store = storage[0]
+        .as_storpool()
+        .context("sp-ssd is not a StorPool storage")?;
+    assert_eq!(sp_store.template_name(), "sp-ssd");
+    assert_eq!(sp_store.extra_tags(), Some("owner=team-a"));
+    let common = sp_store.common();
+    assert!(common.shared());
+    assert!(common.content().contains("rootdir"));
+    assert!(common.enabled_on("pve2"));
+    assert!(!common.enabled_on("pve3"));
+
+    if let StorageConfig::Dir(ref dir) = storage[1] {
+        assert_eq!(dir.path(), "/var/lib/vz");
+        assert!(dir.common().enabled_on("pve3"));
+        assert_eq!(dir.common().digest(), None);
+    } else {
+        bail!("Expected a directory storage, got {:?}", storage[1]);
+    }
+
+    assert_eq!(storage[2].storage_type(), "cephfs");
+    assert!(storage[2].common().content().is_empty());
+    assert!(!storage[2].common().enabled_on("pve1"));
+    Ok(())
+}
+
+#[test]
+fn test_parse_node_storage() -> Result<()> {
+    let status = PathNNSSStatus::from_json(json!({
+        "type": "storpool",
+        "content": "images",
+        "total": 1000,
+        "used": 400,
+        "avail": 600,
+        "active": 1,
+        "enabled": 1,
+        "shared": 1,
+    }))?;
+    assert_eq!(status.storage_type(), "storpool");
+    assert_eq!(
+        (status.total(), status.used(), status.avail()),
+        (Some(1000), Some(400), Some(600))
+    );
+    assert!(status.active() && status.enabled() && status.shared());
+
+    let content = PathNNSSContent::from_json(json!([
+        {
+            "volid": "sp-ssd:vm-616-disk-0-sp-4.1.a.raw",
+            "content": "images",
+            "format": "raw",
+            "size": 1_073_741_824_u64,
+            "vmid": 616,
+        },
+        {
+            "volid": "sp-ssd:vm-617-disk-0-sp-4.1.b.raw",
+            "content": "images",
+            "format": "raw",
+            "size": 1_073_741_824_u64,
+            "vmid": 617,
+            "parent": "base-600-disk-0-sp-4.1.c.raw",
+        },
+    ]))?;
+    assert_eq!(content.len(), 2);
+    assert_eq!(content[0].vmid(), Some(616));
+    assert_eq!(content[0].parent(), None);
+    assert_eq!(content[1].parent(), Some("base-600-disk-0-sp-4.1.c.raw"));
+    Ok(())
+}
+
+#[test]
+fn test_parse_task_status() -> Result<()> {
+    let running = PathNNTTStatus::from_json(json!({
+        "upid": "UPID:pve1:0001:0002:0003:qmigrate:616:root@pam:",
+        "node": "pve1",
+        "type": "qmigrate",
+        "id": "616",
+        "status": "running",
+    }))?;
+    assert_eq!(running.upid().node()?, "pve1");
+    assert_eq!(running.status(), TaskState::Running);
+    assert!(!running.succeeded());
+
+    let stopped = PathNNTTStatus::from_json(json!({
+        "upid": "UPID:pve1:0001:0002:0003:qmigrate:616:root@pam:",
+        "node": "pve1",
+        "type": "qmigrate",
+        "status": "stopped",
+        "exitstatus": "OK",
+    }))?;
+    assert!(stopped.succeeded());
+    assert_eq!(stopped.id(), None);
+    Ok(())
+}
+
+#[test]
+fn test_parse_snapshots() -> Result<()> {
+    let snapshots = PathNNVVSnapshots::from_json(json!([
+        {
+            "name": "before-upgrade",
+            "description": "Before the upgrade\n",
+            "snaptime": 1_700_000_000_u64,
+            "vmstate": 1,
+        },
+        {
+            "name": "current",
+            "description": "You are here!",
+            "parent": "before-upgrade",
+            "running": 1,
+        },
+    ]))?;
+    assert_eq!(snapshots.len(), 2);
+    assert!(!snapshots[0].is_current());
+    assert_eq!(snapshots[0].snaptime(), Some(1_700_000_000));
+    assert!(snapshots[0].vmstate());
+    assert!(snapshots[1].is_current());
+    assert_eq!(snapshots[1].parent(), Some("before-upgrade"));
+    assert!(!snapshots[1].vmstate());
+
+    assert_eq!(
+        parse::tag_set("prod;web, db"),
+        ["db", "prod", "web"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vm_config_changes() -> Result<()> {
+    let current = PathNNVVConfig::from_json(json!({
+        "digest": "0123456789abcdef",
+        "scsihw": "virtio-scsi-single",
+        "net0": "virtio=AA:BB:CC:DD:EE:FF,bridge=vmbr0",
+        "scsi0": "sp-ssd:vm-616-disk-0-sp-4.1.a.raw,iothread=1,size=32G",
+        "scsi1": "local-lvm:vm-616-disk-1,size=8G",
+        "name": "test",
+        "tags": "team-a;prod",
+    }))?;
+    assert_eq!(current.scsihw(), Some("virtio-scsi-single"));
+    assert!(current.tags().contains("team-a") && current.tags().contains("prod"));
+    assert_eq!(
+        current.disks()[0].to_value(),
+        "sp-ssd:vm-616-disk-0-sp-4.1.a.raw,iothread=1,size=32G"
+    );
+
+    let mut updated = current.clone();
+    assert_eq!(current.changes(&updated), (BTreeMap::new(), Vec::new()));
+
+    updated.disks_mut()[0]
+        .options_mut()
+        .insert("discard".to_owned(), "on".to_owned());
+    updated
+        .disks_mut()
+        .retain(|disk| disk.storage() != "local-lvm");
+    let (changed, removed) = current.changes(&updated);
+    assert_eq!(
+        changed.into_iter().collect::<Vec<_>>(),
+        [(
+            "scsi0".to_owned(),
+            "sp-ssd:vm-616-disk-0-sp-4.1.a.raw,discard=on,iothread=1,size=32G".to_owned()
+        )]
+    );
+    assert_eq!(removed, ["scsi1"]);
+    Ok(())
+}
+
+#[test]
+fn test_parse_pending() -> Result<()> {
+    let pending = PathNNVVPending::from_json(json!([
+        {"key": "digest", "value": "0123456789abcdef"},
+        {"key": "memory", "value": 2048, "pending": 4096},
+        {"key": "scsi0", "value": "sp-ssd:vm-616-disk-0-sp-4.1.a.raw,size=32G",
+         "pending": "sp-ssd:vm-616-disk-0-sp-4.1.a.raw,discard=on,size=32G"},
+        {"key": "scsi1", "value": "local-lvm:vm-616-disk-1,size=8G", "delete": 1},
+        {"key": "scsi2", "pending": "sp-ssd:vm-616-disk-2-sp-4.1.b.raw,size=4G"},
+    ]))?;
+    assert!(pending.has_pending());
+    assert_eq!(pending.items().len(), 5);
+    assert!(!pending.items()[0].is_pending());
+    assert!(pending.items()[3].delete());
+
+    let current = pending.current()?;
+    assert_eq!(current.digest(), "0123456789abcdef");
+    assert_eq!(
+        current.disks().iter().map(VmDisk::key).collect::<Vec<_>>(),
+        ["scsi0", "scsi1"]
+    );
+    assert_eq!(current.disks()[0].options().get("discard"), None);
+
+    let updated = pending.pending()?;
+    assert_eq!(
+        updated.disks().iter().map(VmDisk::key).collect::<Vec<_>>(),
+        ["scsi0", "scsi2"]
+    );
+    assert_eq!(
+        updated.disks()[0]
+            .options()
+            .get("discard")
+            .map(String::as_str),
+        Some("on")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_pools() -> Result<()> {
+    let pools = PathPools::from_json(json!([
+        {"poolid": "team-a", "comment": "The A team"},
+        {"poolid": "team-b"},
+    ]))?;
+    assert_eq!(
+        pools.iter().map(PoolSummary::poolid).collect::<Vec<_>>(),
+        ["team-a", "team-b"]
+    );
+    assert_eq!(pools[0].comment(), Some("The A team"));
+
+    let pool = PathPPool::from_json(json!({
+        "comment": "The A team",
+        "members": [
+            {"id": "qemu/616", "type": "qemu", "node": "local", "vmid": 616, "name": "test"},
+            {"id": "storage/local/sp-ssd", "type": "storage", "node": "local", "storage": "sp-ssd"},
+        ],
+    }))?;
+    assert_eq!(pool.members().len(), 2);
+    assert!(pool.members()[0].is_qemu());
+    assert_eq!(pool.members()[0].vmid(), Some(616));
+    assert!(!pool.members()[1].is_qemu());
+    assert_eq!(pool.members()[1].storage(), Some("sp-ssd"));
+    Ok(())
+}
+
+#[test]
+fn test_parse_cluster_status() -> Result<()> {
+    let status = PathCStatus::from_json(json!([
+        {"type": "cluster", "id": "cluster", "name": "pve", "nodes": 2, "quorate": 1, "version": 5},
+        {"type": "node", "id": "node/pve1", "name": "pve1", "nodeid": 1, "ip": "10.1.2.3", "local": 1, "online": 1, "level": ""},
+        {"type": "node", "id": "node/pve2", "name": "pve2", "nodeid": 2, "ip": "10.1.2.4", "local": 0, "online": 0},
+    ]))?;
+    assert_eq!(status.len(), 3);
+    assert!(status[0].is_cluster());
+    assert!(status[0].quorate());
+    assert_eq!(status[0].nodes(), Some(2));
+    assert!(status[1].is_node());
+    assert!(status[1].local());
+    assert!(status[1].online());
+    assert_eq!(status[1].ip(), Some("10.1.2.3"));
+    assert_eq!(status[2].nodeid(), Some(2));
+    assert!(!status[2].local());
+    assert!(!status[2].online());
+
+    let node = PathNNStatus::from_json(json!({
+        "kversion": "Linux 6.8.12-4-pve #1 SMP PREEMPT_DYNAMIC",
+        "current-kernel": {"sysname": "Linux", "release": "6.8.12-4-pve", "version": "#1 SMP", "machine": "x86_64"},
+        "pveversion": "pve-manager/8.2.4/faa83925c9641325",
+        "loadavg": ["0.25", "0.50", "1.00"],
+        "memory": {"total": 16384, "used": 4096, "free": 12288},
+        "rootfs": {"total": 1000, "used": 250, "free": 750, "avail": 700},
+        "cpu": 0.05,
+        "uptime": 3600,
+    }))?;
+    assert_eq!(node.pveversion(), "pve-manager/8.2.4/faa83925c9641325");
+    assert_eq!(
+        node.current_kernel().map(|kernel| kernel.release()),
+        Some("6.8.12-4-pve")
+    );
+    assert_eq!(node.loadavg(), [0.25, 0.5, 1.0]);
+    assert_eq!(node.memory().used(), 4096);
+    assert_eq!(node.rootfs().avail(), 700);
+    Ok(())
+}
+
+#[test]
+fn test_parse_ha() -> Result<()> {
+    let resources = PathCHResources::from_json(json!([
+        {"sid": "vm:616", "type": "vm", "state": "started", "group": "sp-nodes", "max_restart": 1, "max_relocate": 1, "digest": "abc"},
+        {"sid": "ct:200", "type": "ct", "state": "stopped"},
+    ]))?;
+    assert_eq!(resources.len(), 2);
+    assert_eq!(resources[0].vmid(), Some(616));
+    assert_eq!(resources[0].group(), Some("sp-nodes"));
+    assert_eq!(resources[0].max_relocate(), Some(1));
+    assert_eq!(resources[1].vmid(), None);
+    assert_eq!(resources[1].group(), None);
+
+    let groups = PathCHGroups::from_json(json!([
+        {"group": "sp-nodes", "type": "group", "nodes": "pve1:2,pve2", "restricted": 1, "digest": "def"},
+    ]))?;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        groups[0].nodes().clone().into_iter().collect::<Vec<_>>(),
+        [("pve1".to_owned(), Some(2)), ("pve2".to_owned(), None)]
+    );
+    assert!(groups[0].restricted());
+    assert!(!groups[0].nofailback());
+    assert!(parse::node_priorities("pve1:high").is_err());
+
+    let current = PathCHSCurrent::from_json(json!([
+        {"id": "quorum", "type": "quorum", "node": "pve1", "status": "OK", "quorate": 1},
+        {"id": "service:vm:616", "type": "service", "sid": "vm:616", "node": "pve2", "state": "started", "status": "started"},
+    ]))?;
+    assert_eq!(current.len(), 2);
+    assert!(current[0].quorate());
+    assert_eq!(current[1].sid(), Some("vm:616"));
+    assert_eq!(current[1].node(), Some("pve2"));
+    Ok(())
+}
+
+#[test]
+fn test_parse_version() -> Result<()> {
+    let info = PathVersion::from_json(json!({
+        "version": "8.2.4",
+        "release": "8.2",
+        "repoid": "faa83925c9641325",
+        "console": "xtermjs",
+    }))?;
+    let version = PveVersion::from_info(&info)?;
+    assert_eq!(
+        (version.major(), version.minor(), version.patch()),
+        (8, 2, 4)
+    );
+    assert_eq!(version.release(), "8.2");
+    assert_eq!(version.repoid(), "faa83925c9641325");
+    assert_eq!(version.to_string(), "8.2.4");
+    assert!(version.at_least(8, 2));
+    assert!(version.at_least(7, 4));
+    assert!(!version.at_least(8, 3));
+    assert!(version.has_guest_tags());
+
+    let old = PveVersion::from_info(&PathVersion::from_json(json!({
+        "version": "7.4-3",
+        "release": "7.4",
+        "repoid": "9002ab8a",
+    }))?)?;
+    assert_eq!(old, PveVersion::new(7, 4, 3));
+    assert!(old < version);
+    assert!(old > PveVersion::new(7, 3, 9));
+    assert!(old.has_guest_tags());
+    assert!(!PveVersion::new(7, 2, 11).has_guest_tags());
+
+    assert_eq!(parse::pve_version("8.1")?, (8, 1, 0));
+    assert!(parse::pve_version("8").is_err());
+    assert!(parse::pve_version("eight.1").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_parse_disk_size() -> Result<()> {
+    assert_eq!(parse::disk_size("4096")?, 4096);
+    assert_eq!(parse::disk_size("512M")?, 512 * 1024 * 1024);
+    assert_eq!(parse::disk_size("32G")?, 32 * 1024 * 1024 * 1024);
+    assert!(parse::disk_size("32X").is_err());
+    assert!(parse::disk_size("G").is_err());
+    assert!(parse::disk_size("32é").is_err());
+    assert_eq!(parse::disk_size("16777215T")?, 16_777_215 << 40_u32);
+    assert!(parse::disk_size("16777216T").is_err());
+    Ok(())
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_https_failover() -> Result<()> {
+    println!();
+
+    let api = Proxmoxy::get_https_api_with(
+        BackendConfig {
+            auth: Auth::Token("username".to_owned(), "password".to_owned()),
+            url: "http://127.0.0.1:6".to_owned(),
+        },
+        HttpsOptions {
+            connect_timeout: Some(Duration::from_secs(5)),
+            fallback_urls: vec!["http://127.0.0.1:7".to_owned()],
+            ..HttpsOptions::default()
+        },
+    )?;
+    match api.get(api.path().nodes()).await {
+        Ok(nodes) => bail!("Did not expect to get any nodes: {nodes:?}"),
+        Err(err) => {
+            let msg = format!("{err:#}", err = AnyError::from(err));
+            info!(msg);
+            assert!(msg.contains("http://127.0.0.1:7/api2/json/nodes"));
+        }
+    }
+    Ok(())
+}
+
+/// Serve the specified API responses in order, one per connection, on a local port.
+///
+/// Return the base URL of the server and a handle that yields
+/// the method, path, and body of each request that was received.
+fn mock_api(responses: Vec<(u16, JsonValue)>) -> Result<(String, JoinHandle<Result<Vec<String>>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Could not bind a local port")?;
+    listener.set_nonblocking(true)?;
+    let url = format!("http://{addr}", addr = listener.local_addr()?);
+    let handle = thread::spawn(move || -> Result<Vec<String>> {
+        let mut requests = Vec::new();
+        for (status, data) in responses {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let mut stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(err) if err.kind() == IoErrorKind::WouldBlock => {
+                        if Instant::now() >= deadline {
+                            bail!("No request {idx} within ten seconds", idx = requests.len());
+                        }
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            };
+            stream.set_nonblocking(false)?;
+
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse()?;
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let mut words = request_line.split_whitespace();
+            requests.push(format!(
+                "{method} {path} {body}",
+                method = words.next().unwrap_or_default(),
+                path = words.next().unwrap_or_default(),
+                body = String::from_utf8(body)?
+            ));
+
+            let text = json!({ "data": data }).to_string();
+            write!(
+                stream,
+                "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n{text}",
+                len = text.len()
+            )?;
+        }
+        Ok(requests)
+    });
+    Ok((url, handle))
+}
+
+/// Build an API client for a [`mock_api`] server.
+fn mock_client(url: String) -> Result<Proxmoxy> {
+    Ok(Proxmoxy::get_https_api(BackendConfig {
+        auth: Auth::Token("username".to_owned(), "password".to_owned()),
+        url,
+    })?)
+}
+
+/// Wait for the [`mock_api`] server to serve all its responses.
+fn mock_requests(handle: JoinHandle<Result<Vec<String>>>) -> Result<Vec<String>> {
+    handle
+        .join()
+        .map_err(|_| AnyError::msg("The mock API server thread panicked"))?
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_wait_task_no_deadline() -> Result<()> {
+    let upid_str = "UPID:pve1:0001:0002:0003:qmigrate:616:root@pam:";
+    let task = |status: &str| {
+        json!({
+            "upid": upid_str,
+            "node": "pve1",
+            "type": "qmigrate",
+            "id": "616",
+            "status": status,
+            "exitstatus": (status == "stopped").then(|| "OK"),
+        })
+    };
+    let (url, handle) = mock_api(vec![(200, task("running")), (200, task("stopped"))])?;
+    let api = mock_client(url)?;
+    let upid = PathNNTTStatus::from_json(task("running"))?.upid().clone();
+    let status = api.wait_task(&upid, Duration::MAX).await?;
+    assert!(status.succeeded());
+
+    let path = format!("/api2/json/nodes/pve1/tasks/{upid_str}/status ");
+    assert_eq!(
+        mock_requests(handle)?,
+        [format!("GET {path}"), format!("GET {path}")]
+    );
+    Ok(())
+}
+
+/// Add a tag to the configuration of VM 616 on a [`mock_api`] server.
+///
+/// Return the result of the update and the requests received by the server.
+async fn mock_update_vm_config(
+    responses: Vec<(u16, JsonValue)>,
+) -> Result<(crate::Result<bool>, Vec<String>)> {
+    let (url, handle) = mock_api(responses)?;
+    let api = mock_client(url)?;
+    let path = api.path().nodes().id("pve1").qemu().id(616).config();
+    let res = api
+        .update_vm_config(path, 1, |vmcfg| {
+            vmcfg.tags_mut().insert("new".to_owned());
+        })
+        .await;
+    Ok((res, mock_requests(handle)?))
+}
+
+/// Summarize the requests received by a [`mock_api`] server, keeping only the digest of the body.
+fn mock_digests(requests: &[String]) -> Vec<String> {
+    requests
+        .iter()
+        .map(|req| {
+            let (method, rest) = req.split_once(' ').unwrap_or_default();
+            let (_, body) = rest.split_once(' ').unwrap_or_default();
+            match body
+                .split('&')
+                .find_map(|param| param.strip_prefix("digest="))
+            {
+                Some(digest) => format!("{method} {digest}"),
+                None => method.to_owned(),
+            }
+        })
+        .collect()
+}
+
+#[traced_test]
+#[tokio::test]
+async fn test_update_vm_config() -> Result<()> {
+    let vm_config = |digest: &str| json!({"digest": digest, "name": "test", "tags": "old"});
+    let failed = (500, JsonValue::Null);
+    let done = (200, JsonValue::Null);
+
+    let (res, requests) = mock_update_vm_config(vec![
+        (200, vm_config("a")),
+        failed.clone(),
+        (200, vm_config("b")),
+        (200, vm_config("b")),
+        done,
+    ])
+    .await?;
+    assert!(res?);
+    assert_eq!(
+        mock_digests(&requests),
+        ["GET", "PUT a", "GET", "GET", "PUT b"]
+    );
+    assert!(requests[4].contains("tags=new%3Bold") || requests[4].contains("tags=old%3Bnew"));
+
+    let (res, requests) = mock_update_vm_config(vec![
+        (200, vm_config("a")),
+        failed.clone(),
+        (200, vm_config("a")),
+    ])
+    .await?;
+    match res {
+        Err(Error::Api(err)) => info!("{err:#}"),
+        other => bail!("Expected an API error, got {other:?}"),
+    }
+    assert_eq!(mock_digests(&requests), ["GET", "PUT a", "GET"]);
+
+    let (res, requests) = mock_update_vm_config(vec![
+        (200, vm_config("a")),
+        failed.clone(),
+        (200, vm_config("b")),
+        (200, vm_config("b")),
+        failed,
+        (200, vm_config("c")),
+    ])
+    .await?;
+    match res {
+        Err(Error::Conflict(msg)) => info!(msg),
+        other => bail!("Expected a conflict, got {other:?}"),
+    }
+    assert_eq!(
+        mock_digests(&requests),
+        ["GET", "PUT a", "GET", "GET", "PUT b", "GET"]
+    );
+
+    let (res, requests) = mock_update_vm_config(vec![(
+        200,
+        json!({"digest": "a", "name": "test", "tags": "new;old"}),
+    )])
+    .await?;
+    assert!(!res?);
+    assert_eq!(mock_digests(&requests), ["GET"]);
+    Ok(())
+}
+
 #[traced_test]
 #[tokio::test]
 async fn test_api_queries() -> Result<()> {
@@ -136,7 +868,11 @@ async fn test_api_queries() -> Result<()> {
                 api_mode = cl_data.api_mode
             );
         }
-        (cl_name, cl_data.url)
+        let cl_url = cl_data
+            .url
+            .or_else(|| cl_data.endpoints.into_iter().next())
+            .with_context(|| format!("No API URL for '{cl_name}' in {cfg_path}"))?;
+        (cl_name, cl_url)
     };
 
     let auth_token = {
@@ -200,6 +936,15 @@ async fn test_api_queries() -> Result<()> {
                 .len()
         );
 
+        let node_storage = api
+            .get(node_path.clone().storage())
+            .await
+            .with_context(|| format!("get node {node_name} storage"))?;
+        info!(
+            "node {node_name}: storage count: {count}",
+            count = node_storage.len()
+        );
+
         let qemu_path = node_path.qemu();
         let vms = api
             .get(qemu_path.clone())
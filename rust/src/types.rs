@@ -2,8 +2,9 @@
 // SPDX-FileCopyrightText: StorPool <support@storpool.com>
 // SPDX-License-Identifier: BSD-2-Clause
 
-use std::collections::HashMap;
-use std::fmt::{Formatter, Result as FmtResult};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::result::Result as StdResult;
 use std::str::FromStr;
 
@@ -53,54 +54,187 @@ impl NameSubdir {
     }
 }
 
-/// A storage defined for the Proxmox VE cluster.
-///
-/// Maybe we should turn this into an enum...
-#[derive(Debug, Deserialize)]
-pub struct Storage {
-    /// A comma-separated list of content types that may be placed on this type of storage.
-    content: String,
+/// Deserialize a Proxmox VE boolean flag that may be sent as a number, a string, or a boolean.
+fn de_pve_bool<'de, D>(deserializer: D) -> StdResult<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::Bool(value) => Ok(value),
+        JsonValue::Number(value) => Ok(value.as_u64() != Some(0)),
+        JsonValue::String(value) => match value.as_str() {
+            "0" | "" => Ok(false),
+            "1" => Ok(true),
+            other => Err(DeError::custom(format!("invalid boolean value {other:?}"))),
+        },
+        other => Err(DeError::custom(format!("invalid boolean value {other:?}"))),
+    }
+}
 
-    /// The checksum of the current version of this storage definition.
-    digest: String,
+/// Deserialize a comma-separated list of strings into a set.
+fn de_comma_set<'de, D>(deserializer: D) -> StdResult<HashSet<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(parse::comma_set(&String::deserialize(deserializer)?))
+}
 
-    /// Additional tags to set for StorPool volumes and snapshots.
-    extra_tags: Option<String>,
+/// Deserialize an optional comma-separated list of strings into a set.
+fn de_opt_comma_set<'de, D>(deserializer: D) -> StdResult<Option<HashSet<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.map(|value| parse::comma_set(&value)))
+}
+
+/// Deserialize a list of Proxmox VE tags into a set.
+fn de_tag_set<'de, D>(deserializer: D) -> StdResult<HashSet<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?
+        .map(|value| parse::tag_set(&value))
+        .unwrap_or_default())
+}
+
+/// Deserialize the node list of a Proxmox VE HA group into node priorities.
+fn de_node_priorities<'de, D>(deserializer: D) -> StdResult<BTreeMap<String, Option<u32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse::node_priorities(&String::deserialize(deserializer)?).map_err(DeError::custom)
+}
+
+/// Deserialize a list of load averages that may be sent as strings or as numbers.
+fn de_loadavg<'de, D>(deserializer: D) -> StdResult<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<JsonValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|value| match value {
+            JsonValue::Number(ref num) => num
+                .as_f64()
+                .ok_or_else(|| DeError::custom(format!("invalid load average {value:?}"))),
+            JsonValue::String(ref text) => text
+                .parse()
+                .map_err(|_| DeError::custom(format!("invalid load average {value:?}"))),
+            other => Err(DeError::custom(format!("invalid load average {other:?}"))),
+        })
+        .collect()
+}
 
+/// The settings common to all the Proxmox VE storage types.
+#[derive(Debug, Deserialize)]
+pub struct StorageCommon {
     /// The name of the Proxmox VE storage.
     storage: String,
 
-    /// The name of the StorPool template, if not the same as the storage name.
-    template: Option<String>,
+    /// The checksum of the current version of this storage definition.
+    digest: Option<String>,
 
-    /// The Proxmox VE driver that handles this type of storage.
-    #[serde(rename = "type")]
-    storage_type: String,
+    /// The content types that may be placed on this storage.
+    #[serde(default, deserialize_with = "de_comma_set")]
+    content: HashSet<String>,
+
+    /// The nodes that this storage is enabled on, if not all of them.
+    #[serde(default, deserialize_with = "de_opt_comma_set")]
+    nodes: Option<HashSet<String>>,
+
+    /// Has this storage been disabled?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    disable: bool,
+
+    /// Is the same storage available on all the nodes?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    shared: bool,
 }
 
-impl Storage {
+impl StorageCommon {
     #[inline]
     #[must_use]
-    pub fn content(&self) -> &str {
+    pub fn storage(&self) -> &str {
+        &self.storage
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn content(&self) -> &HashSet<String> {
         &self.content
     }
 
     #[inline]
     #[must_use]
-    pub fn digest(&self) -> &str {
-        &self.digest
+    pub const fn nodes(&self) -> Option<&HashSet<String>> {
+        self.nodes.as_ref()
     }
 
     #[inline]
     #[must_use]
-    pub fn extra_tags(&self) -> Option<&str> {
-        self.extra_tags.as_deref()
+    pub const fn disable(&self) -> bool {
+        self.disable
     }
 
     #[inline]
     #[must_use]
-    pub fn storage(&self) -> &str {
-        &self.storage
+    pub const fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Is this storage enabled on the specified node?
+    #[inline]
+    #[must_use]
+    pub fn enabled_on(&self, node: &str) -> bool {
+        !self.disable
+            && self
+                .nodes
+                .as_ref()
+                .map_or(true, |nodes| nodes.contains(node))
+    }
+}
+
+/// Generate the accessor for the settings common to all storage types.
+macro_rules! storage_common {
+    ( $( $class:ident ),* ) => {
+        $(
+            impl $class {
+                /// The settings common to all storage types.
+                #[inline]
+                #[must_use]
+                pub const fn common(&self) -> &StorageCommon {
+                    &self.common
+                }
+            }
+        )*
+    };
+}
+
+/// A storage handled by the `StorPool` plugin.
+#[derive(Debug, Deserialize)]
+pub struct StorPoolStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// Additional tags to set for `StorPool` volumes and snapshots.
+    #[serde(rename = "extra-tags")]
+    extra_tags: Option<String>,
+
+    /// The name of the `StorPool` template, if not the same as the storage name.
+    template: Option<String>,
+}
+
+impl StorPoolStorage {
+    #[inline]
+    #[must_use]
+    pub fn extra_tags(&self) -> Option<&str> {
+        self.extra_tags.as_deref()
     }
 
     #[inline]
@@ -109,221 +243,1646 @@ impl Storage {
         self.template.as_deref()
     }
 
+    /// The name of the `StorPool` template used for this storage's volumes.
     #[inline]
     #[must_use]
-    pub fn storage_type(&self) -> &str {
-        &self.storage_type
+    pub fn template_name(&self) -> &str {
+        self.template.as_deref().unwrap_or(&self.common.storage)
     }
 }
 
-/// The status reported for a single node.
-///
-/// Note: any changes to this enum shall be considered breaking.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
-#[allow(clippy::exhaustive_enums)]
-pub enum NodeStatus {
-    /// The node is offline.
-    #[serde(rename = "offline")]
-    Offline,
+/// A directory on a local or mounted filesystem.
+#[derive(Debug, Deserialize)]
+pub struct DirStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
 
-    /// The node is online.
-    #[serde(rename = "online")]
-    Online,
+    /// The filesystem path to the directory.
+    path: String,
+}
 
-    /// There is no information about the node's status.
-    #[serde(rename = "unknown")]
-    Unknown,
+impl DirStorage {
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
 }
 
-/// General information about a Proxmox VE node.
+/// A Ceph RADOS block device pool.
 #[derive(Debug, Deserialize)]
-pub struct NodeSummary {
-    /// The node type (usually the constant string "node").
+pub struct RbdStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// The name of the Ceph pool.
+    pool: Option<String>,
+
+    /// The Ceph monitor addresses for an external cluster.
+    monhost: Option<String>,
+
+    /// Should the kernel RBD module be used instead of librbd?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    krbd: bool,
+}
+
+impl RbdStorage {
+    #[inline]
+    #[must_use]
+    pub fn pool(&self) -> Option<&str> {
+        self.pool.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn monhost(&self) -> Option<&str> {
+        self.monhost.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn krbd(&self) -> bool {
+        self.krbd
+    }
+}
+
+/// An LVM thin pool.
+#[derive(Debug, Deserialize)]
+pub struct LvmThinStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// The name of the LVM volume group.
+    vgname: String,
+
+    /// The name of the LVM thin pool.
+    thinpool: String,
+}
+
+impl LvmThinStorage {
+    #[inline]
+    #[must_use]
+    pub fn vgname(&self) -> &str {
+        &self.vgname
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn thinpool(&self) -> &str {
+        &self.thinpool
+    }
+}
+
+/// A ZFS pool or dataset.
+#[derive(Debug, Deserialize)]
+pub struct ZfsPoolStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// The name of the ZFS pool or dataset.
+    pool: String,
+
+    /// Should thin-provisioned volumes be created?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    sparse: bool,
+}
+
+impl ZfsPoolStorage {
+    #[inline]
+    #[must_use]
+    pub fn pool(&self) -> &str {
+        &self.pool
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn sparse(&self) -> bool {
+        self.sparse
+    }
+}
+
+/// An NFS export mounted on the nodes.
+#[derive(Debug, Deserialize)]
+pub struct NfsStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// The NFS server address.
+    server: String,
+
+    /// The exported path on the NFS server.
+    export: String,
+
+    /// The local mount point.
+    path: String,
+}
+
+impl NfsStorage {
+    #[inline]
+    #[must_use]
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn export(&self) -> &str {
+        &self.export
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// A storage handled by a driver that we do not know much about.
+#[derive(Debug, Deserialize)]
+pub struct OtherStorage {
+    /// The settings common to all storage types.
+    #[serde(flatten)]
+    common: StorageCommon,
+
+    /// The Proxmox VE driver that handles this type of storage.
     #[serde(rename = "type")]
-    node_type: String,
+    storage_type: String,
+}
 
-    /// The node name.
-    node: String,
+storage_common!(
+    DirStorage,
+    LvmThinStorage,
+    NfsStorage,
+    OtherStorage,
+    RbdStorage,
+    StorPoolStorage,
+    ZfsPoolStorage
+);
 
-    /// The node status in the Proxmox VE cluster.
-    status: NodeStatus,
+/// A storage defined for the Proxmox VE cluster.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StorageConfig {
+    /// A directory on a local or mounted filesystem.
+    Dir(DirStorage),
 
-    /// A qualified object identifier for this node.
+    /// An LVM thin pool.
+    LvmThin(LvmThinStorage),
+
+    /// An NFS export mounted on the nodes.
+    Nfs(NfsStorage),
+
+    /// A Ceph RADOS block device pool.
+    Rbd(RbdStorage),
+
+    /// A storage handled by the `StorPool` plugin.
+    StorPool(StorPoolStorage),
+
+    /// A ZFS pool or dataset.
+    ZfsPool(ZfsPoolStorage),
+
+    /// A storage handled by some other driver.
+    Other(OtherStorage),
+}
+
+impl StorageConfig {
+    /// The identifier of the directory storage driver.
+    const DIR: &str = "dir";
+
+    /// The identifier of the LVM thin pool storage driver.
+    const LVMTHIN: &str = "lvmthin";
+
+    /// The identifier of the NFS storage driver.
+    const NFS: &str = "nfs";
+
+    /// The identifier of the Ceph RBD storage driver.
+    const RBD: &str = "rbd";
+
+    /// The identifier of the `StorPool` storage driver.
+    const STORPOOL: &str = "storpool";
+
+    /// The identifier of the ZFS pool storage driver.
+    const ZFSPOOL: &str = "zfspool";
+
+    /// The settings common to all storage types.
+    #[inline]
+    #[must_use]
+    pub const fn common(&self) -> &StorageCommon {
+        match *self {
+            Self::Dir(ref store) => store.common(),
+            Self::LvmThin(ref store) => store.common(),
+            Self::Nfs(ref store) => store.common(),
+            Self::Rbd(ref store) => store.common(),
+            Self::StorPool(ref store) => store.common(),
+            Self::ZfsPool(ref store) => store.common(),
+            Self::Other(ref store) => store.common(),
+        }
+    }
+
+    /// The name of the Proxmox VE storage.
+    #[inline]
+    #[must_use]
+    pub fn storage(&self) -> &str {
+        self.common().storage()
+    }
+
+    /// The Proxmox VE driver that handles this type of storage.
+    #[inline]
+    #[must_use]
+    pub fn storage_type(&self) -> &str {
+        match *self {
+            Self::Dir(_) => Self::DIR,
+            Self::LvmThin(_) => Self::LVMTHIN,
+            Self::Nfs(_) => Self::NFS,
+            Self::Rbd(_) => Self::RBD,
+            Self::StorPool(_) => Self::STORPOOL,
+            Self::ZfsPool(_) => Self::ZFSPOOL,
+            Self::Other(ref store) => &store.storage_type,
+        }
+    }
+
+    /// The `StorPool`-specific settings if this is a `StorPool` storage.
+    #[inline]
+    #[must_use]
+    pub const fn as_storpool(&self) -> Option<&StorPoolStorage> {
+        match *self {
+            Self::StorPool(ref store) => Some(store),
+            Self::Dir(_)
+            | Self::LvmThin(_)
+            | Self::Nfs(_)
+            | Self::Rbd(_)
+            | Self::ZfsPool(_)
+            | Self::Other(_) => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StorageConfig {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = JsonValue::deserialize(deserializer)?;
+        let storage_type = raw
+            .get("type")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| DeError::custom("no storage type specified"))?
+            .to_owned();
+        let res = match storage_type.as_str() {
+            Self::DIR => serde_json::from_value(raw).map(Self::Dir),
+            Self::LVMTHIN => serde_json::from_value(raw).map(Self::LvmThin),
+            Self::NFS => serde_json::from_value(raw).map(Self::Nfs),
+            Self::RBD => serde_json::from_value(raw).map(Self::Rbd),
+            Self::STORPOOL => serde_json::from_value(raw).map(Self::StorPool),
+            Self::ZFSPOOL => serde_json::from_value(raw).map(Self::ZfsPool),
+            _ => serde_json::from_value(raw).map(Self::Other),
+        };
+        res.map_err(|err| {
+            DeError::custom(format!(
+                "Could not parse a '{storage_type}' storage definition: {err}"
+            ))
+        })
+    }
+}
+
+/// A single entry in the Proxmox VE cluster status: either the cluster itself or a node.
+#[derive(Debug, Deserialize)]
+pub struct ClusterStatusItem {
+    /// The type of the entry: "cluster" or "node".
+    #[serde(rename = "type")]
+    item_type: String,
+
+    /// A qualified identifier for the entry, e.g. "cluster" or "node/pve1".
     id: String,
 
-    /// CPU utilization.
-    cpu: Option<f64>,
+    /// The name of the cluster or the node.
+    name: String,
 
-    /// Disk usage.
-    disk: Option<u64>,
+    /// Does the cluster have quorum? Only set for the "cluster" entry.
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    quorate: bool,
 
-    /// Support level.
-    level: Option<String>,
+    /// The number of nodes in the cluster; only set for the "cluster" entry.
+    nodes: Option<u32>,
 
-    /// Number of available CPUs.
-    maxcpu: Option<u32>,
+    /// The version of the cluster configuration; only set for the "cluster" entry.
+    version: Option<u32>,
 
-    /// Available disk space.
-    maxdisk: Option<u64>,
+    /// The ID of the node in the cluster; only set for the "node" entries.
+    nodeid: Option<u32>,
 
-    /// Available memory in bytes.
-    maxmem: Option<u64>,
+    /// The IP address of the node; only set for the "node" entries.
+    ip: Option<String>,
 
-    /// Used memory in bytes.
-    mem: Option<u64>,
+    /// Is this the node that the API request was sent to?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    local: bool,
 
-    /// The SSL fingerprint of the node certificate.
-    ssl_fingerprint: Option<String>,
+    /// Is the node online?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    online: bool,
 
-    /// Node uptime in seconds.
-    uptime: Option<u64>,
+    /// The support level of the node's subscription.
+    level: Option<String>,
 }
 
-impl NodeSummary {
+impl ClusterStatusItem {
     #[inline]
     #[must_use]
-    pub fn node(&self) -> &str {
-        &self.node
+    pub fn item_type(&self) -> &str {
+        &self.item_type
     }
 
     #[inline]
     #[must_use]
-    pub const fn status(&self) -> NodeStatus {
-        self.status
+    pub fn id(&self) -> &str {
+        &self.id
     }
 
     #[inline]
     #[must_use]
-    pub const fn cpu(&self) -> Option<f64> {
-        self.cpu
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn quorate(&self) -> bool {
+        self.quorate
     }
 
-    #[inline]
-    #[must_use]
-    pub const fn disk(&self) -> Option<u64> {
-        self.disk
-    }
+    #[inline]
+    #[must_use]
+    pub const fn nodes(&self) -> Option<u32> {
+        self.nodes
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn nodeid(&self) -> Option<u32> {
+        self.nodeid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn local(&self) -> bool {
+        self.local
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn online(&self) -> bool {
+        self.online
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+
+    /// Is this the entry describing the cluster as a whole?
+    #[inline]
+    #[must_use]
+    pub fn is_cluster(&self) -> bool {
+        self.item_type == "cluster"
+    }
+
+    /// Is this an entry describing a single node?
+    #[inline]
+    #[must_use]
+    pub fn is_node(&self) -> bool {
+        self.item_type == "node"
+    }
+}
+
+/// A guest managed by the Proxmox VE high availability stack.
+#[derive(Debug, Deserialize)]
+pub struct HaResource {
+    /// The service identifier, e.g. "vm:616".
+    sid: String,
+
+    /// The type of the resource, e.g. "vm" or "ct".
+    #[serde(rename = "type")]
+    resource_type: String,
+
+    /// The requested state of the resource, e.g. "started".
+    state: Option<String>,
+
+    /// The HA group that the resource belongs to, if any.
+    group: Option<String>,
+
+    /// The maximum number of restarts on the same node after a failure.
+    max_restart: Option<u32>,
+
+    /// The maximum number of relocations to other nodes after a failure.
+    max_relocate: Option<u32>,
+
+    /// A free-form description of the resource.
+    comment: Option<String>,
+}
+
+impl HaResource {
+    #[inline]
+    #[must_use]
+    pub fn sid(&self) -> &str {
+        &self.sid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn resource_type(&self) -> &str {
+        &self.resource_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_restart(&self) -> Option<u32> {
+        self.max_restart
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_relocate(&self) -> Option<u32> {
+        self.max_relocate
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// The ID of the QEMU virtual machine, if this resource is one.
+    #[inline]
+    #[must_use]
+    pub fn vmid(&self) -> Option<u32> {
+        self.sid
+            .strip_prefix("vm:")
+            .and_then(|vmid| vmid.parse().ok())
+    }
+}
+
+/// A group of nodes that HA-managed guests may run on.
+#[derive(Debug, Deserialize)]
+pub struct HaGroup {
+    /// The name of the group.
+    group: String,
+
+    /// The nodes in the group along with their priorities, if specified.
+    #[serde(deserialize_with = "de_node_priorities")]
+    nodes: BTreeMap<String, Option<u32>>,
+
+    /// May the guests only run on the nodes in the group?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    restricted: bool,
+
+    /// Should the guests stay on a lower priority node when a higher priority one comes back?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    nofailback: bool,
+
+    /// A free-form description of the group.
+    comment: Option<String>,
+}
+
+impl HaGroup {
+    #[inline]
+    #[must_use]
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn nodes(&self) -> &BTreeMap<String, Option<u32>> {
+        &self.nodes
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn restricted(&self) -> bool {
+        self.restricted
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn nofailback(&self) -> bool {
+        self.nofailback
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+}
+
+/// A single entry in the current status of the Proxmox VE high availability stack.
+#[derive(Debug, Deserialize)]
+pub struct HaStatusItem {
+    /// The identifier of the entry, e.g. "quorum", "master", "lrm:pve1", or "service:vm:616".
+    id: String,
+
+    /// The type of the entry: "quorum", "master", "lrm", or "service".
+    #[serde(rename = "type")]
+    item_type: String,
+
+    /// A human-readable description of the entry's status.
+    status: Option<String>,
+
+    /// The node that the entry refers to, if any.
+    node: Option<String>,
+
+    /// The service identifier for the "service" entries, e.g. "vm:616".
+    sid: Option<String>,
+
+    /// The current state of a service, e.g. "started" or "fence".
+    state: Option<String>,
+
+    /// Does the HA stack have quorum? Only set for the "quorum" entry.
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    quorate: bool,
+}
+
+impl HaStatusItem {
+    #[inline]
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn item_type(&self) -> &str {
+        &self.item_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn node(&self) -> Option<&str> {
+        self.node.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn sid(&self) -> Option<&str> {
+        self.sid.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn quorate(&self) -> bool {
+        self.quorate
+    }
+}
+
+/// The status reported for a single node.
+///
+/// Note: any changes to this enum shall be considered breaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[allow(clippy::exhaustive_enums)]
+pub enum NodeStatus {
+    /// The node is offline.
+    #[serde(rename = "offline")]
+    Offline,
+
+    /// The node is online.
+    #[serde(rename = "online")]
+    Online,
+
+    /// There is no information about the node's status.
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl AsRef<str> for NodeStatus {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        match *self {
+            Self::Offline => "offline",
+            Self::Online => "online",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// General information about a Proxmox VE node.
+#[derive(Debug, Deserialize)]
+pub struct NodeSummary {
+    /// The node type (usually the constant string "node").
+    #[serde(rename = "type")]
+    node_type: String,
+
+    /// The node name.
+    node: String,
+
+    /// The node status in the Proxmox VE cluster.
+    status: NodeStatus,
+
+    /// A qualified object identifier for this node.
+    id: String,
+
+    /// CPU utilization.
+    cpu: Option<f64>,
+
+    /// Disk usage.
+    disk: Option<u64>,
+
+    /// Support level.
+    level: Option<String>,
+
+    /// Number of available CPUs.
+    maxcpu: Option<u32>,
+
+    /// Available disk space.
+    maxdisk: Option<u64>,
+
+    /// Available memory in bytes.
+    maxmem: Option<u64>,
+
+    /// Used memory in bytes.
+    mem: Option<u64>,
+
+    /// The SSL fingerprint of the node certificate.
+    ssl_fingerprint: Option<String>,
+
+    /// Node uptime in seconds.
+    uptime: Option<u64>,
+}
+
+impl NodeSummary {
+    #[inline]
+    #[must_use]
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn status(&self) -> NodeStatus {
+        self.status
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn cpu(&self) -> Option<f64> {
+        self.cpu
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn disk(&self) -> Option<u64> {
+        self.disk
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maxcpu(&self) -> Option<u32> {
+        self.maxcpu
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maxdisk(&self) -> Option<u64> {
+        self.maxdisk
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maxmem(&self) -> Option<u64> {
+        self.maxmem
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn mem(&self) -> Option<u64> {
+        self.mem
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn node_type(&self) -> &str {
+        &self.node_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn ssl_fingerprint(&self) -> Option<&str> {
+        self.ssl_fingerprint.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn uptime(&self) -> Option<u64> {
+        self.uptime
+    }
+}
+
+/// The version of the kernel running on a Proxmox VE node.
+#[derive(Debug, Deserialize)]
+pub struct NodeKernel {
+    /// The operating system name, e.g. "Linux".
+    sysname: String,
+
+    /// The kernel release, e.g. "6.8.12-4-pve".
+    release: String,
+
+    /// The kernel build version.
+    version: String,
+
+    /// The hardware architecture, e.g. `x86_64`.
+    machine: String,
+}
+
+impl NodeKernel {
+    #[inline]
+    #[must_use]
+    pub fn sysname(&self) -> &str {
+        &self.sysname
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn release(&self) -> &str {
+        &self.release
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn machine(&self) -> &str {
+        &self.machine
+    }
+}
+
+/// The memory usage of a Proxmox VE node, in bytes.
+#[derive(Debug, Deserialize)]
+pub struct NodeMemory {
+    /// The total amount of memory.
+    total: u64,
+
+    /// The used memory.
+    used: u64,
+
+    /// The free memory.
+    free: u64,
+}
+
+impl NodeMemory {
+    #[inline]
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> u64 {
+        self.used
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn free(&self) -> u64 {
+        self.free
+    }
+}
+
+/// The usage of the root filesystem of a Proxmox VE node, in bytes.
+#[derive(Debug, Deserialize)]
+pub struct NodeRootFs {
+    /// The total size of the filesystem.
+    total: u64,
+
+    /// The used space.
+    used: u64,
+
+    /// The free space.
+    free: u64,
+
+    /// The space available to unprivileged users.
+    avail: u64,
+}
+
+impl NodeRootFs {
+    #[inline]
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> u64 {
+        self.used
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn free(&self) -> u64 {
+        self.free
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn avail(&self) -> u64 {
+        self.avail
+    }
+}
+
+/// Detailed status information about a single Proxmox VE node.
+#[derive(Debug, Deserialize)]
+pub struct NodeDetails {
+    /// The full kernel version string, e.g. "Linux 6.8.12-4-pve #1 SMP ...".
+    kversion: Option<String>,
+
+    /// The version of the running kernel, split into its components.
+    #[serde(rename = "current-kernel")]
+    current_kernel: Option<NodeKernel>,
+
+    /// The version of the Proxmox VE manager, e.g. "pve-manager/8.2.4/faa83925c9641325".
+    pveversion: String,
+
+    /// The 1, 5, and 15 minute load averages.
+    #[serde(default, deserialize_with = "de_loadavg")]
+    loadavg: Vec<f64>,
+
+    /// The memory usage.
+    memory: NodeMemory,
+
+    /// The root filesystem usage.
+    rootfs: NodeRootFs,
+
+    /// CPU utilization.
+    cpu: Option<f64>,
+
+    /// Node uptime in seconds.
+    uptime: Option<u64>,
+}
+
+impl NodeDetails {
+    #[inline]
+    #[must_use]
+    pub fn kversion(&self) -> Option<&str> {
+        self.kversion.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn current_kernel(&self) -> Option<&NodeKernel> {
+        self.current_kernel.as_ref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn pveversion(&self) -> &str {
+        &self.pveversion
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn loadavg(&self) -> &[f64] {
+        &self.loadavg
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn memory(&self) -> &NodeMemory {
+        &self.memory
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn rootfs(&self) -> &NodeRootFs {
+        &self.rootfs
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn cpu(&self) -> Option<f64> {
+        self.cpu
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn uptime(&self) -> Option<u64> {
+        self.uptime
+    }
+}
+
+/// The status of a storage as seen by a single Proxmox VE node.
+#[derive(Debug, Deserialize)]
+pub struct NodeStorageStatus {
+    /// The Proxmox VE driver that handles this type of storage.
+    #[serde(rename = "type")]
+    storage_type: String,
+
+    /// The content types that may be placed on this storage.
+    #[serde(default, deserialize_with = "de_comma_set")]
+    content: HashSet<String>,
+
+    /// The total size of the storage in bytes.
+    total: Option<u64>,
+
+    /// The used space in bytes.
+    used: Option<u64>,
+
+    /// The available space in bytes.
+    avail: Option<u64>,
+
+    /// Is the storage active on this node?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    active: bool,
+
+    /// Is the storage enabled on this node?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    enabled: bool,
+
+    /// Is the same storage available on all the nodes?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    shared: bool,
+}
+
+impl NodeStorageStatus {
+    #[inline]
+    #[must_use]
+    pub fn storage_type(&self) -> &str {
+        &self.storage_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn content(&self) -> &HashSet<String> {
+        &self.content
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> Option<u64> {
+        self.used
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn avail(&self) -> Option<u64> {
+        self.avail
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn active(&self) -> bool {
+        self.active
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn shared(&self) -> bool {
+        self.shared
+    }
+}
+
+/// A storage available on a single Proxmox VE node.
+#[derive(Debug, Deserialize)]
+pub struct NodeStorage {
+    /// The name of the Proxmox VE storage.
+    storage: String,
+
+    /// The status of the storage as seen by the node.
+    #[serde(flatten)]
+    status: NodeStorageStatus,
+}
+
+impl NodeStorage {
+    #[inline]
+    #[must_use]
+    pub fn storage(&self) -> &str {
+        &self.storage
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn status(&self) -> &NodeStorageStatus {
+        &self.status
+    }
+}
+
+/// A single volume stored on a Proxmox VE storage.
+#[derive(Debug, Deserialize)]
+pub struct StorageContent {
+    /// The full volume identifier, e.g. `sp-ssd:vm-616-disk-0-sp-4.1.a.raw`.
+    volid: String,
+
+    /// The content type of the volume, e.g. "images" or "iso".
+    content: String,
+
+    /// The format of the volume data, e.g. "raw".
+    format: String,
+
+    /// The size of the volume in bytes.
+    size: u64,
+
+    /// The space actually used by the volume in bytes, if known.
+    used: Option<u64>,
+
+    /// The ID of the virtual machine that owns this volume, if any.
+    vmid: Option<u32>,
+
+    /// The volume that this one was cloned from, if any.
+    parent: Option<String>,
+}
+
+impl StorageContent {
+    #[inline]
+    #[must_use]
+    pub fn volid(&self) -> &str {
+        &self.volid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn used(&self) -> Option<u64> {
+        self.used
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn vmid(&self) -> Option<u32> {
+        self.vmid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+}
+
+/// Summary information about a Proxmox VE resource pool.
+#[derive(Debug, Deserialize)]
+pub struct PoolSummary {
+    /// The name of the pool.
+    poolid: String,
+
+    /// A free-form description of the pool.
+    comment: Option<String>,
+}
+
+impl PoolSummary {
+    #[inline]
+    #[must_use]
+    pub fn poolid(&self) -> &str {
+        &self.poolid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+}
+
+/// A guest or a storage that belongs to a Proxmox VE resource pool.
+#[derive(Debug, Deserialize)]
+pub struct PoolMember {
+    /// The resource identifier, e.g. "qemu/616" or "storage/local/sp-ssd".
+    id: String,
+
+    /// The type of the resource, e.g. "qemu", "lxc", or "storage".
+    #[serde(rename = "type")]
+    member_type: String,
+
+    /// The node that the resource is on.
+    node: Option<String>,
+
+    /// The ID of the guest, if the resource is a virtual machine or a container.
+    vmid: Option<u32>,
+
+    /// The name of the storage, if the resource is a storage.
+    storage: Option<String>,
+}
+
+impl PoolMember {
+    #[inline]
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn member_type(&self) -> &str {
+        &self.member_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn node(&self) -> Option<&str> {
+        self.node.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn vmid(&self) -> Option<u32> {
+        self.vmid
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn storage(&self) -> Option<&str> {
+        self.storage.as_deref()
+    }
+
+    /// Is this resource a QEMU virtual machine?
+    #[inline]
+    #[must_use]
+    pub fn is_qemu(&self) -> bool {
+        self.member_type == "qemu"
+    }
+}
+
+/// Detailed information about a single Proxmox VE resource pool.
+#[derive(Debug, Deserialize)]
+pub struct PoolInfo {
+    /// A free-form description of the pool.
+    comment: Option<String>,
+
+    /// The guests and storages that belong to the pool.
+    #[serde(default)]
+    members: Vec<PoolMember>,
+}
+
+impl PoolInfo {
+    #[inline]
+    #[must_use]
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn members(&self) -> &[PoolMember] {
+        &self.members
+    }
+}
+
+/// The status reported for a single virtual machine.
+///
+/// Note: any changes to this enum shall be considered breaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[allow(clippy::exhaustive_enums)]
+pub enum VmStatus {
+    /// The VM is running.
+    #[serde(rename = "running")]
+    Running,
+
+    /// The VM is stopped.
+    #[serde(rename = "stopped")]
+    Stopped,
+}
+
+/// Summary information about a Proxmox VE virtual machine.
+#[derive(Debug, Deserialize)]
+pub struct VmSummary {
+    /// The ID of the VM.
+    vmid: u32,
+
+    /// QEMU process status.
+    status: VmStatus,
+
+    /// Maximum usable CPUs.
+    cpus: Option<u32>,
+
+    /// The current config lock, if any.
+    lock: Option<String>,
+
+    /// The name of the VM.
+    name: Option<String>,
+
+    /// The maximum memory available to the VM in bytes.
+    maxmem: Option<u64>,
+
+    /// The memory currently used by the VM in bytes.
+    mem: Option<u64>,
+
+    /// The tags assigned to the VM.
+    #[serde(default, deserialize_with = "de_tag_set")]
+    tags: HashSet<String>,
+}
+
+impl VmSummary {
+    #[inline]
+    #[must_use]
+    pub const fn vmid(&self) -> u32 {
+        self.vmid
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn status(&self) -> VmStatus {
+        self.status
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn cpus(&self) -> Option<u32> {
+        self.cpus
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn lock(&self) -> Option<&str> {
+        self.lock.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maxmem(&self) -> Option<u64> {
+        self.maxmem
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn mem(&self) -> Option<u64> {
+        self.mem
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn tags(&self) -> &HashSet<String> {
+        &self.tags
+    }
+}
+
+/// A snapshot of a Proxmox VE virtual machine.
+///
+/// The snapshot list also contains a "current" entry for the running state of the VM.
+#[derive(Debug, Deserialize)]
+pub struct VmSnapshot {
+    /// The name of the snapshot.
+    name: String,
+
+    /// The description of the snapshot.
+    #[serde(default)]
+    description: String,
+
+    /// The time the snapshot was taken at, in seconds since the Unix epoch.
+    snaptime: Option<u64>,
+
+    /// Does the snapshot include the VM's memory state?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    vmstate: bool,
+
+    /// The name of the snapshot that this one was taken after.
+    parent: Option<String>,
+}
+
+impl VmSnapshot {
+    /// The name of the pseudo-snapshot representing the current state of the VM.
+    pub const CURRENT: &'static str = "current";
+
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn snaptime(&self) -> Option<u64> {
+        self.snaptime
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn vmstate(&self) -> bool {
+        self.vmstate
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    /// Is this the pseudo-snapshot representing the current state of the VM?
+    #[inline]
+    #[must_use]
+    pub fn is_current(&self) -> bool {
+        self.name == Self::CURRENT
+    }
+}
+
+/// The preconditions for migrating a virtual machine to another node.
+#[derive(Debug, Deserialize)]
+pub struct VmMigrateInfo {
+    /// Is the VM currently running?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    running: bool,
+
+    /// The nodes that the VM may be migrated to, if a target was specified.
+    #[serde(default)]
+    allowed_nodes: Vec<String>,
+
+    /// The disks that are stored on the local node and would need to be copied.
+    #[serde(default)]
+    local_disks: Vec<JsonValue>,
+
+    /// The local resources (passed-through devices, etc.) that prevent a migration.
+    #[serde(default)]
+    local_resources: Vec<String>,
+}
+
+impl VmMigrateInfo {
+    #[inline]
+    #[must_use]
+    pub const fn running(&self) -> bool {
+        self.running
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn allowed_nodes(&self) -> &[String] {
+        &self.allowed_nodes
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn local_disks(&self) -> &[JsonValue] {
+        &self.local_disks
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn local_resources(&self) -> &[String] {
+        &self.local_resources
+    }
+}
+
+/// The unique identifier of a Proxmox VE task, e.g. `UPID:pve1:0001:0002:0003:qmigrate:616:root@pam:`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct Upid(String);
+
+impl Upid {
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The name of the node that the task runs on.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the task identifier is not in the expected format.
+    #[inline]
+    pub fn node(&self) -> Result<&str> {
+        let mut fields = self.0.split(':');
+        match (fields.next(), fields.next()) {
+            (Some("UPID"), Some(node)) if !node.is_empty() => Ok(node),
+            _ => Err(Error::Api(anyhow!(
+                "Invalid task identifier {upid:?}",
+                upid = self.0
+            ))),
+        }
+    }
+}
+
+/// The state of a Proxmox VE task.
+///
+/// Note: any changes to this enum shall be considered breaking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[allow(clippy::exhaustive_enums)]
+pub enum TaskState {
+    /// The task is still running.
+    #[serde(rename = "running")]
+    Running,
+
+    /// The task has completed, successfully or not.
+    #[serde(rename = "stopped")]
+    Stopped,
+}
+
+/// The current status of a Proxmox VE task.
+#[derive(Debug, Deserialize)]
+pub struct TaskStatus {
+    /// The unique identifier of the task.
+    upid: Upid,
+
+    /// The node that the task runs on.
+    node: String,
+
+    /// The type of the task, e.g. "qmigrate".
+    #[serde(rename = "type")]
+    task_type: String,
+
+    /// The object that the task operates on, e.g. a VM ID.
+    id: Option<String>,
 
-    #[inline]
-    #[must_use]
-    pub fn id(&self) -> &str {
-        &self.id
-    }
+    /// Is the task still running?
+    status: TaskState,
 
-    #[inline]
-    #[must_use]
-    pub fn level(&self) -> Option<&str> {
-        self.level.as_deref()
-    }
+    /// The exit status of a completed task, "OK" on success.
+    exitstatus: Option<String>,
+}
 
+impl TaskStatus {
     #[inline]
     #[must_use]
-    pub const fn maxcpu(&self) -> Option<u32> {
-        self.maxcpu
+    pub const fn upid(&self) -> &Upid {
+        &self.upid
     }
 
     #[inline]
     #[must_use]
-    pub const fn maxdisk(&self) -> Option<u64> {
-        self.maxdisk
+    pub fn node(&self) -> &str {
+        &self.node
     }
 
     #[inline]
     #[must_use]
-    pub const fn maxmem(&self) -> Option<u64> {
-        self.maxmem
+    pub fn task_type(&self) -> &str {
+        &self.task_type
     }
 
     #[inline]
     #[must_use]
-    pub const fn mem(&self) -> Option<u64> {
-        self.mem
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
     }
 
     #[inline]
     #[must_use]
-    pub fn node_type(&self) -> &str {
-        &self.node_type
+    pub const fn status(&self) -> TaskState {
+        self.status
     }
 
     #[inline]
     #[must_use]
-    pub fn ssl_fingerprint(&self) -> Option<&str> {
-        self.ssl_fingerprint.as_deref()
+    pub fn exitstatus(&self) -> Option<&str> {
+        self.exitstatus.as_deref()
     }
 
+    /// Has the task completed successfully?
     #[inline]
     #[must_use]
-    pub const fn uptime(&self) -> Option<u64> {
-        self.uptime
+    pub fn succeeded(&self) -> bool {
+        self.status == TaskState::Stopped && self.exitstatus.as_deref() == Some("OK")
     }
 }
 
-/// The status reported for a single virtual machine.
-///
-/// Note: any changes to this enum shall be considered breaking.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
-#[allow(clippy::exhaustive_enums)]
-pub enum VmStatus {
-    /// The VM is running.
-    #[serde(rename = "running")]
-    Running,
-
-    /// The VM is stopped.
-    #[serde(rename = "stopped")]
-    Stopped,
-}
-
-/// Summary information about a Proxmox VE virtual machine.
+/// Summary information about a task that was started on a Proxmox VE node.
 #[derive(Debug, Deserialize)]
-pub struct VmSummary {
-    /// The ID of the VM.
-    vmid: u32,
+pub struct TaskSummary {
+    /// The unique identifier of the task.
+    upid: Upid,
 
-    /// QEMU process status.
-    status: VmStatus,
+    /// The type of the task, e.g. "qmigrate".
+    #[serde(rename = "type")]
+    task_type: String,
 
-    /// Maximum usable CPUs.
-    cpus: Option<u32>,
+    /// The object that the task operates on, e.g. a VM ID.
+    id: Option<String>,
 
-    /// The current config lock, if any.
-    lock: Option<String>,
+    /// The exit status of a completed task, "OK" on success.
+    status: Option<String>,
 }
 
-impl VmSummary {
+impl TaskSummary {
     #[inline]
     #[must_use]
-    pub const fn vmid(&self) -> u32 {
-        self.vmid
+    pub const fn upid(&self) -> &Upid {
+        &self.upid
     }
 
     #[inline]
     #[must_use]
-    pub const fn status(&self) -> VmStatus {
-        self.status
+    pub fn task_type(&self) -> &str {
+        &self.task_type
     }
 
     #[inline]
     #[must_use]
-    pub const fn cpus(&self) -> Option<u32> {
-        self.cpus
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
     }
 
     #[inline]
     #[must_use]
-    pub fn lock(&self) -> Option<&str> {
-        self.lock.as_deref()
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[allow(clippy::exhaustive_enums)]
 pub enum VmNetIface {
     Virtio(u32, String),
 }
 
 /// The type of the disk as seen by the virtual machine (IDE, SCSI, etc.).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(clippy::exhaustive_enums)]
 pub enum VmDiskType {
     /// A simulated IDE disk.
@@ -381,7 +1940,7 @@ impl FromStr for VmDiskType {
 }
 
 /// A single disk attached to a Proxmox VE virtual machine.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VmDisk {
     /// The disk type as seen by the VM.
     disk_type: VmDiskType,
@@ -425,6 +1984,52 @@ impl VmDisk {
     pub const fn options(&self) -> &HashMap<String, String> {
         &self.options
     }
+    #[inline]
+    pub fn options_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.options
+    }
+
+    /// The name of the configuration key for this disk, e.g. "scsi0".
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> String {
+        format!(
+            "{disk_type}{idx}",
+            disk_type = self.disk_type.as_ref(),
+            idx = self.idx
+        )
+    }
+
+    /// Build the disk definition string as expected in the VM configuration.
+    ///
+    /// The options are sorted by name so that the same disk always yields the same string.
+    #[inline]
+    #[must_use]
+    pub fn to_value(&self) -> String {
+        let mut options: Vec<(&String, &String)> = self.options.iter().collect();
+        options.sort();
+        options.into_iter().fold(
+            format!(
+                "{storage}:{volid}",
+                storage = self.storage,
+                volid = self.volid
+            ),
+            |acc, (name, value)| format!("{acc},{name}={value}"),
+        )
+    }
+
+    /// The size of the disk in bytes as specified in the `size` option, if any.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the `size` option cannot be parsed.
+    #[inline]
+    pub fn size(&self) -> Result<Option<u64>> {
+        self.options
+            .get("size")
+            .map(|value| parse::disk_size(value))
+            .transpose()
+    }
 }
 
 /// Parse the definition of a Proxmox VE disk as found in the VM configuration.
@@ -445,7 +2050,7 @@ pub fn parse_disk(disk_type: VmDiskType, idx: u32, contents: &str) -> Result<VmD
 }
 
 // The configuration of a Proxmox VE virtual machine.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct VmConfig {
     /// The checksum of the current version of the virtual machine's configuration.
     digest: String,
@@ -458,6 +2063,9 @@ pub struct VmConfig {
 
     /// The various disks (IDE, SATA, SCSI, Virtio) attached to the virtual machine.
     disks: Vec<VmDisk>,
+
+    /// The tags assigned to the virtual machine.
+    tags: HashSet<String>,
 }
 
 impl VmConfig {
@@ -481,6 +2089,75 @@ impl VmConfig {
     pub fn disks(&self) -> &[VmDisk] {
         &self.disks
     }
+    #[inline]
+    #[must_use]
+    pub const fn tags(&self) -> &HashSet<String> {
+        &self.tags
+    }
+    #[inline]
+    pub fn set_scsihw(&mut self, scsihw: Option<String>) {
+        self.scsihw = scsihw;
+    }
+    #[inline]
+    pub fn net_mut(&mut self) -> &mut Vec<VmNetIface> {
+        &mut self.net
+    }
+    #[inline]
+    pub fn disks_mut(&mut self) -> &mut Vec<VmDisk> {
+        &mut self.disks
+    }
+    #[inline]
+    pub fn tags_mut(&mut self) -> &mut HashSet<String> {
+        &mut self.tags
+    }
+
+    /// Build the configuration keys and values for the settings that we know about.
+    #[inline]
+    #[must_use]
+    pub fn to_params(&self) -> BTreeMap<String, String> {
+        let mut res = BTreeMap::new();
+        if let Some(ref scsihw) = self.scsihw {
+            res.insert("scsihw".to_owned(), scsihw.clone());
+        }
+        for iface in &self.net {
+            match *iface {
+                VmNetIface::Virtio(idx, ref value) => {
+                    res.insert(format!("net{idx}"), value.clone());
+                }
+            }
+        }
+        for disk in &self.disks {
+            res.insert(disk.key(), disk.to_value());
+        }
+        if !self.tags.is_empty() {
+            let mut tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            tags.sort_unstable();
+            res.insert("tags".to_owned(), tags.join(";"));
+        }
+        res
+    }
+
+    /// Compare to an updated version of the configuration.
+    ///
+    /// Return the keys that were added or changed along with their new values,
+    /// and the keys that were removed.
+    #[inline]
+    #[must_use]
+    pub fn changes(&self, updated: &Self) -> (BTreeMap<String, String>, Vec<String>) {
+        let old = self.to_params();
+        let new = updated.to_params();
+        let changed = new
+            .iter()
+            .filter(|&(key, value)| old.get(key) != Some(value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let removed = old
+            .keys()
+            .filter(|key| !new.contains_key(*key))
+            .cloned()
+            .collect();
+        (changed, removed)
+    }
 }
 
 /// A helper for deserializing the [`VmConfig`] struct.
@@ -573,6 +2250,30 @@ impl<'de> Visitor<'de> for VmConfigVisitor {
                             )));
                         }
                     }
+                    "scsihw" => {
+                        if let JsonValue::String(value) = raw_value {
+                            res = VmConfig {
+                                scsihw: Some(value),
+                                ..res
+                            };
+                        } else {
+                            return Err(DeError::custom(format!(
+                                "unexpected 'scsihw' value: {raw_value:?}"
+                            )));
+                        }
+                    }
+                    "tags" => {
+                        if let JsonValue::String(value) = raw_value {
+                            res = VmConfig {
+                                tags: parse::tag_set(&value),
+                                ..res
+                            };
+                        } else {
+                            return Err(DeError::custom(format!(
+                                "unexpected 'tags' value: {raw_value:?}"
+                            )));
+                        }
+                    }
                     _ => (),
                 }
             }
@@ -590,3 +2291,287 @@ impl<'de> Deserialize<'de> for VmConfig {
         deserializer.deserialize_map(VmConfigVisitor)
     }
 }
+
+/// A single setting in the configuration of a virtual machine along with its pending changes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VmPendingItem {
+    /// The name of the configuration setting.
+    key: String,
+
+    /// The current value of the setting, if it is set.
+    value: Option<JsonValue>,
+
+    /// The value that the setting will have once the pending changes are applied.
+    pending: Option<JsonValue>,
+
+    /// Will the setting be removed once the pending changes are applied?
+    #[serde(default, deserialize_with = "de_pve_bool")]
+    delete: bool,
+}
+
+impl VmPendingItem {
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn value(&self) -> Option<&JsonValue> {
+        self.value.as_ref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn pending(&self) -> Option<&JsonValue> {
+        self.pending.as_ref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn delete(&self) -> bool {
+        self.delete
+    }
+
+    /// Will this setting change once the pending changes are applied?
+    #[inline]
+    #[must_use]
+    pub const fn is_pending(&self) -> bool {
+        self.delete || self.pending.is_some()
+    }
+}
+
+/// The configuration of a virtual machine along with its pending changes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct VmPendingConfig {
+    /// The configuration settings.
+    items: Vec<VmPendingItem>,
+}
+
+impl VmPendingConfig {
+    #[inline]
+    #[must_use]
+    pub fn items(&self) -> &[VmPendingItem] {
+        &self.items
+    }
+
+    /// Are there any changes that have not been applied to the running VM yet?
+    #[inline]
+    #[must_use]
+    pub fn has_pending(&self) -> bool {
+        self.items.iter().any(VmPendingItem::is_pending)
+    }
+
+    /// Build a configuration from the selected values of the settings.
+    fn build<F>(&self, select: F) -> Result<VmConfig>
+    where
+        F: Fn(&VmPendingItem) -> Option<&JsonValue>,
+    {
+        let values = self
+            .items
+            .iter()
+            .filter_map(|item| select(item).map(|value| (item.key.clone(), value.clone())))
+            .collect();
+        serde_json::from_value(JsonValue::Object(values))
+            .map_err(|err| Error::Api(anyhow!("Could not parse a VM configuration: {err}")))
+    }
+
+    /// The configuration that the VM is currently running with.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the configuration settings could not be parsed.
+    #[inline]
+    pub fn current(&self) -> Result<VmConfig> {
+        self.build(|item| item.value.as_ref())
+    }
+
+    /// The configuration that the VM will have once the pending changes are applied.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the configuration settings could not be parsed.
+    #[inline]
+    pub fn pending(&self) -> Result<VmConfig> {
+        self.build(|item| {
+            if item.delete {
+                None
+            } else {
+                item.pending.as_ref().or(item.value.as_ref())
+            }
+        })
+    }
+}
+
+/// The version information returned by the Proxmox VE API.
+#[derive(Debug, Deserialize)]
+pub struct VersionInfo {
+    /// The full version of the `pve-manager` package, e.g. "8.2.4".
+    version: String,
+
+    /// The Proxmox VE release, e.g. "8.2".
+    release: String,
+
+    /// The repository revision of the `pve-manager` package.
+    repoid: String,
+}
+
+impl VersionInfo {
+    #[inline]
+    #[must_use]
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn release(&self) -> &str {
+        &self.release
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn repoid(&self) -> &str {
+        &self.repoid
+    }
+}
+
+/// A parsed Proxmox VE version that may be compared to others.
+///
+/// Only the numeric components take part in the comparison; the repository
+/// revision is kept for informational purposes.
+#[derive(Debug, Clone)]
+pub struct PveVersion {
+    /// The major version, e.g. 8.
+    major: u32,
+
+    /// The minor version, e.g. 2.
+    minor: u32,
+
+    /// The patch level, e.g. 4.
+    patch: u32,
+
+    /// The Proxmox VE release as reported by the API, e.g. "8.2".
+    release: String,
+
+    /// The repository revision of the `pve-manager` package.
+    repoid: String,
+}
+
+impl PveVersion {
+    /// The first release that supports tagging guests, as major and minor versions.
+    pub const GUEST_TAGS: (u32, u32) = (7, 3);
+
+    /// Build a version from its numeric components, e.g. to compare against.
+    #[inline]
+    #[must_use]
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            release: format!("{major}.{minor}"),
+            repoid: String::new(),
+        }
+    }
+
+    /// Parse the version information returned by the Proxmox VE API.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Api`] if the version string could not be parsed.
+    #[inline]
+    pub fn from_info(info: &VersionInfo) -> Result<Self> {
+        let (major, minor, patch) = parse::pve_version(info.version())?;
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            release: info.release().to_owned(),
+            repoid: info.repoid().to_owned(),
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn major(&self) -> u32 {
+        self.major
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn patch(&self) -> u32 {
+        self.patch
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn release(&self) -> &str {
+        &self.release
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn repoid(&self) -> &str {
+        &self.repoid
+    }
+
+    /// Is this version at least the specified major and minor one?
+    #[inline]
+    #[must_use]
+    pub const fn at_least(&self, major: u32, minor: u32) -> bool {
+        self.major > major || (self.major == major && self.minor >= minor)
+    }
+
+    /// Does the guest listing include the tags and may guests be selected by them?
+    #[inline]
+    #[must_use]
+    pub const fn has_guest_tags(&self) -> bool {
+        let (major, minor) = Self::GUEST_TAGS;
+        self.at_least(major, minor)
+    }
+}
+
+impl PartialEq for PveVersion {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        (self.major, self.minor, self.patch) == (other.major, other.minor, other.patch)
+    }
+}
+
+impl Eq for PveVersion {}
+
+impl PartialOrd for PveVersion {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PveVersion {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+    }
+}
+
+impl Display for PveVersion {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{major}.{minor}.{patch}",
+            major = self.major,
+            minor = self.minor,
+            patch = self.patch
+        )
+    }
+}